@@ -0,0 +1,54 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use netfun::bt::Bencoding;
+
+// a get_peers response carrying a token and 50 compact peers
+fn get_peers_response() -> Vec<u8> {
+    let mut msg = b"d1:ad2:id20:abcdefghij01234567894:porti6881e5:token8:aoeusnth".to_vec();
+    msg.extend_from_slice(b"6:valuesl");
+    for n in 0..50u8 {
+        msg.extend_from_slice(b"6:");
+        msg.extend_from_slice(&[10, 0, 0, n, 0x1A, 0xE1]);
+    }
+    msg.extend_from_slice(b"ee1:ti1612345678e1:y1:re");
+    return msg;
+}
+
+fn integers() -> Vec<u8> {
+    let mut msg = b"l".to_vec();
+    for n in 0..500u32 {
+        msg.extend_from_slice(format!("i{}e", n * 6881).as_bytes());
+    }
+    msg.push(b'e');
+    return msg;
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let get_peers = get_peers_response();
+    c.bench_function("parse get_peers response", |b| {
+        b.iter(|| Bencoding::from_slice(black_box(&get_peers)).unwrap())
+    });
+    let ints = integers();
+    c.bench_function("parse 500 small integers", |b| {
+        b.iter(|| Bencoding::from_slice(black_box(&ints)).unwrap())
+    });
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let get_peers = Bencoding::from_slice(&get_peers_response()).unwrap();
+    c.bench_function("encode get_peers response", |b| {
+        b.iter(|| black_box(&get_peers).encode())
+    });
+    // the same, into one buffer kept across iterations as a DHT node would
+    let mut buf = Vec::new();
+    c.bench_function("encode get_peers response into a reused buffer", |b| {
+        b.iter(|| {
+            buf.clear();
+            black_box(&get_peers).encode_into(&mut buf);
+            buf.len()
+        })
+    });
+}
+
+criterion_group!(benches, bench_parse, bench_encode);
+criterion_main!(benches);
@@ -0,0 +1,25 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use netfun::bt::NodeId;
+
+fn bench_sort_by_distance(c: &mut Criterion) {
+    let target = NodeId::random();
+    let nodes: Vec<NodeId> = (0..4096).map(|_| NodeId::random()).collect();
+    c.bench_function("sort 4096 nodes by BigUint distance", |b| {
+        b.iter(|| {
+            let mut nodes = nodes.clone();
+            nodes.sort_by_key(|n| target.distance(n));
+            black_box(nodes)
+        })
+    });
+    c.bench_function("sort 4096 nodes by byte distance", |b| {
+        b.iter(|| {
+            let mut nodes = nodes.clone();
+            nodes.sort_by(|a, b| target.cmp_distance(a, b));
+            black_box(nodes)
+        })
+    });
+}
+
+criterion_group!(benches, bench_sort_by_distance);
+criterion_main!(benches);
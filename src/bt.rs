@@ -1,16 +1,76 @@
-use std::{collections::HashMap, convert::TryInto, fmt, ops::Deref, str::FromStr};
-use combinator::complete;
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::{
+    cmp::Ordering, convert::TryFrom, fmt, hash::{Hash, Hasher}, ops::Deref, str::FromStr,
+};
+#[cfg(feature = "std")]
+use std::{collections::HashMap, net::IpAddr};
 use nom::{
-    named, tag,
-    Err::{
-        Incomplete as ParseIncomplete,
-        Error as ParseError,
-        Failure as ParseFailure
-    }, IResult, Needed, branch::alt, bytes::complete::{tag, take, take_while1}, character::{complete::one_of, is_digit}, combinator::{self, opt}, error::{Error, ErrorKind}};
+    Err::Error as ParseError, IResult, Offset, bytes::complete::{tag, take_while1}, character::is_digit,
+    combinator::{opt, recognize}, sequence::pair,
+};
 
-use num_bigint::{BigInt, BigUint, Sign};
+use num_bigint::{BigInt, BigUint};
+use num_traits::ToPrimitive;
 
-#[derive(Clone)]
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+#[cfg(feature = "std")]
+mod compact;
+#[cfg(feature = "std")]
+mod de;
+mod dictionary;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "std")]
+mod krpc;
+#[cfg(feature = "std")]
+mod magnet;
+#[cfg(feature = "std")]
+mod metainfo;
+#[cfg(feature = "std")]
+mod reader;
+#[cfg(feature = "std")]
+mod routing;
+#[cfg(feature = "std")]
+mod ser;
+#[cfg(feature = "std")]
+mod token;
+#[cfg(feature = "std")]
+mod transaction;
+
+#[cfg(feature = "arbitrary")]
+pub use arbitrary::ARBITRARY_MAX_DEPTH;
+#[cfg(feature = "std")]
+pub use compact::{
+    decode_compact_nodes, decode_compact_nodes6, decode_compact_peer, decode_compact_peer6, decode_compact_peers,
+    decode_compact_peers6, encode_compact_nodes, encode_compact_nodes6, encode_compact_peer, encode_compact_peer6,
+    encode_compact_peers, encode_compact_peers6, CompactDecodeError, COMPACT_NODE6_LEN, COMPACT_NODE_LEN,
+    COMPACT_PEER6_LEN, COMPACT_PEER_LEN,
+};
+#[cfg(feature = "std")]
+pub use de::from_bencoding;
+pub use dictionary::Dictionary;
+#[cfg(feature = "std")]
+pub use krpc::{
+    decode_get_peers, random_transaction_id, GetPeersResult, KrpcBody, KrpcError, KrpcMessage, KrpcQuery,
+    KrpcResponse, ERROR_GENERIC, ERROR_METHOD_UNKNOWN, ERROR_PROTOCOL, ERROR_SERVER,
+};
+#[cfg(feature = "std")]
+pub use magnet::{Magnet, MagnetError};
+#[cfg(feature = "std")]
+pub use metainfo::{parse_pieces, FileEntry, FileTreeEntry, MetaInfo, MetaInfoError, MetaInfoFiles, MetaVersion};
+#[cfg(feature = "std")]
+pub use reader::{BencodingReadError, BencodingReader};
+#[cfg(feature = "std")]
+pub use routing::{Replacement, RoutingTable, BUCKET_SIZE, REFRESH_INTERVAL};
+#[cfg(feature = "std")]
+pub use ser::to_bencoding;
+#[cfg(feature = "std")]
+pub use token::{TokenGenerator, TOKEN_ROTATION};
+#[cfg(feature = "std")]
+pub use transaction::{TransactionTable, TRANSACTION_TIMEOUT};
+
+#[derive(PartialEq, Eq, Hash, Clone)]
 pub struct NodeId([u8; 20]);
 
 impl Deref for NodeId {
@@ -21,153 +81,1469 @@ impl Deref for NodeId {
     }
 }
 
+impl fmt::LowerHex for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::UpperHex for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.iter() {
+            write!(f, "{:02X}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the ID as 40 lowercase hex digits, which `from_hex` reads back.
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:x}", self)
+    }
+}
+
+impl fmt::Debug for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeId({:x})", self)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum NodeIdParseError {
+    InvalidLength(usize),
+    InvalidDigit(char),
+}
+
+impl fmt::Display for NodeIdParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NodeIdParseError::InvalidLength(len) => {
+                write!(f, "node ID must be 40 hex digits, got {} characters", len)
+            },
+            NodeIdParseError::InvalidDigit(c) => write!(f, "node ID has a non-hex character {:?}", c),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NodeIdParseError {}
+
+/// A byte slice that wasn't exactly 20 bytes long, so couldn't be a `NodeId`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NodeIdLengthError(pub usize);
+
+impl fmt::Display for NodeIdLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node ID must be 20 bytes, got {}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NodeIdLengthError {}
+
+impl From<[u8; 20]> for NodeId {
+    fn from(id: [u8; 20]) -> NodeId {
+        NodeId(id)
+    }
+}
+
+impl TryFrom<&[u8]> for NodeId {
+    type Error = NodeIdLengthError;
+    fn try_from(bytes: &[u8]) -> Result<NodeId, NodeIdLengthError> {
+        if bytes.len() != 20 {
+            return Err(NodeIdLengthError(bytes.len()));
+        }
+        let mut id = [0u8; 20];
+        id.copy_from_slice(bytes);
+        return Ok(NodeId(id));
+    }
+}
+
+impl FromStr for NodeId {
+    type Err = NodeIdParseError;
+    fn from_str(s: &str) -> Result<NodeId, NodeIdParseError> {
+        NodeId::from_hex(s)
+    }
+}
+
 impl NodeId {
+    /// Parses 40 hex digits, in either case.
+    pub fn from_hex(s: &str) -> Result<NodeId, NodeIdParseError> {
+        let len = s.chars().count();
+        if len != 40 {
+            return Err(NodeIdParseError::InvalidLength(len));
+        }
+        let mut id = [0u8; 20];
+        for (n, c) in s.chars().enumerate() {
+            let digit = c.to_digit(16).ok_or(NodeIdParseError::InvalidDigit(c))?;
+            id[n / 2] |= (digit as u8) << (if n % 2 == 0 { 4 } else { 0 });
+        }
+        return Ok(NodeId(id));
+    }
+
+    #[cfg(feature = "std")]
+    /// A uniformly random ID, drawn from the thread-local CSPRNG.
+    pub fn random() -> NodeId {
+        NodeId(rand::random())
+    }
+
+    #[cfg(feature = "std")]
+    /// A random ID whose first `bits` bits are copied from `prefix`, for
+    /// refreshing the bucket that covers that prefix.
+    pub fn random_in_bucket(prefix: &NodeId, bits: usize) -> NodeId {
+        let bits = bits.min(160);
+        let NodeId(mut id) = NodeId::random();
+        let whole_bytes = bits / 8;
+        id[..whole_bytes].copy_from_slice(&prefix[..whole_bytes]);
+        if !bits.is_multiple_of(8) {
+            let mask = 0xffu8 << (8 - bits % 8);
+            id[whole_bytes] = (prefix[whole_bytes] & mask) | (id[whole_bytes] & !mask);
+        }
+        return NodeId(id);
+    }
+
+    #[cfg(feature = "std")]
+    /// A BEP 42 ID for a node whose external address is `ip`: the first 21
+    /// bits come from a CRC32-C of the masked address and the low 3 bits of
+    /// `rand`, which also becomes the last byte. Everything else is random.
+    pub fn from_ip(ip: IpAddr, rand: u8) -> NodeId {
+        let NodeId(mut id) = NodeId::random();
+        let prefix = bep42_prefix(ip, rand);
+        id[0] = prefix[0];
+        id[1] = prefix[1];
+        id[2] = (prefix[2] & 0xf8) | (id[2] & 0x07);
+        id[19] = rand;
+        return NodeId(id);
+    }
+
+    #[cfg(feature = "std")]
+    /// Whether this ID is one `from_ip` could have made for `ip`. Addresses
+    /// on local networks are exempt, so any ID is valid for them.
+    pub fn is_valid_for_ip(&self, ip: IpAddr) -> bool {
+        if bep42_exempt(ip) {
+            return true;
+        }
+        let prefix = bep42_prefix(ip, self[19]);
+        return self[0] == prefix[0] && self[1] == prefix[1] && self[2] & 0xf8 == prefix[2] & 0xf8;
+    }
+
+    /// Equality that looks at every byte whatever they hold, so how long it
+    /// takes gives away nothing about where two IDs differ. `==` stops at the
+    /// first difference, which is fine for IDs but not for secrets kept in one.
+    pub fn ct_eq(&self, other: &NodeId) -> bool {
+        let diff = self.iter().zip(other.iter()).fold(0u8, |diff, (b1, b2)| diff | (b1 ^ b2));
+        // hidden from the optimizer, which could otherwise turn the fold back
+        // into an early exit
+        return core::hint::black_box(diff) == 0;
+    }
+
+    /// The XOR distance as a number, for callers that want to do arithmetic
+    /// on it. Use `distance_bytes` or `cmp_distance` to just compare distances.
     pub fn distance(&self, node_id: &NodeId) -> BigUint {
         let bn1 = BigUint::from_bytes_be(&self);
         let bn2 = BigUint::from_bytes_be(node_id);
         return bn1 ^ bn2;
     }
 
+    /// The XOR distance as big-endian bytes, which order the same way the
+    /// distances themselves do.
+    pub fn distance_bytes(&self, node_id: &NodeId) -> [u8; 20] {
+        let mut dist = [0u8; 20];
+        for (n, (b1, b2)) in self.iter().zip(node_id.iter()).enumerate() {
+            dist[n] = b1 ^ b2;
+        }
+        return dist;
+    }
+
+    /// Orders `a` and `b` by their distance from `self`, nearest first.
+    pub fn cmp_distance(&self, a: &NodeId, b: &NodeId) -> Ordering {
+        self.distance_bytes(a).cmp(&self.distance_bytes(b))
+    }
+
+    /// The number of leading zero bits in the XOR distance to `node_id`, which
+    /// is the index of the k-bucket it belongs in. Identical IDs give 160.
+    pub fn bucket_index(&self, node_id: &NodeId) -> usize {
+        for (n, (b1, b2)) in self.iter().zip(node_id.iter()).enumerate() {
+            let xor = b1 ^ b2;
+            if xor != 0 {
+                return n * 8 + xor.leading_zeros() as usize;
+            }
+        }
+        return 160;
+    }
+
     pub fn closest(&self, node_ids: &[NodeId]) -> NodeId {
         match node_ids.iter().enumerate()
-            .map(|(n, node_id)| (n, self.distance(node_id)))
+            .map(|(n, node_id)| (n, self.distance_bytes(node_id)))
             .min_by(|(_, dist1), (_, dist2)| dist1.cmp(dist2)) {
                 Some((n, _)) => node_ids[n].clone(),
                 None => self.clone(),
         }
     }
+
+    /// Up to `k` of `node_ids`, nearest first by XOR distance from `self`.
+    pub fn k_closest(&self, node_ids: &[NodeId], k: usize) -> Vec<NodeId> {
+        let mut by_distance: Vec<([u8; 20], &NodeId)> = node_ids.iter()
+            .map(|node_id| (self.distance_bytes(node_id), node_id))
+            .collect();
+        let k = k.min(by_distance.len());
+        if k == 0 {
+            return Vec::new();
+        }
+        // only the k nearest need to end up sorted
+        by_distance.select_nth_unstable_by_key(k - 1, |&(dist, _)| dist);
+        by_distance.truncate(k);
+        by_distance.sort_unstable_by_key(|&(dist, _)| dist);
+        return by_distance.into_iter().map(|(_, node_id)| node_id.clone()).collect();
+    }
+}
+
+/// What was wrong with the input at `BencodingParseError::offset`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BencodingErrorKind {
+    /// The input ended partway through a value. This is the only kind that
+    /// more input could fix; the rest mean the input is malformed.
+    UnexpectedEof,
+    /// An integer had no digits, as in `ie` or `i-e`.
+    EmptyInteger,
+    /// An integer had something other than a digit in it, such as a `+` or
+    /// whitespace, had a leading zero or `-0`, or wasn't closed by an `e`.
+    InvalidInteger,
+    /// A byte string's length was negative, non-canonical, or too large to
+    /// be a `usize`.
+    InvalidLength,
+    /// A byte that can't start a value, or a dictionary key that isn't a byte string.
+    UnexpectedByte,
+    /// A dictionary key was duplicated or out of order, under strict parsing.
+    UnsortedKey,
+    /// Lists and dictionaries were nested deeper than `ParseOptions::max_depth`.
+    TooDeep,
+    /// A dictionary key wasn't UTF-8, so it can't be a key in a `Bencoding`.
+    /// A `BencodingRef` keeps these as they are.
+    InvalidKey,
+    /// A complete value was followed by this many more bytes, starting at
+    /// the error's offset. Unlike the other kinds, the value itself was fine,
+    /// so for framed input this means the frame was cut too long.
+    TrailingData(usize),
+}
+
+impl fmt::Display for BencodingErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            BencodingErrorKind::TrailingData(len) => return write!(f, "{} bytes of trailing data", len),
+            BencodingErrorKind::UnexpectedEof => "unexpected end of input",
+            BencodingErrorKind::EmptyInteger => "integer with no digits",
+            BencodingErrorKind::InvalidInteger => "invalid integer",
+            BencodingErrorKind::InvalidLength => "invalid byte string length",
+            BencodingErrorKind::UnexpectedByte => "unexpected byte",
+            BencodingErrorKind::UnsortedKey => "duplicate or out of order dictionary key",
+            BencodingErrorKind::TooDeep => "too deeply nested",
+            BencodingErrorKind::InvalidKey => "dictionary key isn't UTF-8",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+// CRC32-C (Castagnoli), bit by bit, since it only ever covers a few bytes
+#[cfg(feature = "std")]
+fn crc32c(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82F6_3B78 } else { crc >> 1 };
+        }
+    }
+    return !crc;
+}
+
+// the big-endian CRC of `ip`'s masked high bytes, of which BEP 42 IDs use 21 bits
+#[cfg(feature = "std")]
+fn bep42_prefix(ip: IpAddr, rand: u8) -> [u8; 4] {
+    let (mut bytes, len) = match ip {
+        IpAddr::V4(ip) => {
+            let mut bytes = [0u8; 8];
+            bytes[..4].copy_from_slice(&ip.octets());
+            (bytes, 4)
+        },
+        IpAddr::V6(ip) => {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&ip.octets()[..8]);
+            (bytes, 8)
+        },
+    };
+    let mask: &[u8] = if len == 4 { &[0x03, 0x0f, 0x3f, 0xff] } else { &[0x01, 0x03, 0x07, 0x0f, 0x1f, 0x3f, 0x7f, 0xff] };
+    for (byte, mask) in bytes.iter_mut().zip(mask) {
+        *byte &= mask;
+    }
+    bytes[0] |= (rand & 0x07) << 5;
+    return crc32c(&bytes[..len]).to_be_bytes();
+}
+
+// local addresses, which BEP 42 doesn't hold to any particular ID
+#[cfg(feature = "std")]
+fn bep42_exempt(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+        IpAddr::V6(ip) => ip.is_loopback(),
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub struct BencodingParseError;
+pub struct BencodingParseError {
+    /// How far into the input the problem is, in bytes.
+    pub offset: usize,
+    pub kind: BencodingErrorKind,
+}
 impl fmt::Display for BencodingParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "failed to parse bencoding")
+        write!(f, "failed to parse bencoding: {} at byte {}", self.kind, self.offset)
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for BencodingParseError {}
+
+impl BencodingParseError {
+    /// Whether the input was a valid start of a value that just needs more
+    /// bytes, as opposed to malformed, so a reader off a stream should wait.
+    pub fn is_incomplete(&self) -> bool {
+        self.kind == BencodingErrorKind::UnexpectedEof
     }
 }
 
+// a `BencodingParseError` that hasn't been turned into an offset yet, since
+// only the top level knows where the input started
 #[derive(Debug, PartialEq, Eq, Clone)]
+struct RawParseError<'a> {
+    input: &'a [u8],
+    kind: BencodingErrorKind,
+}
+
+type ParseResult<'a, T> = IResult<&'a [u8], T, RawParseError<'a>>;
+
+fn fail<T>(input: &[u8], kind: BencodingErrorKind) -> ParseResult<'_, T> {
+    Err(ParseError(RawParseError{input, kind}))
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BencodingSerdeError(String);
+#[cfg(feature = "std")]
+impl BencodingSerdeError {
+    fn new<T: fmt::Display>(msg: T) -> BencodingSerdeError {
+        BencodingSerdeError(msg.to_string())
+    }
+}
+#[cfg(feature = "std")]
+impl fmt::Display for BencodingSerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to convert bencoding: {}", self.0)
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for BencodingSerdeError {}
+
+/// Controls how strictly `Bencoding::from_slice_with` treats its input.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseOptions {
+    /// Reject dictionaries whose keys are duplicated or out of order.
+    pub strict: bool,
+    /// Maximum number of nested lists and dictionaries, to bound recursion on untrusted input.
+    pub max_depth: usize,
+    /// Maximum declared length of a byte string. A length past the end of a
+    /// slice is rejected regardless, but `BencodingReader` would otherwise
+    /// keep reading to fill it, so set this when reading from a peer.
+    pub max_string_len: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions{strict: false, max_depth: 100, max_string_len: usize::MAX}
+    }
+}
+
+/// A parsed bencoding value.
+///
+/// Integers that fit in an `i64` are parsed as `Int` and anything larger as
+/// `Integer`. The two compare equal whenever they hold the same number.
+#[derive(Debug, Clone)]
 pub enum Bencoding {
     String(String),
+    /// A byte string that isn't valid UTF-8, such as the `pieces` field of a torrent.
+    Bytes(Vec<u8>),
+    Int(i64),
     Integer(BigInt),
     List(Vec<Bencoding>),
-    Dictionary(HashMap<String, Bencoding>),
+    Dictionary(Dictionary),
+}
+
+impl PartialEq for Bencoding {
+    fn eq(&self, other: &Bencoding) -> bool {
+        match (self, other) {
+            (Bencoding::String(a), Bencoding::String(b)) => a == b,
+            (Bencoding::Bytes(a), Bencoding::Bytes(b)) => a == b,
+            (Bencoding::Int(a), Bencoding::Int(b)) => a == b,
+            (Bencoding::Integer(a), Bencoding::Integer(b)) => a == b,
+            (Bencoding::Int(a), Bencoding::Integer(b)) => BigInt::from(*a) == *b,
+            (Bencoding::Integer(a), Bencoding::Int(b)) => *a == BigInt::from(*b),
+            (Bencoding::List(a), Bencoding::List(b)) => a == b,
+            (Bencoding::Dictionary(a), Bencoding::Dictionary(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Bencoding {}
+
+/// Values of different kinds order integers first, then byte strings, then
+/// lists, then dictionaries.
+///
+/// Integers order by value, whether `Int` or `Integer`. Byte strings order by
+/// their bytes, with a `String` before a `Bytes` of the same bytes, since the
+/// two aren't equal. Lists order element by element, and dictionaries as the
+/// lists of their `(key, value)` pairs sorted by key, which is the order
+/// they're encoded in.
+impl Ord for Bencoding {
+    fn cmp(&self, other: &Bencoding) -> Ordering {
+        match (self, other) {
+            (Bencoding::Int(a), Bencoding::Int(b)) => a.cmp(b),
+            (Bencoding::Integer(a), Bencoding::Integer(b)) => a.cmp(b),
+            (Bencoding::Int(a), Bencoding::Integer(b)) => BigInt::from(*a).cmp(b),
+            (Bencoding::Integer(a), Bencoding::Int(b)) => a.cmp(&BigInt::from(*b)),
+            (Bencoding::String(_), Bencoding::String(_))
+            | (Bencoding::String(_), Bencoding::Bytes(_))
+            | (Bencoding::Bytes(_), Bencoding::String(_))
+            | (Bencoding::Bytes(_), Bencoding::Bytes(_)) => {
+                let is_bytes = |value: &Bencoding| matches!(value, Bencoding::Bytes(_));
+                self.as_bytes().cmp(&other.as_bytes()).then(is_bytes(self).cmp(&is_bytes(other)))
+            },
+            (Bencoding::List(a), Bencoding::List(b)) => a.cmp(b),
+            (Bencoding::Dictionary(a), Bencoding::Dictionary(b)) => sorted_entries(a).cmp(&sorted_entries(b)),
+            _ => kind_rank(self).cmp(&kind_rank(other)),
+        }
+    }
+}
+
+impl PartialOrd for Bencoding {
+    fn partial_cmp(&self, other: &Bencoding) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Consistent with `Eq`, so an `Int` and an `Integer` holding the same number
+/// hash the same, and dictionaries hash their entries in key order.
+impl Hash for Bencoding {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Bencoding::Int(n) => {
+                state.write_u8(0);
+                n.hash(state);
+            },
+            Bencoding::Integer(n) => {
+                state.write_u8(0);
+                match n.to_i64() {
+                    Some(n) => n.hash(state),
+                    None => n.hash(state),
+                }
+            },
+            Bencoding::String(s) => {
+                state.write_u8(1);
+                s.as_bytes().hash(state);
+            },
+            Bencoding::Bytes(bytes) => {
+                state.write_u8(2);
+                bytes.hash(state);
+            },
+            Bencoding::List(elems) => {
+                state.write_u8(3);
+                elems.hash(state);
+            },
+            Bencoding::Dictionary(dict) => {
+                state.write_u8(4);
+                sorted_entries(dict).hash(state);
+            },
+        }
+    }
+}
+
+fn kind_rank(value: &Bencoding) -> u8 {
+    match value {
+        Bencoding::Int(_) | Bencoding::Integer(_) => 0,
+        Bencoding::String(_) | Bencoding::Bytes(_) => 1,
+        Bencoding::List(_) => 2,
+        Bencoding::Dictionary(_) => 3,
+    }
+}
+
+fn sorted_entries(dict: &Dictionary) -> Vec<(&String, &Bencoding)> {
+    let mut entries: Vec<(&String, &Bencoding)> = dict.iter().collect();
+    entries.sort_unstable_by_key(|&(key, _)| key);
+    return entries;
+}
+
+/// Builds values without naming the variants, e.g. for a KRPC query:
+///
+/// ```
+/// use netfun::bt::{Bencoding, Dictionary};
+///
+/// let mut args = Dictionary::new();
+/// args.insert("id".to_string(), Bencoding::from(vec![0xAB; 20]));
+/// let mut query = Dictionary::new();
+/// query.insert("a".to_string(), Bencoding::from(args));
+/// query.insert("q".to_string(), Bencoding::from("ping"));
+/// query.insert("t".to_string(), Bencoding::from("aa"));
+/// query.insert("y".to_string(), Bencoding::from("q"));
+///
+/// let encoded = Bencoding::from(query).encode();
+/// assert!(encoded.starts_with(b"d1:ad2:id20:\xAB"));
+/// assert_eq!(Bencoding::from(vec![Bencoding::from(42), Bencoding::from("cat")]).encode(), b"li42e3:cate");
+/// ```
+impl From<i64> for Bencoding {
+    fn from(n: i64) -> Bencoding {
+        Bencoding::Int(n)
+    }
+}
+
+impl From<&str> for Bencoding {
+    fn from(s: &str) -> Bencoding {
+        Bencoding::String(s.to_string())
+    }
+}
+
+impl From<String> for Bencoding {
+    fn from(s: String) -> Bencoding {
+        Bencoding::String(s)
+    }
+}
+
+/// Byte strings that happen to be valid UTF-8 become `String`s, as when parsing.
+impl From<Vec<u8>> for Bencoding {
+    fn from(bytes: Vec<u8>) -> Bencoding {
+        Bencoding::from_raw_string(bytes)
+    }
+}
+
+impl From<Vec<Bencoding>> for Bencoding {
+    fn from(elems: Vec<Bencoding>) -> Bencoding {
+        Bencoding::List(elems)
+    }
+}
+
+impl From<Dictionary> for Bencoding {
+    fn from(dict: Dictionary) -> Bencoding {
+        Bencoding::Dictionary(dict)
+    }
+}
+
+/// The keys are sorted, since a `HashMap` has no order of its own.
+#[cfg(feature = "std")]
+impl From<HashMap<String, Bencoding>> for Bencoding {
+    fn from(dict: HashMap<String, Bencoding>) -> Bencoding {
+        Bencoding::Dictionary(dict.into())
+    }
+}
+
+/// Why a `Bencoding` couldn't be converted to a plain Rust value.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BencodingConversionError {
+    /// The value was the wrong variant, such as a list where an integer was
+    /// wanted, or `Bytes`, which aren't UTF-8, where a `String` was.
+    WrongVariant,
+    /// An integer didn't fit in an `i64`.
+    OutOfRange,
+}
+
+impl fmt::Display for BencodingConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BencodingConversionError::WrongVariant => write!(f, "bencoding is the wrong type"),
+            BencodingConversionError::OutOfRange => write!(f, "bencoded integer doesn't fit in an i64"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BencodingConversionError {}
+
+impl TryFrom<Bencoding> for i64 {
+    type Error = BencodingConversionError;
+    fn try_from(bencoding: Bencoding) -> Result<i64, BencodingConversionError> {
+        match bencoding {
+            Bencoding::Int(n) => Ok(n),
+            Bencoding::Integer(n) => n.to_i64().ok_or(BencodingConversionError::OutOfRange),
+            _ => Err(BencodingConversionError::WrongVariant),
+        }
+    }
+}
+
+impl TryFrom<Bencoding> for String {
+    type Error = BencodingConversionError;
+    fn try_from(bencoding: Bencoding) -> Result<String, BencodingConversionError> {
+        match bencoding {
+            Bencoding::String(s) => Ok(s),
+            _ => Err(BencodingConversionError::WrongVariant),
+        }
+    }
+}
+
+/// Either kind of byte string, like `Bencoding::as_bytes`.
+impl TryFrom<Bencoding> for Vec<u8> {
+    type Error = BencodingConversionError;
+    fn try_from(bencoding: Bencoding) -> Result<Vec<u8>, BencodingConversionError> {
+        match bencoding {
+            Bencoding::String(s) => Ok(s.into_bytes()),
+            Bencoding::Bytes(b) => Ok(b),
+            _ => Err(BencodingConversionError::WrongVariant),
+        }
+    }
+}
+
+/// Writes a `Bencoding` out literally, with `{ key => value, ... }` for
+/// dictionaries and `[ ... ]` for lists. Anything else is converted with
+/// `Bencoding::from`.
+///
+/// ```
+/// use netfun::{bencode, bt::Bencoding};
+///
+/// let id = vec![0xAB; 20];
+/// let query = bencode!({ "t" => "aa", "y" => "q", "q" => "ping", "a" => { "id" => id } });
+/// assert_eq!(Some("ping"), query.get("q").and_then(|q| q.as_str()));
+/// assert_eq!(Bencoding::List(vec![Bencoding::Int(-1), Bencoding::List(vec![])]), bencode!([-1, []]));
+/// ```
+#[macro_export]
+macro_rules! bencode {
+    ({ $($entries:tt)* }) => {{
+        #[allow(unused_mut)]
+        let mut dict = $crate::bt::Dictionary::new();
+        $crate::bencode!(@dict dict $($entries)*);
+        dict.sort_keys();
+        $crate::bt::Bencoding::Dictionary(dict)
+    }};
+    ([ $($elems:tt)* ]) => {
+        $crate::bt::Bencoding::List($crate::bencode!(@list [] $($elems)*))
+    };
+    ($value:expr) => {
+        $crate::bt::Bencoding::from($value)
+    };
+
+    // dictionary entries are inserted one at a time, nested containers first
+    // so that they aren't mistaken for block expressions
+    (@dict $dict:ident) => {};
+    (@dict $dict:ident $key:expr => { $($value:tt)* } $(, $($rest:tt)*)?) => {
+        $dict.insert($crate::alloc::string::String::from($key), $crate::bencode!({ $($value)* }));
+        $crate::bencode!(@dict $dict $($($rest)*)?);
+    };
+    (@dict $dict:ident $key:expr => [ $($value:tt)* ] $(, $($rest:tt)*)?) => {
+        $dict.insert($crate::alloc::string::String::from($key), $crate::bencode!([ $($value)* ]));
+        $crate::bencode!(@dict $dict $($($rest)*)?);
+    };
+    (@dict $dict:ident $key:expr => $value:expr $(, $($rest:tt)*)?) => {
+        $dict.insert($crate::alloc::string::String::from($key), $crate::bencode!($value));
+        $crate::bencode!(@dict $dict $($($rest)*)?);
+    };
+
+    // list elements are gathered up in the brackets until there are none left
+    (@list [$($done:expr,)*]) => {
+        $crate::alloc::vec![$($done,)*]
+    };
+    (@list [$($done:expr,)*] { $($elem:tt)* } $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@list [$($done,)* $crate::bencode!({ $($elem)* }),] $($($rest)*)?)
+    };
+    (@list [$($done:expr,)*] [ $($elem:tt)* ] $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@list [$($done,)* $crate::bencode!([ $($elem)* ]),] $($($rest)*)?)
+    };
+    (@list [$($done:expr,)*] $elem:expr $(, $($rest:tt)*)?) => {
+        $crate::bencode!(@list [$($done,)* $crate::bencode!($elem),] $($($rest)*)?)
+    };
 }
 
 impl Bencoding {
     pub fn from_slice(input: &[u8]) -> Result<Bencoding, BencodingParseError> {
-        match Bencoding::parse(input) {
-            Ok((leftovers, bencoding)) => match leftovers.is_empty() {
-                true => Ok(bencoding),
-                false => Err(BencodingParseError{}),
+        Bencoding::from_slice_with(input, &ParseOptions::default())
+    }
+
+    /// Like `from_slice`, but also rejects dictionaries whose keys are
+    /// duplicated or not in ascending raw-byte order, as required by BEP 3.
+    pub fn from_slice_strict(input: &[u8]) -> Result<Bencoding, BencodingParseError> {
+        Bencoding::from_slice_with(input, &ParseOptions{strict: true, ..ParseOptions::default()})
+    }
+
+    pub fn from_slice_with(input: &[u8], opts: &ParseOptions) -> Result<Bencoding, BencodingParseError> {
+        BencodingRef::from_slice_with(input, opts)?.to_owned_in(input)
+    }
+
+    /// Parses every value in `input`, which may be several written back to
+    /// back, as in a capture of DHT traffic. Empty input has no values.
+    pub fn parse_many(input: &[u8]) -> Result<Vec<Bencoding>, BencodingParseError> {
+        let opts = ParseOptions::default();
+        let mut values = Vec::new();
+        let mut offset = 0;
+        while offset < input.len() {
+            match BencodingRef::parse_prefix(&input[offset..], &opts) {
+                Ok((bencoding, len)) => {
+                    let bencoding = bencoding.to_owned_in(&input[offset..])
+                        .map_err(|e| BencodingParseError{offset: offset + e.offset, ..e})?;
+                    values.push(bencoding);
+                    offset += len;
+                },
+                Err(e) => return Err(BencodingParseError{offset: offset + e.offset, ..e}),
+            }
+        }
+        return Ok(values);
+    }
+
+    /// The number in an `Integer`, which only holds those too big for an
+    /// `i64`; `None` for any other variant, `Int` included.
+    pub fn as_integer(&self) -> Option<&BigInt> {
+        match self {
+            Bencoding::Integer(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Any integer, however big, widened to a `BigInt`; `None` for any other
+    /// variant.
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Bencoding::Int(n) => Some(BigInt::from(*n)),
+            Bencoding::Integer(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
+    /// The integer if it fits in an `i64`; `None` if it doesn't, or for any
+    /// other variant.
+    pub fn as_i64_checked(&self) -> Option<i64> {
+        match self {
+            Bencoding::Int(n) => Some(*n),
+            Bencoding::Integer(n) => n.to_i64(),
+            _ => None,
+        }
+    }
+
+    /// The integer if it fits in a `u64`, which includes the sizes of files
+    /// over 8 EiB; `None` if it's negative or too big, or for any other variant.
+    pub fn as_u64_checked(&self) -> Option<u64> {
+        match self {
+            Bencoding::Int(n) => n.to_u64(),
+            Bencoding::Integer(n) => n.to_u64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Bencoding::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the raw bytes of either kind of byte string.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Bencoding::String(s) => Some(s.as_bytes()),
+            Bencoding::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Bencoding]> {
+        match self {
+            Bencoding::List(elems) => Some(elems),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&Dictionary> {
+        match self {
+            Bencoding::Dictionary(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    /// The elements if this is a list; nothing for any other variant. Walking
+    /// the `files` of a multi-file torrent's `info`:
+    ///
+    /// ```
+    /// use netfun::bencode;
+    ///
+    /// let info = bencode!({ "name" => "album", "files" => [
+    ///     { "length" => 11000, "path" => ["notes.txt"] },
+    ///     { "length" => 10240, "path" => ["data", "bytes.bin"] },
+    /// ] });
+    /// let files = info.get("files").unwrap();
+    /// let paths: Vec<String> = files.iter_list()
+    ///     .map(|file| {
+    ///         let path = file.get("path").unwrap();
+    ///         path.iter_list().filter_map(|part| part.as_str()).collect::<Vec<&str>>().join("/")
+    ///     })
+    ///     .collect();
+    /// assert_eq!(vec!["notes.txt", "data/bytes.bin"], paths);
+    /// assert_eq!(0, info.iter_list().count());
+    /// ```
+    pub fn iter_list(&self) -> impl Iterator<Item = &Bencoding> {
+        self.as_list().unwrap_or(&[]).iter()
+    }
+
+    /// The entries in stored order if this is a dictionary; nothing for any
+    /// other variant.
+    pub fn iter_dict(&self) -> impl Iterator<Item = (&str, &Bencoding)> {
+        self.as_dict().into_iter().flat_map(|dict| dict.iter()).map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// The number of elements in a list or entries in a dictionary; `None`
+    /// for any other variant, byte strings included. Checking the arity of a
+    /// KRPC error, which is a code and a message:
+    ///
+    /// ```
+    /// use netfun::bencode;
+    ///
+    /// let error = bencode!({ "t" => "aa", "y" => "e", "e" => [201, "A Generic Error Ocurred"] });
+    /// assert_eq!(Some(3), error.len());
+    /// assert_eq!(Some(2), error.get("e").unwrap().len());
+    /// assert_eq!(None, error.get("y").unwrap().len());
+    /// ```
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Bencoding::List(elems) => Some(elems.len()),
+            Bencoding::Dictionary(dict) => Some(dict.len()),
+            _ => None,
+        }
+    }
+
+    /// Whether a list or dictionary has nothing in it; `None` for any other variant.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Looks up `key` if this is a dictionary; returns `None` for any other variant.
+    pub fn get(&self, key: &str) -> Option<&Bencoding> {
+        self.as_dict().and_then(|dict| dict.get(key))
+    }
+
+    // integers are kept as `Int` whenever they fit
+    #[cfg(feature = "std")]
+    fn from_bigint(n: BigInt) -> Bencoding {
+        match n.to_i64() {
+            Some(v) => Bencoding::Int(v),
+            None => Bencoding::Integer(n),
+        }
+    }
+
+    // byte strings are kept as `String` whenever they happen to be valid UTF-8
+    fn from_raw_string(bytes: Vec<u8>) -> Bencoding {
+        match String::from_utf8(bytes) {
+            Ok(v) => Bencoding::String(v),
+            Err(e) => Bencoding::Bytes(e.into_bytes()),
+        }
+    }
+
+    /// Sorts the keys of this and every nested dictionary, so that `encode`
+    /// gives the canonical form. Parsed values keep the input's key order,
+    /// which non-conformant encoders don't always sort.
+    pub fn canonicalize(&mut self) {
+        match self {
+            Bencoding::List(elems) => {
+                for elem in elems.iter_mut() {
+                    elem.canonicalize();
+                }
+            },
+            Bencoding::Dictionary(dict) => {
+                dict.sort_keys();
+                for (_, value) in dict.iter_mut() {
+                    value.canonicalize();
+                }
+            },
+            _ => (),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_into(&mut buf);
+        return buf;
+    }
+
+    /// Appends the encoding to the end of `buf`, so that one buffer can be
+    /// cleared and reused for message after message rather than allocating
+    /// a new one each time.
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        match self {
+            Bencoding::Int(n) => {
+                buf.push(b'i');
+                buf.extend_from_slice(n.to_string().as_bytes());
+                buf.push(b'e');
+            },
+            Bencoding::Integer(n) => {
+                buf.push(b'i');
+                buf.extend_from_slice(n.to_string().as_bytes());
+                buf.push(b'e');
+            },
+            Bencoding::String(s) => Bencoding::write_bytes(s.as_bytes(), buf),
+            Bencoding::Bytes(b) => Bencoding::write_bytes(b, buf),
+            Bencoding::List(elems) => {
+                buf.push(b'l');
+                for elem in elems.iter() {
+                    elem.encode_into(buf);
+                }
+                buf.push(b'e');
+            },
+            // in the order they're kept in, so that what was parsed encodes
+            // back the same even if it wasn't canonical
+            Bencoding::Dictionary(dict) => {
+                buf.push(b'd');
+                for (key, value) in dict.iter() {
+                    Bencoding::write_bytes(key.as_bytes(), buf);
+                    value.encode_into(buf);
+                }
+                buf.push(b'e');
+            },
+        }
+    }
+
+    fn write_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+        buf.extend_from_slice(bytes.len().to_string().as_bytes());
+        buf.push(b':');
+        buf.extend_from_slice(bytes);
+    }
+
+    #[cfg(test)]
+    fn parse(input: &[u8]) -> ParseResult<'_, Bencoding> {
+        let (input, bencoding) = BencodingRef::parse_value(input, &ParseOptions::default(), 1)?;
+        return Ok((input, bencoding.to_owned().expect("test keys are UTF-8")));
+    }
+}
+
+/// Renders a JSON-like view for debugging; use `encode` for the wire format.
+///
+/// Byte strings are quoted with anything other than printable ASCII escaped
+/// as `\xNN`, and dictionary entries are sorted by key.
+impl fmt::Display for Bencoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bencoding::Int(n) => write!(f, "{}", n),
+            Bencoding::Integer(n) => write!(f, "{}", n),
+            Bencoding::String(s) => write_quoted(f, s.as_bytes()),
+            Bencoding::Bytes(b) => write_quoted(f, b),
+            Bencoding::List(elems) => {
+                write!(f, "[")?;
+                for (n, elem) in elems.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, "]")
+            },
+            Bencoding::Dictionary(dict) => {
+                write!(f, "{{")?;
+                for (n, (key, value)) in sorted_entries(dict).into_iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_quoted(f, key.as_bytes())?;
+                    write!(f, ": {}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_quoted(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for &b in bytes {
+        match b {
+            b'"' => write!(f, "\\\"")?,
+            b'\\' => write!(f, "\\\\")?,
+            0x20..=0x7E => write!(f, "{}", b as char)?,
+            _ => write!(f, "\\x{:02x}", b)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// A parsed bencoding that borrows its byte strings and dictionary keys from
+/// the input buffer instead of copying them out.
+///
+/// Dictionary entries are kept in the order they appeared in the input, and
+/// their keys as the exact bytes they were, even when they aren't UTF-8, which
+/// a `Bencoding` can't do.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BencodingRef<'a> {
+    Bytes(&'a [u8]),
+    Int(i64),
+    /// Only used for integers that don't fit in an `i64`.
+    Integer(BigInt),
+    List(Vec<BencodingRef<'a>>),
+    Dictionary(Vec<(&'a [u8], BencodingRef<'a>)>),
+}
+
+impl<'a> BencodingRef<'a> {
+    pub fn from_slice(input: &'a [u8]) -> Result<BencodingRef<'a>, BencodingParseError> {
+        BencodingRef::from_slice_with(input, &ParseOptions::default())
+    }
+
+    pub fn from_slice_with(
+        input: &'a [u8], opts: &ParseOptions,
+    ) -> Result<BencodingRef<'a>, BencodingParseError> {
+        let (bencoding, len) = BencodingRef::parse_prefix(input, opts)?;
+        if len < input.len() {
+            return Err(BencodingParseError{offset: len, kind: BencodingErrorKind::TrailingData(input.len() - len)});
+        }
+        return Ok(bencoding);
+    }
+
+    // parses the value at the start of `input`, returning it along with the
+    // number of bytes it took up
+    fn parse_prefix(input: &'a [u8], opts: &ParseOptions) -> Result<(BencodingRef<'a>, usize), BencodingParseError> {
+        let offset = |rest: &[u8]| input.len() - rest.len();
+        match BencodingRef::parse_value(input, opts, 1) {
+            Ok((leftovers, bencoding)) => Ok((bencoding, offset(leftovers))),
+            Err(ParseError(e)) | Err(nom::Err::Failure(e)) => {
+                Err(BencodingParseError{offset: offset(e.input), kind: e.kind})
+            },
+            // every parser below works on complete input
+            Err(nom::Err::Incomplete(_)) => unreachable!(),
+        }
+    }
+
+    // where the value for `key` sits in the dictionary that `input` starts
+    // with, for when its exact bytes matter; like parsing, the last one wins if
+    // the key is repeated
+    #[cfg(feature = "std")]
+    pub(crate) fn dict_value_span(input: &'a [u8], key: &[u8]) -> Option<core::ops::Range<usize>> {
+        let opts = ParseOptions::default();
+        let offset = |rest: &[u8]| input.len() - rest.len();
+        let (mut c_input, _) = BencodingRef::expect(input, b'd', BencodingErrorKind::UnexpectedByte).ok()?;
+        let mut span = None;
+        loop {
+            let (leftovers, end) = BencodingRef::at_end(c_input).ok()?;
+            if end {
+                return span;
+            }
+            let (leftovers, k) = BencodingRef::parse_string(leftovers, &opts).ok()?;
+            let start = offset(leftovers);
+            let (leftovers, _) = BencodingRef::parse_value(leftovers, &opts, 2).ok()?;
+            if k == key {
+                span = Some(start..offset(leftovers));
+            }
+            c_input = leftovers;
+        }
+    }
+
+    /// Looks up `key`, compared byte for byte, if this is a dictionary;
+    /// returns `None` for any other variant. Like `Bencoding`, the last entry
+    /// wins if the key is repeated.
+    pub fn get(&self, key: &[u8]) -> Option<&BencodingRef<'a>> {
+        match self {
+            BencodingRef::Dictionary(dict) => dict.iter().rev().find(|(k, _)| *k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The entries with their raw keys in input order if this is a
+    /// dictionary; nothing for any other variant.
+    pub fn iter_dict(&self) -> impl Iterator<Item = (&'a [u8], &BencodingRef<'a>)> {
+        let entries = match self {
+            BencodingRef::Dictionary(dict) => &dict[..],
+            _ => &[],
+        };
+        return entries.iter().map(|(k, v)| (*k, v));
+    }
+
+    /// Copies everything out of the input buffer into an owned `Bencoding`.
+    ///
+    /// A `Bencoding` keys its dictionaries by `String`, so this fails with the
+    /// first dictionary key that isn't UTF-8, rather than let two keys that
+    /// differ only in invalid bytes run together.
+    pub fn to_owned(&self) -> Result<Bencoding, &'a [u8]> {
+        let owned = match self {
+            BencodingRef::Bytes(b) => Bencoding::from_raw_string(b.to_vec()),
+            BencodingRef::Int(n) => Bencoding::Int(*n),
+            BencodingRef::Integer(n) => Bencoding::Integer(n.clone()),
+            BencodingRef::List(elems) => {
+                Bencoding::List(elems.iter().map(|e| e.to_owned()).collect::<Result<_, _>>()?)
             },
-            Err(_) => Err(BencodingParseError{}),
+            BencodingRef::Dictionary(dict) => Bencoding::Dictionary(dict.iter()
+                .map(|(k, v)| match core::str::from_utf8(k) {
+                    Ok(key) => Ok((key.to_string(), v.to_owned()?)),
+                    Err(_) => Err(*k),
+                })
+                .collect::<Result<_, _>>()?),
+        };
+        return Ok(owned);
+    }
+
+    // `to_owned` for a value parsed from the start of `input`, with a key that
+    // isn't UTF-8 reported as a parse error where the key starts
+    pub(crate) fn to_owned_in(&self, input: &[u8]) -> Result<Bencoding, BencodingParseError> {
+        let invalid_key = |key| BencodingParseError{offset: input.offset(key), kind: BencodingErrorKind::InvalidKey};
+        return self.to_owned().map_err(invalid_key);
+    }
+
+    // consumes `byte`, which has to come next, or else it's an error of `kind`
+    fn expect(input: &'a [u8], byte: u8, kind: BencodingErrorKind) -> ParseResult<'a, ()> {
+        match input.first() {
+            Some(&b) if b == byte => Ok((&input[1..], ())),
+            Some(_) => fail(input, kind),
+            None => fail(input, BencodingErrorKind::UnexpectedEof),
         }
     }
 
-    fn parse_bigint(input: &[u8]) -> IResult<&[u8], BigInt> {
-        // TODO: reject leading zeroes and -0
-        let (input, opt_sign) = opt(tag("-"))(input)?;
-        let (input, digits) = take_while1(is_digit)(input)?;
-        let sign = opt_sign.unwrap_or_default();
-        let n_slice = [&sign[..], &digits[..]].concat();
-        return match BigInt::from_str(&String::from_utf8_lossy(&n_slice)) {
-            Ok(v) => Ok((input, v)),
-            Err(_) => return Err(ParseError(Error{input, code: ErrorKind::IsNot})),
+    // returns the optionally negative decimal, which is always ASCII, or an
+    // error of `kind` if it isn't there or isn't canonical
+    fn parse_number(input: &'a [u8], kind: BencodingErrorKind) -> ParseResult<'a, &'a str> {
+        let (rest, number) = match recognize(pair(opt(tag("-")), take_while1(is_digit)))(input) {
+            Ok(parsed) => parsed,
+            Err(ParseError(nom::error::Error{input: rest, ..})) if rest.is_empty() => {
+                return fail(rest, BencodingErrorKind::UnexpectedEof);
+            },
+            Err(_) => return fail(input, kind),
         };
+        let digits = if number[0] == b'-' { &number[1..] } else { number };
+        // the only digit sequence allowed to start with 0 is "0" itself, and it may not be negated
+        if digits[0] == b'0' && (digits.len() > 1 || digits.len() < number.len()) {
+            return fail(input, kind);
+        }
+        return Ok((rest, core::str::from_utf8(number).unwrap()));
     }
 
-    fn parse_integer(input: &[u8]) -> IResult<&[u8], Bencoding> {
-        let (input, _) = tag("i")(input)?;
-        let (input, n) = Bencoding::parse_bigint(input)?;
-        let (input, _) = Bencoding::parse_end(input)?;
-        return Ok((input, Bencoding::Integer(n)));
+    fn parse_integer(input: &'a [u8]) -> ParseResult<'a, BencodingRef<'a>> {
+        let (input, _) = BencodingRef::expect(input, b'i', BencodingErrorKind::UnexpectedByte)?;
+        if input.starts_with(b"e") || input.starts_with(b"-e") {
+            return fail(input, BencodingErrorKind::EmptyInteger);
+        }
+        let (rest, number) = BencodingRef::parse_number(input, BencodingErrorKind::InvalidInteger)?;
+        let (rest, _) = BencodingRef::expect(rest, b'e', BencodingErrorKind::InvalidInteger)?;
+        // most integers are small, so only fall back to a BigInt when i64 overflows
+        if let Ok(n) = i64::from_str(number) {
+            return Ok((rest, BencodingRef::Int(n)));
+        }
+        return match BigInt::from_str(number) {
+            Ok(n) => Ok((rest, BencodingRef::Integer(n))),
+            Err(_) => fail(input, BencodingErrorKind::InvalidInteger),
+        };
     }
 
-    fn parse_string(input: &[u8]) -> IResult<&[u8], Bencoding> {
-        let (input, n) = Bencoding::parse_bigint(input)?;
-        if n.sign() == Sign::Minus {
-            return Err(ParseError(Error{input, code: ErrorKind::IsNot}));
+    // the length is checked against what's left before anything is taken, so
+    // a huge one can't cause a huge allocation
+    fn parse_string(input: &'a [u8], opts: &ParseOptions) -> ParseResult<'a, &'a [u8]> {
+        // checked up front so that a lone `-` isn't mistaken for the start of a number
+        if input.first() == Some(&b'-') {
+            return fail(input, BencodingErrorKind::InvalidLength);
         }
-        let n_u32: u32 = match n.try_into() {
+        let (rest, number) = BencodingRef::parse_number(input, BencodingErrorKind::InvalidLength)?;
+        let len = match usize::from_str(number) {
             Ok(v) => v,
-            Err(_) => return Err(ParseError(Error{input, code: ErrorKind::IsNot})),
+            Err(_) => return fail(input, BencodingErrorKind::InvalidLength),
         };
-        let (input, _) = tag(":")(input)?;
-        let (input, s) = take(n_u32)(input)?;
-        return Ok((input, Bencoding::String(String::from_utf8_lossy(s).into_owned())));
+        if len > opts.max_string_len {
+            return fail(input, BencodingErrorKind::InvalidLength);
+        }
+        let (rest, _) = BencodingRef::expect(rest, b':', BencodingErrorKind::UnexpectedByte)?;
+        if len > rest.len() {
+            return fail(&rest[rest.len()..], BencodingErrorKind::UnexpectedEof);
+        }
+        return Ok((&rest[len..], &rest[..len]));
     }
 
-    named!(parse_end, tag!("e"));
+    fn check_depth(input: &'a [u8], opts: &ParseOptions, depth: usize) -> ParseResult<'a, ()> {
+        if depth > opts.max_depth {
+            return fail(input, BencodingErrorKind::TooDeep);
+        }
+        return Ok((input, ()));
+    }
+
+    // whether a list or dictionary has reached its closing `e`, which is consumed
+    fn at_end(input: &'a [u8]) -> ParseResult<'a, bool> {
+        match input.first() {
+            Some(b'e') => Ok((&input[1..], true)),
+            Some(_) => Ok((input, false)),
+            None => fail(input, BencodingErrorKind::UnexpectedEof),
+        }
+    }
 
-    fn parse_list(input: &[u8]) -> IResult<&[u8], Bencoding> {
-        let (mut c_input, _) = tag("l")(input)?;
+    fn parse_list(input: &'a [u8], opts: &ParseOptions, depth: usize) -> ParseResult<'a, BencodingRef<'a>> {
+        BencodingRef::check_depth(input, opts, depth)?;
+        let (mut c_input, _) = BencodingRef::expect(input, b'l', BencodingErrorKind::UnexpectedByte)?;
         let mut elems = Vec::new();
         loop {
-            match Bencoding::parse_end(c_input) {
-                Ok((leftovers, _)) => {
-                    c_input = leftovers;
-                    break;
-                },
-                Err(e) => match e {
-                    ParseError(_) => (),
-                    other => return Err(other),
-                }
-            };
-            let (leftovers, elem) = Bencoding::parse(c_input)?;
+            let (leftovers, end) = BencodingRef::at_end(c_input)?;
+            c_input = leftovers;
+            if end {
+                break;
+            }
+            let (leftovers, elem) = BencodingRef::parse_value(c_input, opts, depth + 1)?;
             c_input = leftovers;
             elems.push(elem);
         }
-        return Ok((c_input, Bencoding::List(elems)));
+        return Ok((c_input, BencodingRef::List(elems)));
     }
 
-    fn parse_dictionary(input: &[u8]) -> IResult<&[u8], Bencoding> {
-        let (mut c_input, _) = tag("d")(input)?;
-        let mut dict = HashMap::new();
+    fn parse_dictionary(
+        input: &'a [u8], opts: &ParseOptions, depth: usize,
+    ) -> ParseResult<'a, BencodingRef<'a>> {
+        BencodingRef::check_depth(input, opts, depth)?;
+        let (mut c_input, _) = BencodingRef::expect(input, b'd', BencodingErrorKind::UnexpectedByte)?;
+        let mut dict = Vec::new();
         loop {
-            match Bencoding::parse_end(c_input) {
-                Ok((leftovers, _)) => {
-                    c_input = leftovers;
-                    break;
-                },
-                Err(e) => match e {
-                    ParseError(_) => (),
-                    other => return Err(other),
-                }
-            };
-            let (leftovers, wrapped_key) = Bencoding::parse_string(c_input)?;
+            let (leftovers, end) = BencodingRef::at_end(c_input)?;
             c_input = leftovers;
-            let key = match wrapped_key {
-                Bencoding::String(k) => k,
-                _ => return Err(ParseError(Error{input, code: ErrorKind::IsNot})),
-            };
-            let (leftovers, value) = Bencoding::parse(c_input)?;
+            if end {
+                break;
+            }
+            if !matches!(c_input[0], b'0'..=b'9' | b'-') {
+                return fail(c_input, BencodingErrorKind::UnexpectedByte);
+            }
+            let (leftovers, key) = BencodingRef::parse_string(c_input, opts)?;
+            if opts.strict {
+                if let Some((prev, _)) = dict.last() {
+                    if key <= *prev {
+                        return fail(c_input, BencodingErrorKind::UnsortedKey);
+                    }
+                }
+            }
+            let (leftovers, value) = BencodingRef::parse_value(leftovers, opts, depth + 1)?;
             c_input = leftovers;
-            dict.insert(key, value);
+            dict.push((key, value));
         }
-        // TODO: test for alphasort using OrderedMap
-        return Ok((c_input, Bencoding::Dictionary(dict)));
+        return Ok((c_input, BencodingRef::Dictionary(dict)));
     }
 
-    fn parse(input: &[u8]) -> IResult<&[u8], Bencoding> {
-        Ok(alt((
-            complete(Bencoding::parse_integer),
-            complete(Bencoding::parse_list),
-            complete(Bencoding::parse_dictionary),
-            complete(Bencoding::parse_string),
-        ))(input)?)
+    // `depth` is the nesting level a container starting at `input` would have
+    fn parse_value(input: &'a [u8], opts: &ParseOptions, depth: usize) -> ParseResult<'a, BencodingRef<'a>> {
+        match input.first() {
+            Some(b'i') => BencodingRef::parse_integer(input),
+            Some(b'l') => BencodingRef::parse_list(input, opts, depth),
+            Some(b'd') => BencodingRef::parse_dictionary(input, opts, depth),
+            Some(b'0'..=b'9') | Some(b'-') => {
+                let (input, s) = BencodingRef::parse_string(input, opts)?;
+                Ok((input, BencodingRef::Bytes(s)))
+            },
+            Some(_) => fail(input, BencodingErrorKind::UnexpectedByte),
+            None => fail(input, BencodingErrorKind::UnexpectedEof),
+        }
     }
 }
 
 
-struct MetaInfo {
-    
-}
-
 #[cfg(test)]
 mod tests {
+    use alloc::{format, vec};
+
     use super::*;
 
+    #[test]
+    fn test_node_id_from_hex() {
+        let expected = [
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x00, 0xff,
+            0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe, 0x7f, 0x80,
+        ];
+        let lower = "0123456789abcdef00ff1032547698badcfe7f80";
+        assert_eq!(&expected[..], &NodeId::from_hex(lower).unwrap()[..]);
+        assert_eq!(&expected[..], &NodeId::from_str(&lower.to_uppercase()).unwrap()[..]);
+        let failure_cases = vec![
+            ("", NodeIdParseError::InvalidLength(0)),
+            ("0123456789abcdef00ff1032547698badcfe7f8", NodeIdParseError::InvalidLength(39)),
+            ("0123456789abcdef00ff1032547698badcfe7f800", NodeIdParseError::InvalidLength(41)),
+            ("0123456789abcdef00ff1032547698badcfe7f8g", NodeIdParseError::InvalidDigit('g')),
+            ("+123456789abcdef00ff1032547698badcfe7f80", NodeIdParseError::InvalidDigit('+')),
+            ("é123456789abcdef00ff1032547698badcfe7f80", NodeIdParseError::InvalidDigit('é')),
+        ];
+        for (input, error) in failure_cases.into_iter() {
+            assert_eq!(Some(error), NodeId::from_hex(input).err());
+        }
+    }
+
+    #[test]
+    fn test_node_id_format() {
+        let hex = "0123456789abcdef00ff1032547698badcfe7f80";
+        let node_id = NodeId::from_hex(hex).unwrap();
+        assert_eq!(hex, format!("{:x}", node_id));
+        assert_eq!(hex.to_uppercase(), format!("{:X}", node_id));
+        assert_eq!(hex, node_id.to_string());
+        assert_eq!(format!("NodeId({})", hex), format!("{:?}", node_id));
+        assert_eq!(node_id, NodeId::from_str(&node_id.to_string()).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_node_id_eq_hash() {
+        let id1 = NodeId::from_hex("0123456789abcdef00ff1032547698badcfe7f80").unwrap();
+        let id2 = NodeId::from_hex("0123456789ABCDEF00FF1032547698BADCFE7F80").unwrap();
+        let id3 = NodeId::from_hex("0123456789abcdef00ff1032547698badcfe7f81").unwrap();
+        assert_eq!(id1, id2);
+        assert_ne!(id1, id3);
+        let mut nodes = HashMap::new();
+        nodes.insert(id1, "first");
+        nodes.insert(id3, "second");
+        assert_eq!(Some(&"first"), nodes.get(&id2));
+        assert_eq!(2, nodes.len());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_node_id_ct_eq() {
+        let id = NodeId::random();
+        assert!(id.ct_eq(&id.clone()));
+        // a difference in any single bit is caught, wherever it is
+        for bit in 0..160 {
+            let mut other = id.clone();
+            other.0[bit / 8] ^= 0x80 >> (bit % 8);
+            assert!(!id.ct_eq(&other), "bit {}", bit);
+            assert!(!other.ct_eq(&id), "bit {}", bit);
+        }
+        assert!(NodeId([0; 20]).ct_eq(&NodeId([0; 20])));
+        assert!(!NodeId([0; 20]).ct_eq(&NodeId([0xff; 20])));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_node_id_random() {
+        assert_ne!(NodeId::random(), NodeId::random());
+        let prefix = NodeId::from_hex("0123456789abcdef00ff1032547698badcfe7f80").unwrap();
+        let bit = |id: &NodeId, n: usize| id[n / 8] >> (7 - n % 8) & 1;
+        for &bits in [0, 1, 7, 8, 9, 100, 159, 160].iter() {
+            let mut seen_after_prefix = [false; 2];
+            for _ in 0..64 {
+                let id = NodeId::random_in_bucket(&prefix, bits);
+                for n in 0..bits {
+                    assert_eq!(bit(&prefix, n), bit(&id, n));
+                }
+                if bits < 160 {
+                    seen_after_prefix[bit(&id, bits) as usize] = true;
+                }
+            }
+            // the bits after the prefix are still random, so both values turn up
+            assert_eq!(bits < 160, seen_after_prefix == [true, true]);
+        }
+        assert_eq!(prefix, NodeId::random_in_bucket(&prefix, 500));
+    }
+
+    #[test]
+    fn test_node_id_k_closest() {
+        let node_id = |last| {
+            let mut id = [0u8; 20];
+            id[19] = last;
+            NodeId(id)
+        };
+        let target = node_id(0b0101);
+        let nodes: Vec<NodeId> = [0b0000, 0b1111, 0b0100, 0b0111, 0b0001, 0b1101]
+            .iter().map(|&last| node_id(last)).collect();
+        assert_eq!(
+            vec![node_id(0b0100), node_id(0b0111), node_id(0b0001)],
+            target.k_closest(&nodes, 3),
+        );
+        assert_eq!(target.closest(&nodes), target.k_closest(&nodes, 1)[0]);
+        let all = target.k_closest(&nodes, 8);
+        assert_eq!(nodes.len(), all.len());
+        assert_eq!(node_id(0b1111), all[5]);
+        assert!(target.k_closest(&nodes, 0).is_empty());
+        assert!(target.k_closest(&[], 8).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_node_id_bucket_index() {
+        let zero = NodeId([0u8; 20]);
+        let mut first_bit = [0u8; 20];
+        first_bit[0] = 0x80;
+        let mut last_bit = [0u8; 20];
+        last_bit[19] = 0x01;
+        let mut middle = [0u8; 20];
+        middle[2] = 0x10;
+        middle[19] = 0xff;
+        assert_eq!(0, zero.bucket_index(&NodeId(first_bit)));
+        assert_eq!(159, zero.bucket_index(&NodeId(last_bit)));
+        assert_eq!(19, NodeId(middle).bucket_index(&zero));
+        assert_eq!(160, zero.bucket_index(&zero));
+        let node_id = NodeId::random();
+        assert_eq!(160, node_id.bucket_index(&node_id));
+    }
+
+    #[test]
+    fn test_node_id_from_bytes() {
+        let bytes: Vec<u8> = (0..20).collect();
+        let node_id = NodeId::try_from(&bytes[..]).unwrap();
+        assert_eq!("000102030405060708090a0b0c0d0e0f10111213", node_id.to_string());
+        let mut array = [0u8; 20];
+        array.copy_from_slice(&bytes);
+        assert_eq!(node_id, NodeId::from(array));
+
+        let failure_cases = vec![
+            (&bytes[..19], NodeIdLengthError(19)),
+            (&bytes[..0], NodeIdLengthError(0)),
+            (&[0u8; 21][..], NodeIdLengthError(21)),
+        ];
+        for (input, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), NodeId::try_from(input));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_node_id_from_ip() {
+        // the test vectors from BEP 42, where the middle of each ID is random
+        let cases = vec![
+            ("124.31.75.21", 1, "5fbfbf"),
+            ("21.75.31.124", 86, "5a3ce9"),
+            ("65.23.51.170", 22, "a5d432"),
+            ("84.124.73.14", 65, "1b0321"),
+            ("43.213.53.83", 90, "e56f6c"),
+        ];
+        for (ip, rand, prefix) in cases.into_iter() {
+            let ip: IpAddr = ip.parse().unwrap();
+            let node_id = NodeId::from_ip(ip, rand);
+            let hex = node_id.to_string();
+            // only the top 5 bits of the third byte are fixed
+            assert_eq!(&prefix[..4], &hex[..4]);
+            assert_eq!(u8::from_str_radix(&prefix[4..], 16).unwrap() & 0xf8, node_id[2] & 0xf8);
+            assert_eq!(rand, node_id[19]);
+            assert!(node_id.is_valid_for_ip(ip));
+        }
+
+        let ip: IpAddr = "124.31.75.21".parse().unwrap();
+        let valid = NodeId::from_hex("5fbfbff10c5d6a4ec8a88e4c6ab4c28b95eee401").unwrap();
+        assert!(valid.is_valid_for_ip(ip));
+        assert!(!valid.is_valid_for_ip("124.31.75.22".parse().unwrap()));
+        let mut wrong_rand = valid.clone();
+        wrong_rand.0[19] = 2;
+        assert!(!wrong_rand.is_valid_for_ip(ip));
+
+        let ip: IpAddr = "2001:db8:85a3::8a2e:370:7334".parse().unwrap();
+        assert!(NodeId::from_ip(ip, 7).is_valid_for_ip(ip));
+        assert!(!NodeId::from_ip(ip, 7).is_valid_for_ip("2001:db8:85a4::1".parse().unwrap()));
+        assert!(NodeId::random().is_valid_for_ip("192.168.1.2".parse().unwrap()));
+        assert!(NodeId::random().is_valid_for_ip("127.0.0.1".parse().unwrap()));
+        assert_eq!(0xe3069283, crc32c(b"123456789"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_node_id_distance_bytes() {
+        let id1 = NodeId::from_hex("0123456789abcdef00ff1032547698badcfe7f80").unwrap();
+        let id2 = NodeId::from_hex("ff23456789abcdef00ff1032547698badcfe7f8f").unwrap();
+        let mut expected = [0u8; 20];
+        expected[0] = 0xfe;
+        expected[19] = 0x0f;
+        assert_eq!(expected, id1.distance_bytes(&id2));
+        assert_eq!(BigUint::from_bytes_be(&expected), id1.distance(&id2));
+        assert_eq!([0u8; 20], id1.distance_bytes(&id1));
+
+        for _ in 0..32 {
+            let (target, a, b) = (NodeId::random(), NodeId::random(), NodeId::random());
+            assert_eq!(target.distance(&a).cmp(&target.distance(&b)), target.cmp_distance(&a, &b));
+        }
+        assert_eq!(Ordering::Less, id1.cmp_distance(&id1, &id2));
+        assert_eq!(Ordering::Greater, id1.cmp_distance(&id2, &id1));
+        assert_eq!(Ordering::Equal, id1.cmp_distance(&id2, &id2));
+    }
+
     #[test]
     fn test_bencoding_integer() {
         let make_bencoded_bigint = |s| Bencoding::Integer(BigInt::from_str(s).unwrap()) ;
@@ -186,9 +1562,43 @@ mod tests {
                 "i-123456789123456789e",
                 Ok((ev.as_ref(), make_bencoded_bigint("-123456789123456789"))),
         ));
+        success_cases.push(("i0e", Ok((ev.as_ref(), make_bencoded_bigint("0")))));
         for case in success_cases.iter() {
             assert_eq!(case.1, Bencoding::parse(&case.0.as_bytes()));
         }
+        let failure_cases = vec!["i03e", "i-0e", "i00e", "i007e", "i-03e"];
+        for case in failure_cases.iter() {
+            let error = BencodingParseError{offset: 1, kind: BencodingErrorKind::InvalidInteger};
+            assert_eq!(Err(error), Bencoding::from_slice(case.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_bencoding_integer_errors() {
+        // the offset is of the first byte that couldn't be part of the integer,
+        // except that a malformed number as a whole is reported from its start
+        let failure_cases = vec![
+            ("ie", 1, BencodingErrorKind::EmptyInteger),
+            ("i-e", 1, BencodingErrorKind::EmptyInteger),
+            ("i e", 1, BencodingErrorKind::InvalidInteger),
+            ("i+5e", 1, BencodingErrorKind::InvalidInteger),
+            ("i 5e", 1, BencodingErrorKind::InvalidInteger),
+            ("i5 e", 2, BencodingErrorKind::InvalidInteger),
+            ("i--5e", 1, BencodingErrorKind::InvalidInteger),
+            ("i5.0e", 2, BencodingErrorKind::InvalidInteger),
+            ("i5", 2, BencodingErrorKind::UnexpectedEof),
+        ];
+        for (case, offset, kind) in failure_cases.into_iter() {
+            assert_eq!(Err(BencodingParseError{offset, kind}), Bencoding::from_slice(case.as_bytes()));
+        }
+        assert_eq!(
+            "failed to parse bencoding: integer with no digits at byte 1",
+            Bencoding::from_slice(b"ie").unwrap_err().to_string(),
+        );
+        assert_eq!(
+            "failed to parse bencoding: invalid integer at byte 2",
+            Bencoding::from_slice(b"i5 e").unwrap_err().to_string(),
+        );
     }
 
     #[test]
@@ -202,6 +1612,115 @@ mod tests {
         for case in success_cases.iter() {
             assert_eq!(case.1, Bencoding::parse(&case.0.as_bytes()));
         }
+        let failure_cases = vec![
+            ("3:ab", 4, BencodingErrorKind::UnexpectedEof),
+            ("1:", 2, BencodingErrorKind::UnexpectedEof),
+            ("18446744073709551616:a", 0, BencodingErrorKind::InvalidLength),
+            // reported at the end of the input, without trying to take that much
+            ("999999999:abc", 13, BencodingErrorKind::UnexpectedEof),
+            ("l999999999999:e", 15, BencodingErrorKind::UnexpectedEof),
+        ];
+        for (case, offset, kind) in failure_cases.into_iter() {
+            assert_eq!(Err(BencodingParseError{offset, kind}), Bencoding::from_slice(case.as_bytes()));
+        }
+
+        let opts = ParseOptions{max_string_len: 4, ..ParseOptions::default()};
+        assert_eq!(Ok(Bencoding::from("spam")), Bencoding::from_slice_with(b"4:spam", &opts));
+        let too_long = BencodingParseError{offset: 1, kind: BencodingErrorKind::InvalidLength};
+        assert_eq!(Err(too_long), Bencoding::from_slice_with(b"l5:spamse", &opts));
+    }
+
+    #[test]
+    fn test_bencoding_parse_errors() {
+        let failure_cases = vec![
+            ("", 0, BencodingErrorKind::UnexpectedEof),
+            ("x", 0, BencodingErrorKind::UnexpectedByte),
+            ("i12", 3, BencodingErrorKind::UnexpectedEof),
+            ("i-", 2, BencodingErrorKind::UnexpectedEof),
+            ("i1x2e", 2, BencodingErrorKind::InvalidInteger),
+            ("ie", 1, BencodingErrorKind::EmptyInteger),
+            ("-1:a", 0, BencodingErrorKind::InvalidLength),
+            ("03:abc", 0, BencodingErrorKind::InvalidLength),
+            ("3x", 1, BencodingErrorKind::UnexpectedByte),
+            ("l1:a", 4, BencodingErrorKind::UnexpectedEof),
+            ("l1:ax", 4, BencodingErrorKind::UnexpectedByte),
+            ("di1e1:ae", 1, BencodingErrorKind::UnexpectedByte),
+            ("d1:a", 4, BencodingErrorKind::UnexpectedEof),
+            ("i1ei2e", 3, BencodingErrorKind::TrailingData(3)),
+        ];
+        for (case, offset, kind) in failure_cases.into_iter() {
+            assert_eq!(Err(BencodingParseError{offset, kind}), Bencoding::from_slice(case.as_bytes()));
+        }
+        assert_eq!(
+            "failed to parse bencoding: invalid integer at byte 2",
+            Bencoding::from_slice(b"i1x2e").unwrap_err().to_string(),
+        );
+    }
+
+    #[test]
+    fn test_bencoding_incomplete() {
+        let input = b"d4:infod6:lengthi-42e4:name3:cate4:listl1:ai0eee";
+        assert!(Bencoding::from_slice(input).is_ok());
+        // every prefix of a value could still become that value
+        for len in 0..input.len() {
+            let error = Bencoding::from_slice(&input[..len]).unwrap_err();
+            assert!(error.is_incomplete(), "{:?} gave {:?}", &input[..len], error);
+            assert_eq!(len, error.offset);
+        }
+        assert!(Bencoding::from_slice(b"i123").unwrap_err().is_incomplete());
+
+        // whereas nothing could be added to these to make them valid
+        let failure_cases = vec!["i1x2e", "i1x", "i01", "-", "-1", "l1:ax", "di1e", "i1ei"];
+        for case in failure_cases.into_iter() {
+            assert!(!Bencoding::from_slice(case.as_bytes()).unwrap_err().is_incomplete(), "{}", case);
+        }
+    }
+
+    #[test]
+    fn test_bencoding_parse_many() {
+        let expected = vec![Bencoding::Int(1), Bencoding::Int(2), Bencoding::String("abc".to_string())];
+        assert_eq!(Ok(expected), Bencoding::parse_many(b"i1ei2e3:abc"));
+        assert_eq!(Ok(vec![]), Bencoding::parse_many(b""));
+        assert_eq!(Ok(vec![Bencoding::List(vec![])]), Bencoding::parse_many(b"le"));
+
+        // offsets are from the start of the whole input
+        let failure_cases = vec![
+            ("i1ei2x", 5, BencodingErrorKind::InvalidInteger),
+            ("i1e3:ab", 7, BencodingErrorKind::UnexpectedEof),
+            ("lei1ee", 5, BencodingErrorKind::UnexpectedByte),
+        ];
+        for (case, offset, kind) in failure_cases.into_iter() {
+            assert_eq!(Err(BencodingParseError{offset, kind}), Bencoding::parse_many(case.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_bencoding_int_fast_path() {
+        let ev = Vec::new();
+        let cases = vec![
+            ("i9223372036854775807e", BencodingRef::Int(i64::MAX)),
+            ("i-9223372036854775808e", BencodingRef::Int(i64::MIN)),
+            ("i9223372036854775808e", BencodingRef::Integer(BigInt::from(i64::MAX) + 1)),
+        ];
+        for (encoded, bencoding) in cases.iter() {
+            let parsed = BencodingRef::parse_value(encoded.as_bytes(), &ParseOptions::default(), 1);
+            assert_eq!(Ok((ev.as_ref(), bencoding.clone())), parsed);
+            assert_eq!(encoded.as_bytes(), &bencoding.to_owned().unwrap().encode()[..]);
+        }
+        assert_eq!(Bencoding::Int(42), Bencoding::Integer(BigInt::from(42)));
+        assert_eq!(Bencoding::Integer(BigInt::from(-7)), Bencoding::Int(-7));
+        assert_ne!(Bencoding::Int(42), Bencoding::Integer(BigInt::from(43)));
+    }
+
+    #[test]
+    fn test_bencoding_bytes() {
+        let ev = Vec::new();
+        let input = b"4:\xFF\xFEab";
+        assert_eq!(
+            Ok((ev.as_ref(), Bencoding::Bytes(vec![0xFF, 0xFE, b'a', b'b']))),
+            Bencoding::parse(input),
+        );
+        assert_eq!(&input[..], &Bencoding::from_slice(input).unwrap().encode()[..]);
     }
 
     #[test]
@@ -221,13 +1740,14 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn test_bencoding_dictionary() {
         let ev = Vec::new();
         let mut success_cases = Vec::new();
         let mut sc1_map = HashMap::new();
         sc1_map.insert("cow".to_string(), Bencoding::String("moo".to_string()));
         sc1_map.insert("spam".to_string(), Bencoding::String("eggs".to_string()));
-        let sc1 = Bencoding::Dictionary(sc1_map);
+        let sc1 = Bencoding::from(sc1_map);
         success_cases.push((
             "d3:cow3:moo4:spam4:eggse",
             Ok((ev.as_ref(), sc1)),
@@ -236,4 +1756,360 @@ mod tests {
             assert_eq!(case.1, Bencoding::parse(&case.0.as_bytes()));
         }
     }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_bencoding_dictionary_strict() {
+        let mut sc1_map = HashMap::new();
+        sc1_map.insert("cow".to_string(), Bencoding::String("moo".to_string()));
+        sc1_map.insert("spam".to_string(), Bencoding::String("eggs".to_string()));
+        let sc1 = Bencoding::from(sc1_map);
+        assert_eq!(Ok(sc1), Bencoding::from_slice_strict(b"d3:cow3:moo4:spam4:eggse"));
+        let failure_cases = vec![
+            ("d4:spam4:eggs3:cow3:mooe", 13),
+            ("d3:cow3:moo3:cow3:mooe", 11),
+            ("l1:ad1:bi1e1:ai2eee", 11),
+        ];
+        for (case, offset) in failure_cases.into_iter() {
+            let error = BencodingParseError{offset, kind: BencodingErrorKind::UnsortedKey};
+            assert_eq!(Err(error), Bencoding::from_slice_strict(case.as_bytes()));
+            assert!(Bencoding::from_slice(case.as_bytes()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_bencoding_max_depth() {
+        let nested = |n| [vec![b'l'; n], vec![b'e'; n]].concat();
+        let too_deep = BencodingParseError{offset: 100, kind: BencodingErrorKind::TooDeep};
+        assert_eq!(Err(too_deep.clone()), Bencoding::from_slice(&nested(10000)));
+        assert_eq!(Err(too_deep), Bencoding::from_slice(&nested(101)));
+        assert!(Bencoding::from_slice(&nested(100)).is_ok());
+        let opts = ParseOptions{max_depth: 2, ..ParseOptions::default()};
+        assert!(Bencoding::from_slice_with(b"ld1:al1:bee", &opts).is_err());
+        assert!(Bencoding::from_slice_with(b"ld1:a1:bee", &opts).is_ok());
+        assert!(Bencoding::from_slice_with(b"i7e", &ParseOptions{max_depth: 0, ..opts}).is_ok());
+    }
+
+    #[test]
+    fn test_bencoding_ref() {
+        let input = b"d4:name3:cat6:pieces2:\xFF\xFE4:sizei42e4:tagsl1:a1:bee";
+        let bencoding = BencodingRef::from_slice(input).unwrap();
+        let expected = BencodingRef::Dictionary(vec![
+            (&b"name"[..], BencodingRef::Bytes(&b"cat"[..])),
+            (&b"pieces"[..], BencodingRef::Bytes(&b"\xFF\xFE"[..])),
+            (&b"size"[..], BencodingRef::Int(42)),
+            (&b"tags"[..], BencodingRef::List(vec![
+                BencodingRef::Bytes(&b"a"[..]),
+                BencodingRef::Bytes(&b"b"[..]),
+            ])),
+        ]);
+        assert_eq!(expected, bencoding);
+        if let BencodingRef::Dictionary(dict) = &bencoding {
+            // borrowed straight out of the input buffer
+            assert_eq!(input[3..].as_ptr(), dict[0].0.as_ptr());
+        }
+        assert_eq!(Bencoding::from_slice(input), Ok(bencoding.to_owned().unwrap()));
+        let trailing = BencodingParseError{offset: 5, kind: BencodingErrorKind::TrailingData(5)};
+        assert_eq!(Err(trailing), BencodingRef::from_slice(b"l1:ae3:cat"));
+
+        // one byte too many after a whole dictionary
+        let error = Bencoding::from_slice(b"d1:ai1ee\n").unwrap_err();
+        assert_eq!(BencodingParseError{offset: 8, kind: BencodingErrorKind::TrailingData(1)}, error);
+        assert!(!error.is_incomplete());
+        assert_eq!("failed to parse bencoding: 1 bytes of trailing data at byte 8", error.to_string());
+    }
+
+    #[test]
+    fn test_bencoding_ref_raw_keys() {
+        // two keys that differ only in bytes that aren't UTF-8
+        let input = b"d2:\xFFa1:x2:\xFEa1:y1:bi1e1:bi2ee";
+        let bencoding = BencodingRef::from_slice(input).unwrap();
+        assert_eq!(Some(&BencodingRef::Bytes(&b"x"[..])), bencoding.get(b"\xFFa"));
+        assert_eq!(Some(&BencodingRef::Bytes(&b"y"[..])), bencoding.get(b"\xFEa"));
+        assert_eq!(None, bencoding.get(b"a"));
+        assert_eq!(Some(&BencodingRef::Int(2)), bencoding.get(b"b"));
+        let keys: Vec<&[u8]> = bencoding.iter_dict().map(|(k, _)| k).collect();
+        assert_eq!(vec![&b"\xFFa"[..], &b"\xFEa"[..], &b"b"[..], &b"b"[..]], keys);
+        assert_eq!(0, BencodingRef::Int(1).iter_dict().count());
+        assert_eq!(None, BencodingRef::Int(1).get(b"b"));
+
+        // whereas an owned copy couldn't tell them apart, so there isn't one
+        assert_eq!(Err(&b"\xFFa"[..]), bencoding.to_owned());
+        let error = BencodingParseError{offset: 3, kind: BencodingErrorKind::InvalidKey};
+        assert_eq!(Err(error.clone()), Bencoding::from_slice(input));
+        let mut many = b"i1e".to_vec();
+        many.extend_from_slice(input);
+        assert_eq!(Err(BencodingParseError{offset: 6, ..error}), Bencoding::parse_many(&many));
+        assert_eq!(Ok(Bencoding::from(vec![Bencoding::from(b"\xFF".to_vec())])), Bencoding::from_slice(b"l1:\xFFe"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_bencoding_ord_hash() {
+        use std::collections::{hash_map::DefaultHasher, HashSet};
+
+        let big = || Bencoding::Integer(BigInt::from_str("123456789123456789123").unwrap());
+        let ordered = vec![
+            Bencoding::Integer(BigInt::from_str("-123456789123456789123").unwrap()),
+            Bencoding::Int(-1),
+            Bencoding::Int(7),
+            big(),
+            bencode!(""),
+            bencode!("a"),
+            Bencoding::Bytes(b"a".to_vec()),
+            bencode!("ab"),
+            bencode!(vec![0xffu8]),
+            bencode!([]),
+            bencode!([1]),
+            bencode!([1, "a"]),
+            bencode!([2]),
+            bencode!({}),
+            bencode!({"a" => 1}),
+            bencode!({"a" => 1, "b" => 0}),
+            bencode!({"a" => 2}),
+            bencode!({"b" => 0}),
+        ];
+        for (n, a) in ordered.iter().enumerate() {
+            for (m, b) in ordered.iter().enumerate() {
+                assert_eq!(n.cmp(&m), a.cmp(b), "{:?} vs {:?}", a, b);
+            }
+        }
+        let mut shuffled = ordered.clone();
+        shuffled.reverse();
+        shuffled.sort();
+        assert_eq!(ordered, shuffled);
+
+        let hash = |value: &Bencoding| {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        };
+        let small = Bencoding::Integer(BigInt::from(7));
+        assert_eq!(Ordering::Equal, Bencoding::Int(7).cmp(&small));
+        assert_eq!(hash(&Bencoding::Int(7)), hash(&small));
+        // dictionaries with the same entries hash the same whatever order they were inserted in
+        let mut forwards = HashMap::new();
+        let mut backwards = HashMap::new();
+        for n in 0..32 {
+            forwards.insert(n.to_string(), Bencoding::Int(n));
+            backwards.insert((31 - n).to_string(), Bencoding::Int(31 - n));
+        }
+        assert_eq!(hash(&Bencoding::from(forwards)), hash(&Bencoding::from(backwards)));
+
+        let set: HashSet<Bencoding> = ordered.iter().cloned().chain(vec![small, big(), bencode!([1, "a"])]).collect();
+        assert_eq!(ordered.len(), set.len());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_bencoding_from() {
+        assert_eq!(Bencoding::Int(-42), Bencoding::from(-42));
+        assert_eq!(Bencoding::String("cat".to_string()), Bencoding::from("cat"));
+        assert_eq!(Bencoding::String("cat".to_string()), Bencoding::from("cat".to_string()));
+        assert_eq!(Bencoding::String("cat".to_string()), Bencoding::from(b"cat".to_vec()));
+        assert_eq!(Bencoding::Bytes(vec![0xFF, 0x00]), Bencoding::from(vec![0xFF, 0x00]));
+        assert_eq!(
+            Bencoding::List(vec![Bencoding::Int(1), Bencoding::String("a".to_string())]),
+            Bencoding::from(vec![Bencoding::from(1), Bencoding::from("a")]),
+        );
+        let mut dict = HashMap::new();
+        dict.insert("q".to_string(), Bencoding::from("ping"));
+        assert_eq!(Ok(Bencoding::from(dict)), Bencoding::from_slice(b"d1:q4:pinge"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_bencode_macro() {
+        let id = vec![0xABu8; 20];
+        let mut args = HashMap::new();
+        args.insert("id".to_string(), Bencoding::Bytes(id.clone()));
+        args.insert("ports".to_string(), Bencoding::List(vec![Bencoding::Int(6881), Bencoding::Int(-1)]));
+        let mut query = HashMap::new();
+        query.insert("t".to_string(), Bencoding::String("aa".to_string()));
+        query.insert("y".to_string(), Bencoding::String("q".to_string()));
+        query.insert("q".to_string(), Bencoding::String("ping".to_string()));
+        query.insert("a".to_string(), Bencoding::from(args));
+        let built = bencode!({
+            "t" => "aa",
+            "y" => "q",
+            "q" => "ping",
+            "a" => { "id" => id.clone(), "ports" => [6881, -1] },
+        });
+        assert_eq!(Bencoding::from(query), built);
+
+        let mut inner = HashMap::new();
+        inner.insert("k".to_string(), Bencoding::Int(1 + 2));
+        let expected = Bencoding::List(vec![
+            Bencoding::from(inner),
+            Bencoding::List(vec![Bencoding::List(vec![])]),
+            Bencoding::String("x".to_string()),
+        ]);
+        let key = "k".to_string();
+        assert_eq!(expected, bencode!([{ key => 1 + 2 }, [[]], "x"]));
+        assert_eq!(Bencoding::from(HashMap::new()), bencode!({}));
+        assert_eq!(Bencoding::List(vec![]), bencode!([]));
+        assert_eq!(Bencoding::Int(7), bencode!(7));
+    }
+
+    #[test]
+    fn test_bencoding_accessors() {
+        let bencoding = Bencoding::from_slice(b"d4:infod6:lengthi42ee4:listl1:a1:be4:name3:cate").unwrap();
+        assert_eq!(Some("cat"), bencoding.get("name").and_then(|v| v.as_str()));
+        assert_eq!(
+            Some(BigInt::from(42)),
+            bencoding.get("info").and_then(|v| v.get("length")).and_then(|v| v.to_bigint()),
+        );
+        let big = BigInt::from(u64::MAX) * 2u8;
+        assert_eq!(Some(&big), Bencoding::Integer(big.clone()).as_integer());
+        assert_eq!(Some(big.clone()), Bencoding::Integer(big).to_bigint());
+        // `Int`s are only widened on request
+        assert_eq!(None, Bencoding::Int(42).as_integer());
+        assert_eq!(Some(2), bencoding.get("list").and_then(|v| v.as_list()).map(|l| l.len()));
+        assert_eq!(Some(3), bencoding.as_dict().map(|d| d.len()));
+        assert_eq!(None, bencoding.get("missing"));
+        assert_eq!(None, bencoding.get("name").and_then(|v| v.get("name")));
+        assert_eq!(None, bencoding.get("name").and_then(|v| v.to_bigint()));
+        assert_eq!(None, bencoding.get("list").and_then(|v| v.as_str()));
+        assert_eq!(None, bencoding.as_list());
+        assert_eq!(None, Bencoding::Bytes(vec![0xFF]).as_str());
+    }
+
+    #[test]
+    fn test_bencoding_iter() {
+        let bencoding = Bencoding::from_slice(b"d4:name3:cat4:listl1:a1:bee").unwrap();
+        let entries: Vec<(&str, &Bencoding)> = bencoding.iter_dict().collect();
+        assert_eq!(vec!["name", "list"], entries.iter().map(|&(key, _)| key).collect::<Vec<&str>>());
+        let list = bencoding.get("list").unwrap();
+        assert_eq!(vec![Some("a"), Some("b")], list.iter_list().map(|v| v.as_str()).collect::<Vec<Option<&str>>>());
+
+        // the wrong variant has nothing in it
+        assert_eq!(0, bencoding.iter_list().count());
+        assert_eq!(0, list.iter_dict().count());
+        assert_eq!(0, Bencoding::Int(1).iter_list().count());
+        assert_eq!(0, Bencoding::from("x").iter_dict().count());
+
+        assert_eq!(Some(2), list.len());
+        assert_eq!(Some(false), bencoding.is_empty());
+        assert_eq!(Some(true), Bencoding::List(vec![]).is_empty());
+        assert_eq!(Some(true), Bencoding::from(Dictionary::new()).is_empty());
+        assert_eq!(None, Bencoding::from("").is_empty());
+    }
+
+    #[test]
+    fn test_bencoding_checked_integers() {
+        let parse = |s: &str| Bencoding::from_slice(s.as_bytes()).unwrap();
+        assert_eq!(Some(i64::MAX), parse("i9223372036854775807e").as_i64_checked());
+        assert_eq!(Some(i64::MIN), parse("i-9223372036854775808e").as_i64_checked());
+        assert_eq!(None, parse("i9223372036854775808e").as_i64_checked());
+        assert_eq!(None, parse("i-9223372036854775809e").as_i64_checked());
+        assert_eq!(Some(0), parse("i0e").as_i64_checked());
+
+        assert_eq!(Some(i64::MAX as u64 + 1), parse("i9223372036854775808e").as_u64_checked());
+        assert_eq!(Some(u64::MAX), parse("i18446744073709551615e").as_u64_checked());
+        assert_eq!(None, parse("i18446744073709551616e").as_u64_checked());
+        assert_eq!(None, parse("i-1e").as_u64_checked());
+        assert_eq!(Some(0), parse("i0e").as_u64_checked());
+
+        assert_eq!(None, Bencoding::from("1").as_i64_checked());
+        assert_eq!(None, Bencoding::List(vec![]).as_u64_checked());
+    }
+
+    #[test]
+    fn test_bencoding_try_from() {
+        assert_eq!(Ok(-3), i64::try_from(Bencoding::Int(-3)));
+        assert_eq!(Ok(7), i64::try_from(Bencoding::Integer(BigInt::from(7))));
+        assert_eq!(Ok("cat".to_string()), String::try_from(Bencoding::from("cat")));
+        assert_eq!(Ok(b"cat".to_vec()), Vec::<u8>::try_from(Bencoding::from("cat")));
+        assert_eq!(Ok(vec![0xFF, 0x00]), Vec::<u8>::try_from(Bencoding::from(vec![0xFF, 0x00])));
+
+        let big = Bencoding::Integer(BigInt::from_str("9223372036854775808").unwrap());
+        assert_eq!(Err(BencodingConversionError::OutOfRange), i64::try_from(big));
+        let wrong_variant = BencodingConversionError::WrongVariant;
+        assert_eq!(Err(wrong_variant.clone()), i64::try_from(Bencoding::from("7")));
+        assert_eq!(Err(wrong_variant.clone()), String::try_from(Bencoding::Int(7)));
+        assert_eq!(Err(wrong_variant.clone()), String::try_from(Bencoding::from(vec![0xFF])));
+        assert_eq!(Err(wrong_variant), Vec::<u8>::try_from(Bencoding::List(vec![])));
+
+        // pulling a KRPC error's code and message apart
+        let error = Bencoding::from(vec![Bencoding::from(201), Bencoding::from("A Generic Error Ocurred")]);
+        let parse = |error: Bencoding| -> Result<(i64, String), BencodingConversionError> {
+            let mut fields = error.as_list().ok_or(BencodingConversionError::WrongVariant)?.to_vec().into_iter();
+            let code = i64::try_from(fields.next().ok_or(BencodingConversionError::WrongVariant)?)?;
+            let message = String::try_from(fields.next().ok_or(BencodingConversionError::WrongVariant)?)?;
+            return Ok((code, message));
+        };
+        assert_eq!(Ok((201, "A Generic Error Ocurred".to_string())), parse(error));
+    }
+
+    #[test]
+    fn test_bencoding_display() {
+        let bencoding = Bencoding::from_slice(
+            b"d1:yi-3e4:spaml1:a2:\xFF\x00i7ee3:cow3:m\"\\e",
+        ).unwrap();
+        assert_eq!(
+            r#"{"cow": "m\"\\", "spam": ["a", "\xff\x00", 7], "y": -3}"#,
+            bencoding.to_string(),
+        );
+        assert_eq!("[]", Bencoding::List(vec![]).to_string());
+        assert_eq!("{}", Bencoding::from(Dictionary::new()).to_string());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_bencoding_encode_roundtrip() {
+        let mut dict = HashMap::new();
+        dict.insert("spam".to_string(), Bencoding::String("eggs".to_string()));
+        dict.insert("cow".to_string(), Bencoding::Integer(BigInt::from(-42)));
+        let cases = vec![
+            (Bencoding::Integer(BigInt::from(0)), &b"i0e"[..]),
+            (Bencoding::Integer(BigInt::from_str("-123456789123456789").unwrap()), &b"i-123456789123456789e"[..]),
+            (Bencoding::String("".to_string()), &b"0:"[..]),
+            (Bencoding::String("cat".to_string()), &b"3:cat"[..]),
+            (Bencoding::Bytes(vec![0xC3, 0x28]), &b"2:\xC3("[..]),
+            (Bencoding::List(vec![]), &b"le"[..]),
+            (Bencoding::List(vec![
+                Bencoding::String("spam".to_string()),
+                Bencoding::Integer(BigInt::from(7)),
+            ]), &b"l4:spami7ee"[..]),
+            (Bencoding::from(dict), &b"d3:cowi-42e4:spam4:eggse"[..]),
+        ];
+        for (bencoding, encoded) in cases.iter() {
+            assert_eq!(*encoded, &bencoding.encode()[..]);
+            assert_eq!(Ok(bencoding.clone()), Bencoding::from_slice(&bencoding.encode()));
+        }
+    }
+
+    #[test]
+    fn test_bencoding_encode_into() {
+        let message = bencode!({"t" => "aa", "y" => "q", "q" => "ping", "a" => {"id" => "abcdefghij0123456789"}});
+        let mut buf = b"prefix".to_vec();
+        message.encode_into(&mut buf);
+        assert_eq!(b"prefix", &buf[..6]);
+        assert_eq!(message.encode(), &buf[6..]);
+
+        // a cleared buffer keeps its room for the next message
+        let capacity = buf.capacity();
+        buf.clear();
+        Bencoding::from(42).encode_into(&mut buf);
+        assert_eq!(b"i42e", &buf[..]);
+        assert_eq!(capacity, buf.capacity());
+    }
+
+    #[test]
+    fn test_bencoding_unsorted_roundtrip() {
+        // keys out of order at both levels, as some encoders write them
+        let input = b"d4:spaml1:ai1ee3:cowd1:zi1e1:yi2ee1:ai0ee";
+        let mut bencoding = Bencoding::from_slice(input).unwrap();
+        assert_eq!(&input[..], &bencoding.encode()[..]);
+        let keys: Vec<&str> = bencoding.as_dict().unwrap().keys().map(|key| key.as_str()).collect();
+        assert_eq!(vec!["spam", "cow", "a"], keys);
+        assert_eq!(Some(&Bencoding::Int(2)), bencoding.get("cow").and_then(|cow| cow.get("y")));
+
+        let unsorted = bencoding.clone();
+        bencoding.canonicalize();
+        assert_eq!(&b"d1:ai0e3:cowd1:yi2e1:zi1ee4:spaml1:ai1eee"[..], &bencoding.encode()[..]);
+        assert_eq!(Ok(bencoding.clone()), Bencoding::from_slice_strict(&bencoding.encode()));
+        // the order doesn't make them different values
+        assert_eq!(unsorted, bencoding);
+    }
 }
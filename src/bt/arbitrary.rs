@@ -0,0 +1,55 @@
+//! Random `Bencoding` values for property tests, with the `arbitrary` feature.
+
+use num_bigint::BigInt;
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    collection::{hash_map, vec},
+    prop_oneof,
+    strategy::{BoxedStrategy, Strategy},
+};
+
+use super::Bencoding;
+
+/// How many levels of lists and dictionaries a generated value can have.
+pub const ARBITRARY_MAX_DEPTH: u32 = 4;
+
+impl Arbitrary for Bencoding {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Bencoding>;
+
+    fn arbitrary_with(_: ()) -> BoxedStrategy<Bencoding> {
+        let leaf = prop_oneof![
+            any::<String>().prop_map(Bencoding::String),
+            // bytes that happen to be UTF-8 are a `String`, as when parsing
+            vec(any::<u8>(), 0..32).prop_map(Bencoding::from_raw_string),
+            any::<i64>().prop_map(Bencoding::Int),
+            any::<i128>().prop_map(|n| Bencoding::Integer(BigInt::from(n))),
+        ];
+        let value = leaf.prop_recursive(ARBITRARY_MAX_DEPTH, 64, 8, |inner| {
+            prop_oneof![
+                vec(inner.clone(), 0..8).prop_map(Bencoding::List),
+                // `Dictionary::from` sorts a `HashMap`'s keys
+                hash_map(any::<String>(), inner, 0..8).prop_map(Bencoding::from),
+            ]
+        });
+        return value.boxed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{prop_assert_eq, proptest};
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_bencoding_round_trip(value in any::<Bencoding>()) {
+            let encoded = value.encode();
+            prop_assert_eq!(Ok(value.clone()), Bencoding::from_slice(&encoded));
+            // the generator builds dictionaries with sorted keys, which `encode`
+            // keeps, so the strict parser takes it too
+            prop_assert_eq!(Ok(value), Bencoding::from_slice_strict(&encoded));
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! The "compact" binary formats for peers and DHT nodes (BEP 5, BEP 23), and
+//! their IPv6 counterparts (BEP 7, BEP 32).
+//!
+//! A compact peer is 4 bytes of IPv4 address followed by a 2-byte port, both
+//! big-endian. A compact node is a 20-byte node ID followed by a compact peer.
+//! The IPv6 forms are the same with a 16-byte address instead.
+
+use std::{convert::TryFrom, fmt, net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6}};
+
+use super::NodeId;
+
+pub const COMPACT_PEER_LEN: usize = 6;
+pub const COMPACT_NODE_LEN: usize = 20 + COMPACT_PEER_LEN;
+pub const COMPACT_PEER6_LEN: usize = 18;
+pub const COMPACT_NODE6_LEN: usize = 20 + COMPACT_PEER6_LEN;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CompactDecodeError;
+impl fmt::Display for CompactDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "compact peer or node info has the wrong length")
+    }
+}
+impl std::error::Error for CompactDecodeError {}
+
+pub fn encode_compact_peer(addr: &SocketAddrV4) -> [u8; COMPACT_PEER_LEN] {
+    let mut buf = [0u8; COMPACT_PEER_LEN];
+    buf[..4].copy_from_slice(&addr.ip().octets());
+    buf[4..].copy_from_slice(&addr.port().to_be_bytes());
+    return buf;
+}
+
+/// Decodes a single peer, such as one entry of a `get_peers` response's `values`.
+pub fn decode_compact_peer(input: &[u8]) -> Result<SocketAddrV4, CompactDecodeError> {
+    if input.len() != COMPACT_PEER_LEN {
+        return Err(CompactDecodeError{});
+    }
+    let ip = Ipv4Addr::new(input[0], input[1], input[2], input[3]);
+    return Ok(SocketAddrV4::new(ip, u16::from_be_bytes([input[4], input[5]])));
+}
+
+pub fn encode_compact_peers(addrs: &[SocketAddrV4]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(addrs.len() * COMPACT_PEER_LEN);
+    for addr in addrs.iter() {
+        buf.extend_from_slice(&encode_compact_peer(addr));
+    }
+    return buf;
+}
+
+/// Decodes back-to-back peers, as in a tracker's compact `peers` string.
+pub fn decode_compact_peers(input: &[u8]) -> Result<Vec<SocketAddrV4>, CompactDecodeError> {
+    if !input.len().is_multiple_of(COMPACT_PEER_LEN) {
+        return Err(CompactDecodeError{});
+    }
+    input.chunks(COMPACT_PEER_LEN).map(decode_compact_peer).collect()
+}
+
+pub fn encode_compact_nodes(nodes: &[(NodeId, SocketAddrV4)]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(nodes.len() * COMPACT_NODE_LEN);
+    for (id, addr) in nodes.iter() {
+        buf.extend_from_slice(id);
+        buf.extend_from_slice(&encode_compact_peer(addr));
+    }
+    return buf;
+}
+
+/// Decodes back-to-back nodes, as in the `nodes` field of a `find_node` or
+/// `get_peers` response.
+pub fn decode_compact_nodes(input: &[u8]) -> Result<Vec<(NodeId, SocketAddrV4)>, CompactDecodeError> {
+    if !input.len().is_multiple_of(COMPACT_NODE_LEN) {
+        return Err(CompactDecodeError{});
+    }
+    let mut nodes = Vec::with_capacity(input.len() / COMPACT_NODE_LEN);
+    for chunk in input.chunks(COMPACT_NODE_LEN) {
+        let id = NodeId::try_from(&chunk[..20]).map_err(|_| CompactDecodeError{})?;
+        nodes.push((id, decode_compact_peer(&chunk[20..])?));
+    }
+    return Ok(nodes);
+}
+
+pub fn encode_compact_peer6(addr: &SocketAddrV6) -> [u8; COMPACT_PEER6_LEN] {
+    let mut buf = [0u8; COMPACT_PEER6_LEN];
+    buf[..16].copy_from_slice(&addr.ip().octets());
+    buf[16..].copy_from_slice(&addr.port().to_be_bytes());
+    return buf;
+}
+
+/// Decodes a single IPv6 peer. The flow info and scope ID aren't sent, so
+/// they come back as 0.
+pub fn decode_compact_peer6(input: &[u8]) -> Result<SocketAddrV6, CompactDecodeError> {
+    if input.len() != COMPACT_PEER6_LEN {
+        return Err(CompactDecodeError{});
+    }
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&input[..16]);
+    return Ok(SocketAddrV6::new(Ipv6Addr::from(octets), u16::from_be_bytes([input[16], input[17]]), 0, 0));
+}
+
+pub fn encode_compact_peers6(addrs: &[SocketAddrV6]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(addrs.len() * COMPACT_PEER6_LEN);
+    for addr in addrs.iter() {
+        buf.extend_from_slice(&encode_compact_peer6(addr));
+    }
+    return buf;
+}
+
+/// Decodes back-to-back IPv6 peers, as in a tracker's compact `peers6` string.
+pub fn decode_compact_peers6(input: &[u8]) -> Result<Vec<SocketAddrV6>, CompactDecodeError> {
+    if !input.len().is_multiple_of(COMPACT_PEER6_LEN) {
+        return Err(CompactDecodeError{});
+    }
+    input.chunks(COMPACT_PEER6_LEN).map(decode_compact_peer6).collect()
+}
+
+pub fn encode_compact_nodes6(nodes: &[(NodeId, SocketAddrV6)]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(nodes.len() * COMPACT_NODE6_LEN);
+    for (id, addr) in nodes.iter() {
+        buf.extend_from_slice(id);
+        buf.extend_from_slice(&encode_compact_peer6(addr));
+    }
+    return buf;
+}
+
+/// Decodes back-to-back IPv6 nodes, as in the `nodes6` field of a
+/// `find_node` or `get_peers` response.
+pub fn decode_compact_nodes6(input: &[u8]) -> Result<Vec<(NodeId, SocketAddrV6)>, CompactDecodeError> {
+    if !input.len().is_multiple_of(COMPACT_NODE6_LEN) {
+        return Err(CompactDecodeError{});
+    }
+    let mut nodes = Vec::with_capacity(input.len() / COMPACT_NODE6_LEN);
+    for chunk in input.chunks(COMPACT_NODE6_LEN) {
+        let id = NodeId::try_from(&chunk[..20]).map_err(|_| CompactDecodeError{})?;
+        nodes.push((id, decode_compact_peer6(&chunk[20..])?));
+    }
+    return Ok(nodes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_peers() {
+        let addr = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 7), 6881);
+        assert_eq!([10, 0, 0, 7, 0x1A, 0xE1], encode_compact_peer(&addr));
+        assert_eq!(Ok(addr), decode_compact_peer(&[10, 0, 0, 7, 0x1A, 0xE1]));
+
+        let addrs = vec![addr, SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 255), 65535)];
+        let encoded = encode_compact_peers(&addrs);
+        assert_eq!(&[10, 0, 0, 7, 0x1A, 0xE1, 192, 168, 1, 255, 0xFF, 0xFF][..], &encoded[..]);
+        assert_eq!(Ok(addrs), decode_compact_peers(&encoded));
+        assert_eq!(Ok(Vec::new()), decode_compact_peers(&[]));
+
+        assert_eq!(Err(CompactDecodeError{}), decode_compact_peer(&[10, 0, 0, 7, 0x1A]));
+        assert_eq!(Err(CompactDecodeError{}), decode_compact_peers(&encoded[..11]));
+    }
+
+    #[test]
+    fn test_compact_nodes() {
+        let nodes = vec![
+            (NodeId::random(), SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 7), 6881)),
+            (NodeId::random(), SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 1)),
+        ];
+        let encoded = encode_compact_nodes(&nodes);
+        assert_eq!(2 * COMPACT_NODE_LEN, encoded.len());
+        assert_eq!(&nodes[1].0[..], &encoded[26..46]);
+        assert_eq!(&[127, 0, 0, 1, 0, 1][..], &encoded[46..]);
+        assert_eq!(Ok(nodes), decode_compact_nodes(&encoded));
+        assert_eq!(Err(CompactDecodeError{}), decode_compact_nodes(&encoded[..27]));
+    }
+    #[test]
+    fn test_compact_peers6() {
+        let addr = SocketAddrV6::new("2001:db8::7".parse().unwrap(), 6881, 0, 0);
+        let encoded = b"\x20\x01\x0d\xb8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x07\x1a\xe1";
+        assert_eq!(encoded, &encode_compact_peer6(&addr));
+        assert_eq!(Ok(addr), decode_compact_peer6(encoded));
+
+        let addrs = vec![addr, SocketAddrV6::new(Ipv6Addr::LOCALHOST, 65535, 0, 0)];
+        let encoded = encode_compact_peers6(&addrs);
+        assert_eq!(2 * COMPACT_PEER6_LEN, encoded.len());
+        assert_eq!(Ok(addrs), decode_compact_peers6(&encoded));
+        assert_eq!(Ok(Vec::new()), decode_compact_peers6(&[]));
+
+        // a v4 peer isn't a v6 one
+        assert_eq!(Err(CompactDecodeError{}), decode_compact_peer6(&[10, 0, 0, 7, 0x1A, 0xE1]));
+        assert_eq!(Err(CompactDecodeError{}), decode_compact_peers6(&encoded[..19]));
+    }
+
+    #[test]
+    fn test_compact_nodes6() {
+        // a `nodes6` string with two nodes, written out by hand
+        let mut nodes6 = Vec::new();
+        nodes6.extend_from_slice(b"abcdefghij0123456789");
+        nodes6.extend_from_slice(b"\x20\x01\x0d\xb8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x1a\xe1");
+        nodes6.extend_from_slice(b"mnopqrstuvwxyz123456");
+        nodes6.extend_from_slice(b"\xfe\x80\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x01");
+        let mut first = [0u8; 20];
+        first.copy_from_slice(b"abcdefghij0123456789");
+        let mut second = [0u8; 20];
+        second.copy_from_slice(b"mnopqrstuvwxyz123456");
+        let expected = vec![
+            (NodeId(first), SocketAddrV6::new("2001:db8::1".parse().unwrap(), 6881, 0, 0)),
+            (NodeId(second), SocketAddrV6::new("fe80::2".parse().unwrap(), 1, 0, 0)),
+        ];
+        assert_eq!(Ok(expected.clone()), decode_compact_nodes6(&nodes6));
+        assert_eq!(nodes6, encode_compact_nodes6(&expected));
+        assert_eq!(2 * COMPACT_NODE6_LEN, nodes6.len());
+
+        assert_eq!(Err(CompactDecodeError{}), decode_compact_nodes6(&nodes6[..39]));
+        // IPv4 nodes are the wrong length
+        let v4 = encode_compact_nodes(&[(NodeId(first), SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1))]);
+        assert_eq!(Err(CompactDecodeError{}), decode_compact_nodes6(&v4));
+    }
+}
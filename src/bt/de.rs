@@ -0,0 +1,357 @@
+use std::{collections::HashMap, convert::TryInto, fmt, vec};
+
+use num_bigint::BigInt;
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess,
+    Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use super::{Bencoding, BencodingSerdeError};
+
+/// Converts a `Bencoding` into any `Deserialize` type.
+///
+/// This is the inverse of `to_bencoding`. Byte strings can also be read as
+/// sequences of `u8`, so `Vec<u8>` fields work without `serde_bytes`.
+pub fn from_bencoding<T: DeserializeOwned>(bencoding: Bencoding) -> Result<T, BencodingSerdeError> {
+    T::deserialize(bencoding)
+}
+
+impl de::Error for BencodingSerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        BencodingSerdeError::new(msg)
+    }
+}
+
+struct BencodingVisitor;
+
+impl<'de> Visitor<'de> for BencodingVisitor {
+    type Value = Bencoding;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an integer, byte string, list, or dictionary")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Bencoding, E> {
+        Ok(Bencoding::Int(v as i64))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Bencoding, E> {
+        Ok(Bencoding::Int(v))
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<Bencoding, E> {
+        Ok(Bencoding::from_bigint(BigInt::from(v)))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Bencoding, E> {
+        Ok(Bencoding::from_bigint(BigInt::from(v)))
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<Bencoding, E> {
+        Ok(Bencoding::from_bigint(BigInt::from(v)))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Bencoding, E> {
+        Ok(Bencoding::String(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Bencoding, E> {
+        Ok(Bencoding::String(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Bencoding, E> {
+        Ok(Bencoding::from_raw_string(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Bencoding, E> {
+        Ok(Bencoding::from_raw_string(v))
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Bencoding, D::Error> {
+        Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Bencoding, A::Error> {
+        let mut elems = Vec::new();
+        while let Some(elem) = seq.next_element()? {
+            elems.push(elem);
+        }
+        Ok(Bencoding::List(elems))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Bencoding, A::Error> {
+        let mut dict = HashMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            dict.insert(key, value);
+        }
+        Ok(Bencoding::from(dict))
+    }
+}
+
+impl<'de> Deserialize<'de> for Bencoding {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Bencoding, D::Error> {
+        deserializer.deserialize_any(BencodingVisitor)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, BencodingSerdeError> for Bencoding {
+    type Deserializer = Bencoding;
+    fn into_deserializer(self) -> Bencoding {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Bencoding {
+    type Error = BencodingSerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodingSerdeError> {
+        match self {
+            Bencoding::Int(n) => visitor.visit_i64(n),
+            Bencoding::Integer(n) => {
+                if let Ok(v) = TryInto::<i64>::try_into(&n) {
+                    return visitor.visit_i64(v);
+                }
+                if let Ok(v) = TryInto::<u64>::try_into(&n) {
+                    return visitor.visit_u64(v);
+                }
+                match TryInto::<i128>::try_into(&n) {
+                    Ok(v) => visitor.visit_i128(v),
+                    Err(_) => Err(BencodingSerdeError::new("integer out of range")),
+                }
+            },
+            Bencoding::String(s) => visitor.visit_string(s),
+            Bencoding::Bytes(b) => visitor.visit_byte_buf(b),
+            Bencoding::List(elems) => visitor.visit_seq(ListAccess{iter: elems.into_iter()}),
+            Bencoding::Dictionary(dict) => visitor.visit_map(DictionaryAccess{iter: dict.into_iter(), value: None}),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodingSerdeError> {
+        match self.as_i64_checked() {
+            Some(0) => visitor.visit_bool(false),
+            Some(1) => visitor.visit_bool(true),
+            _ => Err(BencodingSerdeError::new("expected integer 0 or 1 for a boolean")),
+        }
+    }
+
+    // a present value is always `Some`; absent dictionary entries are `None`
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodingSerdeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodingSerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self, _name: &'static str, visitor: V,
+    ) -> Result<V::Value, BencodingSerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self, _name: &'static str, visitor: V,
+    ) -> Result<V::Value, BencodingSerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodingSerdeError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodingSerdeError> {
+        match self {
+            Bencoding::String(s) => visitor.visit_byte_buf(s.into_bytes()),
+            Bencoding::Bytes(b) => visitor.visit_byte_buf(b),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodingSerdeError> {
+        // byte strings double as sequences of u8 so that plain `Vec<u8>` works
+        let bytes = match self {
+            Bencoding::String(s) => s.into_bytes(),
+            Bencoding::Bytes(b) => b,
+            other => return other.deserialize_any(visitor),
+        };
+        let elems: Vec<Bencoding> = bytes.into_iter()
+            .map(|b| Bencoding::Int(b as i64))
+            .collect();
+        visitor.visit_seq(ListAccess{iter: elems.into_iter()})
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self, _len: usize, visitor: V,
+    ) -> Result<V::Value, BencodingSerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self, _name: &'static str, _len: usize, visitor: V,
+    ) -> Result<V::Value, BencodingSerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self, _name: &'static str, _variants: &'static [&'static str], visitor: V,
+    ) -> Result<V::Value, BencodingSerdeError> {
+        match self {
+            Bencoding::String(variant) => {
+                visitor.visit_enum(IntoDeserializer::<BencodingSerdeError>::into_deserializer(variant))
+            },
+            Bencoding::Dictionary(dict) if dict.len() == 1 => {
+                let (variant, value) = dict.into_iter().next().unwrap();
+                visitor.visit_enum(EnumAccess{variant, value})
+            },
+            _ => Err(BencodingSerdeError::new("expected a string or single-entry dictionary for an enum")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        map struct identifier ignored_any
+    }
+}
+
+struct ListAccess {
+    iter: vec::IntoIter<Bencoding>,
+}
+
+impl<'de> SeqAccess<'de> for ListAccess {
+    type Error = BencodingSerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self, seed: T,
+    ) -> Result<Option<T::Value>, BencodingSerdeError> {
+        match self.iter.next() {
+            Some(elem) => seed.deserialize(elem).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct DictionaryAccess {
+    iter: vec::IntoIter<(String, Bencoding)>,
+    value: Option<Bencoding>,
+}
+
+impl<'de> MapAccess<'de> for DictionaryAccess {
+    type Error = BencodingSerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self, seed: K,
+    ) -> Result<Option<K::Value>, BencodingSerdeError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Bencoding::String(key)).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self, seed: V,
+    ) -> Result<V::Value, BencodingSerdeError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(BencodingSerdeError::new("next_value called before next_key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Bencoding,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = BencodingSerdeError;
+    type Variant = Bencoding;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self, seed: V,
+    ) -> Result<(V::Value, Bencoding), BencodingSerdeError> {
+        let variant = seed.deserialize(Bencoding::String(self.variant))?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Bencoding {
+    type Error = BencodingSerdeError;
+
+    fn unit_variant(self) -> Result<(), BencodingSerdeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self, seed: T,
+    ) -> Result<T::Value, BencodingSerdeError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self, _len: usize, visitor: V,
+    ) -> Result<V::Value, BencodingSerdeError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self, _fields: &'static [&'static str], visitor: V,
+    ) -> Result<V::Value, BencodingSerdeError> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Query {
+        Ping,
+        FindNode { target: Vec<u8> },
+        Announce(u16),
+    }
+
+    #[test]
+    fn test_from_bencoding_enum() {
+        let cases = vec![
+            ("4:Ping", Query::Ping),
+            ("d8:FindNoded6:target2:abee", Query::FindNode{target: b"ab".to_vec()}),
+            ("d8:Announcei6881ee", Query::Announce(6881)),
+        ];
+        for (encoded, query) in cases.into_iter() {
+            let bencoding = Bencoding::from_slice(encoded.as_bytes()).unwrap();
+            assert_eq!(query, from_bencoding(bencoding).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_from_bencoding_type_mismatch() {
+        let bencoding = Bencoding::from_slice(b"i70000e").unwrap();
+        assert!(from_bencoding::<u16>(bencoding.clone()).is_err());
+        assert!(from_bencoding::<String>(bencoding).is_err());
+    }
+
+    #[test]
+    fn test_from_bencoding_bool() {
+        assert_eq!(Ok(false), from_bencoding::<bool>(Bencoding::Int(0)));
+        assert_eq!(Ok(true), from_bencoding::<bool>(Bencoding::Int(1)));
+        // however the integer is held
+        assert_eq!(Ok(true), from_bencoding::<bool>(Bencoding::Integer(1.into())));
+        assert!(from_bencoding::<bool>(Bencoding::Int(2)).is_err());
+        assert!(from_bencoding::<bool>(Bencoding::from("1")).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! The map inside `Bencoding::Dictionary`, which keeps its entries in the
+//! order they were inserted so that a parsed value encodes back to the same
+//! bytes, even when the input's keys weren't sorted.
+
+use alloc::{string::String, vec, vec::Vec};
+use core::{iter::FromIterator, ops::Index, slice};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use super::Bencoding;
+
+/// A map from keys to values in insertion order.
+///
+/// Lookups binary search while the keys are in sorted order, as they are in
+/// anything canonically encoded, and scan otherwise. Equality ignores order,
+/// like a `HashMap`'s.
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    entries: Vec<(String, Bencoding)>,
+    /// Whether every key is greater than the one before it.
+    sorted: bool,
+}
+
+impl Dictionary {
+    pub fn new() -> Dictionary {
+        Dictionary{entries: Vec::new(), sorted: true}
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        if self.sorted {
+            return self.entries.binary_search_by(|(k, _)| k.as_str().cmp(key)).ok();
+        }
+        return self.entries.iter().position(|(k, _)| k == key);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Bencoding> {
+        self.position(key).map(|n| &self.entries[n].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Bencoding> {
+        let n = self.position(key)?;
+        return Some(&mut self.entries[n].1);
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Adds `key` at the end, or if it's already there, replaces its value in
+    /// place and returns the old one.
+    pub fn insert(&mut self, key: String, value: Bencoding) -> Option<Bencoding> {
+        if let Some(n) = self.position(&key) {
+            return Some(core::mem::replace(&mut self.entries[n].1, value));
+        }
+        if let Some((last, _)) = self.entries.last() {
+            self.sorted &= *last < key;
+        }
+        self.entries.push((key, value));
+        return None;
+    }
+
+    /// Takes `key` out, keeping the other entries in order.
+    pub fn remove(&mut self, key: &str) -> Option<Bencoding> {
+        let n = self.position(key)?;
+        return Some(self.entries.remove(n).1);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Bencoding)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut Bencoding)> {
+        self.entries.iter_mut().map(|(k, v)| (&*k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Bencoding> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    /// Whether the keys are in the sorted order canonical bencoding requires.
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+
+    /// Puts the entries in key order, as canonical bencoding requires.
+    pub fn sort_keys(&mut self) {
+        if !self.sorted {
+            self.entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            self.sorted = true;
+        }
+    }
+}
+
+impl Default for Dictionary {
+    fn default() -> Dictionary {
+        Dictionary::new()
+    }
+}
+
+impl PartialEq for Dictionary {
+    fn eq(&self, other: &Dictionary) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl Eq for Dictionary {}
+
+impl Index<&str> for Dictionary {
+    type Output = Bencoding;
+    fn index(&self, key: &str) -> &Bencoding {
+        self.get(key).expect("no entry for key in dictionary")
+    }
+}
+
+/// Entries with repeated keys replace the earlier value in its place.
+impl FromIterator<(String, Bencoding)> for Dictionary {
+    fn from_iter<I: IntoIterator<Item = (String, Bencoding)>>(iter: I) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.extend(iter);
+        return dict;
+    }
+}
+
+/// Everything is added before any repeated keys are merged, with one sort
+/// rather than a scan per entry, so that extending with unsorted keys doesn't
+/// take quadratic time.
+impl Extend<(String, Bencoding)> for Dictionary {
+    fn extend<I: IntoIterator<Item = (String, Bencoding)>>(&mut self, iter: I) {
+        let start = self.entries.len();
+        self.entries.extend(iter);
+        let added = &self.entries[start.saturating_sub(1)..];
+        if self.sorted && added.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+            return;
+        }
+
+        // runs of the same key, in the order they were added; the first of
+        // each keeps its place and takes the last one's value
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by(|&a, &b| self.entries[a].0.cmp(&self.entries[b].0));
+        let mut keep = vec![true; self.entries.len()];
+        let mut run = 0;
+        for n in 1..=order.len() {
+            if n < order.len() && self.entries[order[n]].0 == self.entries[order[run]].0 {
+                keep[order[n]] = false;
+                continue;
+            }
+            if n - 1 > run {
+                self.entries.swap(order[run], order[n - 1]);
+            }
+            run = n;
+        }
+        let mut keep = keep.into_iter();
+        self.entries.retain(|_| keep.next() == Some(true));
+        self.sorted = self.entries.windows(2).all(|pair| pair[0].0 < pair[1].0);
+    }
+}
+
+/// A `HashMap` has no order of its own, so its entries are sorted by key.
+#[cfg(feature = "std")]
+impl From<HashMap<String, Bencoding>> for Dictionary {
+    fn from(map: HashMap<String, Bencoding>) -> Dictionary {
+        let mut dict: Dictionary = map.into_iter().collect();
+        dict.sort_keys();
+        return dict;
+    }
+}
+
+impl IntoIterator for Dictionary {
+    type Item = (String, Bencoding);
+    type IntoIter = vec::IntoIter<(String, Bencoding)>;
+    fn into_iter(self) -> vec::IntoIter<(String, Bencoding)> {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Dictionary {
+    type Item = &'a (String, Bencoding);
+    type IntoIter = slice::Iter<'a, (String, Bencoding)>;
+    fn into_iter(self) -> slice::Iter<'a, (String, Bencoding)> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, string::ToString, vec};
+
+    use super::*;
+
+    #[test]
+    fn test_dictionary_order() {
+        let mut dict = Dictionary::new();
+        for key in ["b", "a", "c"].iter() {
+            dict.insert(key.to_string(), Bencoding::from(*key));
+        }
+        assert!(!dict.is_sorted());
+        assert_eq!(vec!["b", "a", "c"], dict.keys().map(|k| k.as_str()).collect::<Vec<&str>>());
+        assert_eq!(Some(Bencoding::from("a")), dict.insert("a".to_string(), Bencoding::from(1)));
+        assert_eq!(vec!["b", "a", "c"], dict.keys().map(|k| k.as_str()).collect::<Vec<&str>>());
+        assert_eq!(Bencoding::from(1), dict["a"]);
+        assert_eq!(Some(Bencoding::from("b")), dict.remove("b"));
+        assert_eq!(None, dict.get("b"));
+        assert_eq!(2, dict.len());
+
+        // order doesn't matter to equality
+        let mut sorted = dict.clone();
+        sorted.sort_keys();
+        assert!(sorted.is_sorted());
+        assert_eq!(vec!["a", "c"], sorted.keys().map(|k| k.as_str()).collect::<Vec<&str>>());
+        let reversed: Dictionary = dict.clone().into_iter().rev().collect();
+        assert_eq!(dict, reversed);
+        assert_eq!(dict, sorted);
+        assert_ne!(dict, Dictionary::new());
+
+        // keys added in order are looked up by binary search
+        let counted: Dictionary = (0..100).map(|n| (format!("{:03}", n), Bencoding::from(n))).collect();
+        assert!(counted.is_sorted());
+        assert_eq!(Some(&Bencoding::from(42)), counted.get("042"));
+        assert_eq!(None, counted.get("42"));
+    }
+
+    #[test]
+    fn test_dictionary_extend_unsorted() {
+        let mut dict: Dictionary = vec![("b", 1), ("a", 2), ("b", 3), ("c", 4), ("a", 5)].into_iter()
+            .map(|(k, v)| (k.to_string(), Bencoding::from(v)))
+            .collect();
+        assert_eq!(vec!["b", "a", "c"], dict.keys().map(|k| k.as_str()).collect::<Vec<&str>>());
+        assert_eq!(vec![3, 5, 4], dict.values().map(|v| v.as_i64_checked().unwrap()).collect::<Vec<i64>>());
+        dict.extend(vec![("c".to_string(), Bencoding::from(6)), ("0".to_string(), Bencoding::from(7))]);
+        assert_eq!(vec!["b", "a", "c", "0"], dict.keys().map(|k| k.as_str()).collect::<Vec<&str>>());
+        assert_eq!(Bencoding::from(6), dict["c"]);
+        assert!(!dict.is_sorted());
+
+        // merging leaves the rest sorted
+        let mut dict: Dictionary = vec![("a", 1), ("b", 2), ("a", 3)].into_iter()
+            .map(|(k, v)| (k.to_string(), Bencoding::from(v)))
+            .collect();
+        assert!(dict.is_sorted());
+        assert_eq!(Bencoding::from(3), dict["a"]);
+        dict.extend(vec![("c".to_string(), Bencoding::from(4))]);
+        assert!(dict.is_sorted());
+    }
+
+    #[test]
+    fn test_dictionary_parse_many_unsorted() {
+        // a scan per key would take minutes
+        let count = 100_000;
+        let mut input = b"d".to_vec();
+        for n in (0..count).rev() {
+            input.extend_from_slice(format!("6:{:06}i{}e", n, n).as_bytes());
+        }
+        input.extend_from_slice(b"6:000042i-1ee");
+        let parsed = Bencoding::from_slice(&input).unwrap();
+        let dict = parsed.as_dict().unwrap();
+        assert_eq!(count, dict.len());
+        assert!(!dict.is_sorted());
+        assert_eq!(Some("099999"), dict.keys().next().map(|k| k.as_str()));
+        assert_eq!(Some(&Bencoding::from(-1)), dict.get("000042"));
+        assert_eq!(Some(&Bencoding::from(7)), dict.get("000007"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Conversions between `Bencoding` and `serde_json::Value`.
+//!
+//! Bencoded strings that are valid UTF-8 become JSON strings. Byte strings
+//! that aren't (`Bencoding::Bytes`) become a single-entry object
+//! `{"$base64": "<standard base64>"}`, and integers that don't fit in an
+//! `i64` or `u64` become `{"$bigint": "<decimal digits>"}`. Dictionary keys
+//! that start with `$` get another one in front, so that no dictionary can be
+//! mistaken for either shape. Converting back recognizes exactly those two
+//! shapes and takes the extra `$` off again, so `Bencoding -> Value ->
+//! Bencoding` is lossless.
+
+use std::{collections::HashMap, convert::TryFrom, str::FromStr};
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde_json::{Map, Number, Value};
+
+use super::{Bencoding, BencodingSerdeError};
+
+const BYTES_KEY: &str = "$base64";
+const BIGINT_KEY: &str = "$bigint";
+
+fn tagged(key: &str, value: String) -> Value {
+    let mut map = Map::new();
+    map.insert(key.to_string(), Value::String(value));
+    Value::Object(map)
+}
+
+// `$key` is escaped as `$$key`, which is all that tagged objects are told
+// apart from dictionaries by
+fn escape_key(key: String) -> String {
+    if key.starts_with('$') {
+        return format!("${}", key);
+    }
+    return key;
+}
+
+fn unescape_key(key: String) -> String {
+    if key.starts_with("$$") {
+        return key[1..].to_string();
+    }
+    return key;
+}
+
+impl From<Bencoding> for Value {
+    fn from(bencoding: Bencoding) -> Value {
+        match bencoding {
+            Bencoding::Int(n) => Value::Number(Number::from(n)),
+            Bencoding::Integer(n) => match n.to_u64() {
+                Some(v) => Value::Number(Number::from(v)),
+                None => tagged(BIGINT_KEY, n.to_string()),
+            },
+            Bencoding::String(s) => Value::String(s),
+            Bencoding::Bytes(b) => tagged(BYTES_KEY, base64::encode(&b)),
+            Bencoding::List(elems) => Value::Array(elems.into_iter().map(Value::from).collect()),
+            Bencoding::Dictionary(dict) => Value::Object(dict.into_iter()
+                .map(|(k, v)| (escape_key(k), Value::from(v)))
+                .collect()),
+        }
+    }
+}
+
+impl TryFrom<Value> for Bencoding {
+    type Error = BencodingSerdeError;
+
+    fn try_from(value: Value) -> Result<Bencoding, BencodingSerdeError> {
+        match value {
+            Value::Null => Err(BencodingSerdeError::new("bencoding has no null type")),
+            Value::Bool(b) => Ok(Bencoding::Int(b as i64)),
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(v), _) => Ok(Bencoding::Int(v)),
+                (None, Some(v)) => Ok(Bencoding::Integer(BigInt::from(v))),
+                _ => Err(BencodingSerdeError::new("bencoding has no floating point type")),
+            },
+            Value::String(s) => Ok(Bencoding::String(s)),
+            Value::Array(elems) => Ok(Bencoding::List(elems.into_iter()
+                .map(Bencoding::try_from)
+                .collect::<Result<Vec<Bencoding>, BencodingSerdeError>>()?)),
+            Value::Object(map) => {
+                if map.len() == 1 {
+                    match map.iter().next() {
+                        Some((k, Value::String(s))) if k == BYTES_KEY => {
+                            return match base64::decode(s) {
+                                Ok(b) => Ok(Bencoding::from_raw_string(b)),
+                                Err(e) => Err(BencodingSerdeError::new(e)),
+                            };
+                        },
+                        Some((k, Value::String(s))) if k == BIGINT_KEY => {
+                            return match BigInt::from_str(s) {
+                                Ok(n) => Ok(Bencoding::from_bigint(n)),
+                                Err(e) => Err(BencodingSerdeError::new(e)),
+                            };
+                        },
+                        _ => (),
+                    }
+                }
+                let mut dict = HashMap::new();
+                for (k, v) in map.into_iter() {
+                    dict.insert(unescape_key(k), Bencoding::try_from(v)?);
+                }
+                Ok(Bencoding::from(dict))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_bencoding_to_json() {
+        let bencoding = Bencoding::from_slice(
+            b"d4:infod6:lengthi42e6:pieces3:\xFF\x00\xFEe4:sizei99999999999999999999e4:tagsl1:aee",
+        ).unwrap();
+        let expected = json!({
+            "info": {"length": 42, "pieces": {"$base64": "/wD+"}},
+            "size": {"$bigint": "99999999999999999999"},
+            "tags": ["a"],
+        });
+        let value = Value::from(bencoding.clone());
+        assert_eq!(expected, value);
+        assert_eq!(Ok(bencoding), Bencoding::try_from(value));
+    }
+
+    #[test]
+    fn test_bencoding_to_json_dollar_keys() {
+        // dictionaries that look like tags, and keys that already look escaped
+        let cases = vec![
+            (&b"d7:$bigint1:5e"[..], json!({"$$bigint": "5"})),
+            (b"d7:$base644:AAAAe", json!({"$$base64": "AAAA"})),
+            (b"d3:$$xi1e1:$i2ee", json!({"$$$x": 1, "$$": 2})),
+            (b"d1:ad7:$bigint1:5ee", json!({"a": {"$$bigint": "5"}})),
+        ];
+        for (input, expected) in cases.into_iter() {
+            let bencoding = Bencoding::from_slice(input).unwrap();
+            let value = Value::from(bencoding.clone());
+            assert_eq!(expected, value);
+            assert_eq!(Ok(bencoding), Bencoding::try_from(value));
+        }
+        // a lone `$` in JSON written by hand is kept as it is
+        assert_eq!(Ok(Bencoding::from_slice(b"d4:$fooi1ee").unwrap()), Bencoding::try_from(json!({"$foo": 1})));
+    }
+
+    #[test]
+    fn test_json_to_bencoding_errors() {
+        assert!(Bencoding::try_from(json!(null)).is_err());
+        assert!(Bencoding::try_from(json!([1.5])).is_err());
+        assert!(Bencoding::try_from(json!({"$base64": "not base64!"})).is_err());
+        assert_eq!(Ok(Bencoding::Int(1)), Bencoding::try_from(json!(true)));
+    }
+}
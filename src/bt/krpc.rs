@@ -0,0 +1,546 @@
+//! KRPC, the bencoded query/response protocol the DHT speaks over UDP (BEP 5).
+//!
+//! Every message is a dictionary with a transaction ID `t` and a type `y`:
+//! `q` for queries, which name their method in `q` and carry arguments in `a`,
+//! `r` for responses, which carry their values in `r`, and `e` for errors.
+//!
+//! Over IPv6 (BEP 32), responses carry nodes in `nodes6` rather than `nodes`,
+//! and `values` holds 18-byte IPv6 peers; a dual-stack node may send both.
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    net::{SocketAddr, SocketAddrV4, SocketAddrV6},
+};
+
+use crate::bencode;
+use super::{
+    decode_compact_nodes, decode_compact_nodes6, decode_compact_peer, decode_compact_peer6, encode_compact_nodes,
+    encode_compact_nodes6, encode_compact_peer, encode_compact_peer6, Bencoding, BencodingParseError, Dictionary,
+    NodeId,
+};
+
+// BEP 5's error codes
+pub const ERROR_GENERIC: i64 = 201;
+pub const ERROR_SERVER: i64 = 202;
+pub const ERROR_PROTOCOL: i64 = 203;
+pub const ERROR_METHOD_UNKNOWN: i64 = 204;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum KrpcError {
+    Bencoding(BencodingParseError),
+    MissingField(&'static str),
+    InvalidField(&'static str),
+    /// A query named a method other than the four in BEP 5.
+    UnknownMethod(String),
+}
+
+impl fmt::Display for KrpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KrpcError::Bencoding(e) => write!(f, "{}", e),
+            KrpcError::MissingField(field) => write!(f, "KRPC message is missing `{}`", field),
+            KrpcError::InvalidField(field) => write!(f, "KRPC message has an invalid `{}`", field),
+            KrpcError::UnknownMethod(method) => write!(f, "KRPC query has unknown method {:?}", method),
+        }
+    }
+}
+
+impl std::error::Error for KrpcError {}
+
+impl From<BencodingParseError> for KrpcError {
+    fn from(e: BencodingParseError) -> KrpcError {
+        KrpcError::Bencoding(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum KrpcQuery {
+    Ping { id: NodeId },
+    FindNode { id: NodeId, target: NodeId },
+    GetPeers { id: NodeId, info_hash: NodeId },
+    AnnouncePeer {
+        id: NodeId,
+        info_hash: NodeId,
+        port: u16,
+        /// The token from the queried node's earlier `get_peers` response.
+        token: Vec<u8>,
+        /// Whether the queried node should ignore `port` and use the port the
+        /// query came from, for peers behind NAT.
+        implied_port: bool,
+    },
+}
+
+impl KrpcQuery {
+    /// The method name that goes in the message's `q`.
+    pub fn method(&self) -> &'static str {
+        match self {
+            KrpcQuery::Ping{..} => "ping",
+            KrpcQuery::FindNode{..} => "find_node",
+            KrpcQuery::GetPeers{..} => "get_peers",
+            KrpcQuery::AnnouncePeer{..} => "announce_peer",
+        }
+    }
+
+    /// The ID of the node sending the query.
+    pub fn id(&self) -> &NodeId {
+        match self {
+            KrpcQuery::Ping{id} => id,
+            KrpcQuery::FindNode{id, ..} => id,
+            KrpcQuery::GetPeers{id, ..} => id,
+            KrpcQuery::AnnouncePeer{id, ..} => id,
+        }
+    }
+}
+
+/// The values in a response. Which are present depends on the query it
+/// answers, which only the transaction ID tells.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct KrpcResponse {
+    /// The ID of the node responding.
+    pub id: NodeId,
+    /// The closest nodes the responder knows of, for `find_node` and `get_peers`.
+    pub nodes: Vec<(NodeId, SocketAddrV4)>,
+    /// The same over IPv6, from `nodes6`.
+    pub nodes6: Vec<(NodeId, SocketAddrV6)>,
+    /// Peers for the info hash, for `get_peers`.
+    pub values: Vec<SocketAddrV4>,
+    /// IPv6 peers for the info hash, which share the `values` list.
+    pub values6: Vec<SocketAddrV6>,
+    /// The token to use in a later `announce_peer`, for `get_peers`.
+    pub token: Option<Vec<u8>>,
+}
+
+impl KrpcResponse {
+    /// A response with nothing but the responder's ID, as for `ping` and `announce_peer`.
+    pub fn new(id: NodeId) -> KrpcResponse {
+        KrpcResponse{id, nodes: Vec::new(), nodes6: Vec::new(), values: Vec::new(), values6: Vec::new(), token: None}
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum KrpcBody {
+    Query(KrpcQuery),
+    Response(KrpcResponse),
+    Error { code: i64, message: String },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct KrpcMessage {
+    /// Chosen by the querying node and echoed back in the response, to match
+    /// the two up. Usually two bytes.
+    pub transaction_id: Vec<u8>,
+    pub body: KrpcBody,
+}
+
+/// A fresh two-byte transaction ID for a query.
+pub fn random_transaction_id() -> Vec<u8> {
+    rand::random::<[u8; 2]>().to_vec()
+}
+
+fn get<'a>(dict: &'a Dictionary, key: &'static str) -> Result<&'a Bencoding, KrpcError> {
+    dict.get(key).ok_or(KrpcError::MissingField(key))
+}
+
+fn get_bytes<'a>(dict: &'a Dictionary, key: &'static str) -> Result<&'a [u8], KrpcError> {
+    get(dict, key)?.as_bytes().ok_or(KrpcError::InvalidField(key))
+}
+
+fn get_node_id(dict: &Dictionary, key: &'static str) -> Result<NodeId, KrpcError> {
+    return NodeId::try_from(get_bytes(dict, key)?).map_err(|_| KrpcError::InvalidField(key));
+}
+
+fn parse_query(method: &[u8], args: &Dictionary) -> Result<KrpcQuery, KrpcError> {
+    let id = get_node_id(args, "id")?;
+    let query = match method {
+        b"ping" => KrpcQuery::Ping{id},
+        b"find_node" => KrpcQuery::FindNode{id, target: get_node_id(args, "target")?},
+        b"get_peers" => KrpcQuery::GetPeers{id, info_hash: get_node_id(args, "info_hash")?},
+        b"announce_peer" => {
+            let port = match get(args, "port")? {
+                Bencoding::Int(n) if 0 < *n && *n <= u16::MAX as i64 => *n as u16,
+                _ => return Err(KrpcError::InvalidField("port")),
+            };
+            let implied_port = match args.get("implied_port") {
+                None | Some(Bencoding::Int(0)) => false,
+                Some(Bencoding::Int(1)) => true,
+                Some(_) => return Err(KrpcError::InvalidField("implied_port")),
+            };
+            KrpcQuery::AnnouncePeer{
+                id,
+                info_hash: get_node_id(args, "info_hash")?,
+                port,
+                token: get_bytes(args, "token")?.to_vec(),
+                implied_port,
+            }
+        },
+        _ => return Err(KrpcError::UnknownMethod(String::from_utf8_lossy(method).into_owned())),
+    };
+    return Ok(query);
+}
+
+fn decode_nodes(values: &Dictionary) -> Result<Vec<(NodeId, SocketAddrV4)>, KrpcError> {
+    decode_compact_nodes(get_bytes(values, "nodes")?).map_err(|_| KrpcError::InvalidField("nodes"))
+}
+
+fn decode_nodes6(values: &Dictionary) -> Result<Vec<(NodeId, SocketAddrV6)>, KrpcError> {
+    decode_compact_nodes6(get_bytes(values, "nodes6")?).map_err(|_| KrpcError::InvalidField("nodes6"))
+}
+
+// the two kinds of peer can share the list, told apart by their lengths
+fn decode_peers(values: &Dictionary) -> Result<(Vec<SocketAddrV4>, Vec<SocketAddrV6>), KrpcError> {
+    let peers = get(values, "values")?.as_list().ok_or(KrpcError::InvalidField("values"))?;
+    let (mut v4, mut v6) = (Vec::new(), Vec::new());
+    for peer in peers.iter() {
+        let peer = peer.as_bytes().ok_or(KrpcError::InvalidField("values"))?;
+        match decode_compact_peer(peer) {
+            Ok(addr) => v4.push(addr),
+            Err(_) => v6.push(decode_compact_peer6(peer).map_err(|_| KrpcError::InvalidField("values"))?),
+        }
+    }
+    return Ok((v4, v6));
+}
+
+fn parse_response(values: &Dictionary) -> Result<KrpcResponse, KrpcError> {
+    let mut response = KrpcResponse::new(get_node_id(values, "id")?);
+    if values.contains_key("nodes") {
+        response.nodes = decode_nodes(values)?;
+    }
+    if values.contains_key("nodes6") {
+        response.nodes6 = decode_nodes6(values)?;
+    }
+    if values.contains_key("values") {
+        let (v4, v6) = decode_peers(values)?;
+        response.values = v4;
+        response.values6 = v6;
+    }
+    if values.contains_key("token") {
+        response.token = Some(get_bytes(values, "token")?.to_vec());
+    }
+    return Ok(response);
+}
+
+/// What a `get_peers` response has to offer: peers for the info hash, or
+/// failing that, nodes closer to it to ask next. Either can be a mix of IPv4
+/// and IPv6, IPv4 first.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GetPeersResult {
+    Peers(Vec<SocketAddr>),
+    Nodes(Vec<(NodeId, SocketAddr)>),
+}
+
+/// Decodes the `r` dictionary of a `get_peers` response. Peers win over
+/// nodes if the responder sent both, since they're what the query was after.
+pub fn decode_get_peers(values: &Dictionary) -> Result<GetPeersResult, KrpcError> {
+    if values.contains_key("values") {
+        let (v4, v6) = decode_peers(values)?;
+        let peers = v4.into_iter().map(SocketAddr::V4).chain(v6.into_iter().map(SocketAddr::V6));
+        return Ok(GetPeersResult::Peers(peers.collect()));
+    }
+    // an IPv6 node may send only `nodes6`, but there has to be one or the other
+    let mut nodes: Vec<(NodeId, SocketAddr)> = Vec::new();
+    if values.contains_key("nodes") || !values.contains_key("nodes6") {
+        nodes.extend(decode_nodes(values)?.into_iter().map(|(id, addr)| (id, SocketAddr::V4(addr))));
+    }
+    if values.contains_key("nodes6") {
+        nodes.extend(decode_nodes6(values)?.into_iter().map(|(id, addr)| (id, SocketAddr::V6(addr))));
+    }
+    return Ok(GetPeersResult::Nodes(nodes));
+}
+
+fn parse_error(error: &Bencoding) -> Result<KrpcBody, KrpcError> {
+    match error.as_list() {
+        Some([Bencoding::Int(code), message]) => match message.as_bytes() {
+            Some(message) => Ok(KrpcBody::Error{code: *code, message: String::from_utf8_lossy(message).into_owned()}),
+            None => Err(KrpcError::InvalidField("e")),
+        },
+        _ => Err(KrpcError::InvalidField("e")),
+    }
+}
+
+impl KrpcMessage {
+    pub fn ping(transaction_id: &[u8], id: &NodeId) -> KrpcMessage {
+        KrpcMessage::query(transaction_id, KrpcQuery::Ping{id: id.clone()})
+    }
+
+    pub fn find_node(transaction_id: &[u8], id: &NodeId, target: &NodeId) -> KrpcMessage {
+        KrpcMessage::query(transaction_id, KrpcQuery::FindNode{id: id.clone(), target: target.clone()})
+    }
+
+    pub fn get_peers(transaction_id: &[u8], id: &NodeId, info_hash: &NodeId) -> KrpcMessage {
+        KrpcMessage::query(transaction_id, KrpcQuery::GetPeers{id: id.clone(), info_hash: info_hash.clone()})
+    }
+
+    pub fn announce_peer(
+        transaction_id: &[u8], id: &NodeId, info_hash: &NodeId, port: u16, token: &[u8], implied_port: bool,
+    ) -> KrpcMessage {
+        KrpcMessage::query(transaction_id, KrpcQuery::AnnouncePeer{
+            id: id.clone(),
+            info_hash: info_hash.clone(),
+            port,
+            token: token.to_vec(),
+            implied_port,
+        })
+    }
+
+    pub fn query(transaction_id: &[u8], query: KrpcQuery) -> KrpcMessage {
+        KrpcMessage{transaction_id: transaction_id.to_vec(), body: KrpcBody::Query(query)}
+    }
+
+    pub fn response(transaction_id: &[u8], response: KrpcResponse) -> KrpcMessage {
+        KrpcMessage{transaction_id: transaction_id.to_vec(), body: KrpcBody::Response(response)}
+    }
+
+    pub fn error(transaction_id: &[u8], code: i64, message: &str) -> KrpcMessage {
+        KrpcMessage{
+            transaction_id: transaction_id.to_vec(),
+            body: KrpcBody::Error{code, message: message.to_string()},
+        }
+    }
+
+    pub fn from_slice(input: &[u8]) -> Result<KrpcMessage, KrpcError> {
+        KrpcMessage::from_bencoding(&Bencoding::from_slice(input)?)
+    }
+
+    pub fn from_bencoding(bencoding: &Bencoding) -> Result<KrpcMessage, KrpcError> {
+        let root = bencoding.as_dict().ok_or(KrpcError::InvalidField("message"))?;
+        let transaction_id = get_bytes(root, "t")?.to_vec();
+        let body = match get_bytes(root, "y")? {
+            b"q" => {
+                let args = get(root, "a")?.as_dict().ok_or(KrpcError::InvalidField("a"))?;
+                KrpcBody::Query(parse_query(get_bytes(root, "q")?, args)?)
+            },
+            b"r" => {
+                let values = get(root, "r")?.as_dict().ok_or(KrpcError::InvalidField("r"))?;
+                KrpcBody::Response(parse_response(values)?)
+            },
+            b"e" => parse_error(get(root, "e")?)?,
+            _ => return Err(KrpcError::InvalidField("y")),
+        };
+        return Ok(KrpcMessage{transaction_id, body});
+    }
+
+    pub fn to_bencoding(&self) -> Bencoding {
+        let t = self.transaction_id.clone();
+        match &self.body {
+            KrpcBody::Query(query) => {
+                let id = query.id().to_vec();
+                let args = match query {
+                    KrpcQuery::Ping{..} => bencode!({"id" => id}),
+                    KrpcQuery::FindNode{target, ..} => bencode!({"id" => id, "target" => target.to_vec()}),
+                    KrpcQuery::GetPeers{info_hash, ..} => bencode!({"id" => id, "info_hash" => info_hash.to_vec()}),
+                    KrpcQuery::AnnouncePeer{info_hash, port, token, implied_port, ..} => bencode!({
+                        "id" => id,
+                        "info_hash" => info_hash.to_vec(),
+                        "port" => *port as i64,
+                        "token" => token.clone(),
+                        "implied_port" => *implied_port as i64,
+                    }),
+                };
+                bencode!({"t" => t, "y" => "q", "q" => query.method(), "a" => args})
+            },
+            KrpcBody::Response(response) => {
+                let mut values = HashMap::new();
+                values.insert("id".to_string(), Bencoding::from(response.id.to_vec()));
+                if !response.nodes.is_empty() {
+                    values.insert("nodes".to_string(), Bencoding::from(encode_compact_nodes(&response.nodes)));
+                }
+                if !response.nodes6.is_empty() {
+                    values.insert("nodes6".to_string(), Bencoding::from(encode_compact_nodes6(&response.nodes6)));
+                }
+                if !response.values.is_empty() || !response.values6.is_empty() {
+                    let peers = response.values.iter()
+                        .map(|peer| Bencoding::from(encode_compact_peer(peer).to_vec()))
+                        .chain(response.values6.iter().map(|peer| Bencoding::from(encode_compact_peer6(peer).to_vec())))
+                        .collect::<Vec<Bencoding>>();
+                    values.insert("values".to_string(), Bencoding::from(peers));
+                }
+                if let Some(token) = &response.token {
+                    values.insert("token".to_string(), Bencoding::from(token.clone()));
+                }
+                bencode!({"t" => t, "y" => "r", "r" => Bencoding::from(values)})
+            },
+            KrpcBody::Error{code, message} => {
+                bencode!({"t" => t, "y" => "e", "e" => [*code, message.as_str()]})
+            },
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.to_bencoding().encode()
+    }
+
+    /// Like `encode`, but appends to `buf`; see `Bencoding::encode_into`.
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        self.to_bencoding().encode_into(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn id(s: &str) -> NodeId {
+        let mut id = [0u8; 20];
+        id.copy_from_slice(s.as_bytes());
+        NodeId(id)
+    }
+
+    // the examples from BEP 5, which happen to be in canonical key order
+    #[test]
+    fn test_krpc_queries() {
+        let cases = vec![
+            (
+                KrpcMessage::ping(b"aa", &id("abcdefghij0123456789")),
+                &b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe"[..],
+            ),
+            (
+                KrpcMessage::find_node(b"aa", &id("abcdefghij0123456789"), &id("mnopqrstuvwxyz123456")),
+                &b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q9:find_node1:t2:aa1:y1:qe"[..],
+            ),
+            (
+                KrpcMessage::get_peers(b"aa", &id("abcdefghij0123456789"), &id("mnopqrstuvwxyz123456")),
+                &b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe"[..],
+            ),
+            (
+                KrpcMessage::announce_peer(
+                    b"aa", &id("abcdefghij0123456789"), &id("mnopqrstuvwxyz123456"), 6881, b"aoeusnth", true,
+                ),
+                &b"d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe"[..],
+            ),
+        ];
+        for (message, encoded) in cases.into_iter() {
+            assert_eq!(encoded, &message.encode()[..]);
+            assert_eq!(Ok(message), KrpcMessage::from_slice(encoded));
+        }
+
+        // implied_port is optional and off by default, but always written out
+        let message = KrpcMessage::from_slice(
+            b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe",
+        ).unwrap();
+        match message.body {
+            KrpcBody::Query(KrpcQuery::AnnouncePeer{implied_port, port, ..}) => assert!(!implied_port && port == 6881),
+            other => panic!("expected announce_peer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_krpc_responses() {
+        let ping = KrpcMessage::response(b"aa", KrpcResponse::new(id("mnopqrstuvwxyz123456")));
+        let encoded = &b"d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re"[..];
+        assert_eq!(encoded, &ping.encode()[..]);
+        assert_eq!(Ok(ping), KrpcMessage::from_slice(encoded));
+
+        let encoded = &b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:axje.u6:idhtnmee1:t2:aa1:y1:re"[..];
+        let get_peers = KrpcMessage::response(b"aa", KrpcResponse{
+            values: vec![
+                SocketAddrV4::new(Ipv4Addr::new(b'a', b'x', b'j', b'e'), u16::from_be_bytes([b'.', b'u'])),
+                SocketAddrV4::new(Ipv4Addr::new(b'i', b'd', b'h', b't'), u16::from_be_bytes([b'n', b'm'])),
+            ],
+            token: Some(b"aoeusnth".to_vec()),
+            ..KrpcResponse::new(id("abcdefghij0123456789"))
+        });
+        assert_eq!(encoded, &get_peers.encode()[..]);
+        assert_eq!(Ok(get_peers), KrpcMessage::from_slice(encoded));
+
+        let find_node = KrpcMessage::response(b"\x00\x01", KrpcResponse{
+            nodes: vec![
+                (NodeId::random(), SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 7), 6881)),
+                (NodeId::random(), SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 1), 1)),
+            ],
+            ..KrpcResponse::new(NodeId::random())
+        });
+        assert_eq!(Ok(find_node.clone()), KrpcMessage::from_slice(&find_node.encode()));
+
+        // BEP 32's IPv6 nodes and peers, alongside IPv4 ones from a dual-stack node
+        let v6 = |addr: &str, port| SocketAddrV6::new(addr.parse().unwrap(), port, 0, 0);
+        let dual_stack = KrpcMessage::response(b"\x00\x02", KrpcResponse{
+            nodes: vec![(NodeId::random(), SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 7), 6881))],
+            nodes6: vec![(NodeId::random(), v6("2001:db8::1", 6881)), (NodeId::random(), v6("2001:db8::2", 1))],
+            values: vec![SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 1), 1)],
+            values6: vec![v6("2001:db8::3", 6882)],
+            token: Some(b"aoeusnth".to_vec()),
+            ..KrpcResponse::new(NodeId::random())
+        });
+        assert_eq!(Ok(dual_stack.clone()), KrpcMessage::from_slice(&dual_stack.encode()));
+    }
+
+    #[test]
+    fn test_decode_get_peers() {
+        let r = |input: &[u8]| Bencoding::from_slice(input).unwrap().as_dict().unwrap().clone();
+
+        let peers = r(b"d2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:axje.u6:idhtnmee");
+        let mut expected = vec![
+            SocketAddr::new(Ipv4Addr::new(b'a', b'x', b'j', b'e').into(), u16::from_be_bytes([b'.', b'u'])),
+            SocketAddr::new(Ipv4Addr::new(b'i', b'd', b'h', b't').into(), u16::from_be_bytes([b'n', b'm'])),
+        ];
+        assert_eq!(Ok(GetPeersResult::Peers(expected.clone())), decode_get_peers(&peers));
+
+        // an IPv6 peer among them comes after the IPv4 ones
+        let v6 = SocketAddrV6::new("2001:db8::1".parse().unwrap(), 6881, 0, 0);
+        let mut mixed = peers.clone();
+        if let Some(Bencoding::List(values)) = mixed.get_mut("values") {
+            values.insert(0, Bencoding::from(encode_compact_peer6(&v6).to_vec()));
+        }
+        expected.push(SocketAddr::V6(v6));
+        assert_eq!(Ok(GetPeersResult::Peers(expected)), decode_get_peers(&mixed));
+
+        let closer = vec![
+            (NodeId::random(), SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 7), 6881)),
+            (NodeId::random(), SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 1), 1)),
+        ];
+        let closer6 = vec![(NodeId::random(), v6)];
+        let mut nodes = r(b"d2:id20:abcdefghij01234567895:token8:aoeusnthe");
+        nodes.insert("nodes6".to_string(), Bencoding::from(encode_compact_nodes6(&closer6)));
+        let expected6 = vec![(closer6[0].0.clone(), SocketAddr::V6(v6))];
+        assert_eq!(Ok(GetPeersResult::Nodes(expected6.clone())), decode_get_peers(&nodes));
+        nodes.insert("nodes".to_string(), Bencoding::from(encode_compact_nodes(&closer)));
+        let mut expected: Vec<(NodeId, SocketAddr)> = closer.into_iter()
+            .map(|(id, addr)| (id, SocketAddr::V4(addr)))
+            .collect();
+        expected.extend(expected6);
+        assert_eq!(Ok(GetPeersResult::Nodes(expected)), decode_get_peers(&nodes));
+
+        let failure_cases = vec![
+            (&b"d2:id20:abcdefghij0123456789e"[..], KrpcError::MissingField("nodes")),
+            (&b"d5:nodes3:abce"[..], KrpcError::InvalidField("nodes")),
+            (&b"d6:valuesl3:abcee"[..], KrpcError::InvalidField("values")),
+            (&b"d6:values6:axje.ue"[..], KrpcError::InvalidField("values")),
+            (&b"d5:nodes0:6:nodes63:abce"[..], KrpcError::InvalidField("nodes6")),
+        ];
+        for (input, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), decode_get_peers(&r(input)));
+        }
+    }
+
+    #[test]
+    fn test_krpc_errors() {
+        let encoded = &b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee"[..];
+        let error = KrpcMessage::error(b"aa", ERROR_GENERIC, "A Generic Error Ocurred");
+        assert_eq!(encoded, &error.encode()[..]);
+        assert_eq!(Ok(error), KrpcMessage::from_slice(encoded));
+
+        let failure_cases = vec![
+            (&b"le"[..], KrpcError::InvalidField("message")),
+            (&b"d1:y1:qe"[..], KrpcError::MissingField("t")),
+            (&b"d1:t2:aa1:y1:xe"[..], KrpcError::InvalidField("y")),
+            (&b"d1:ad2:id20:abcdefghij0123456789e1:q4:pong1:t2:aa1:y1:qe"[..], KrpcError::UnknownMethod("pong".to_string())),
+            (&b"d1:ad2:id3:abce1:q4:ping1:t2:aa1:y1:qe"[..], KrpcError::InvalidField("id")),
+            (&b"d1:ad2:id20:abcdefghij0123456789e1:q9:find_node1:t2:aa1:y1:qe"[..], KrpcError::MissingField("target")),
+            (&b"d1:rd2:id20:abcdefghij01234567895:nodes3:abce1:t2:aa1:y1:re"[..], KrpcError::InvalidField("nodes")),
+            (&b"d1:rd2:id20:abcdefghij01234567896:valuesl3:abcee1:t2:aa1:y1:re"[..], KrpcError::InvalidField("values")),
+            (&b"d1:eli201ee1:t2:aa1:y1:ee"[..], KrpcError::InvalidField("e")),
+            (
+                &b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz1234564:porti0e5:token1:xe1:q13:announce_peer1:t2:aa1:y1:qe"[..],
+                KrpcError::InvalidField("port"),
+            ),
+        ];
+        for (input, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), KrpcMessage::from_slice(input));
+        }
+        assert_eq!(2, random_transaction_id().len());
+    }
+}
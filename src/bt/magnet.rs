@@ -0,0 +1,226 @@
+//! Magnet links (BEP 9), which identify a torrent by its info-hash alone.
+//!
+//! Only the `xt`, `dn`, and `tr` parameters are understood; anything else in
+//! the query string is ignored.
+
+use std::{fmt, str::FromStr};
+
+use super::{MetaInfo, NodeId};
+
+const MAGNET_PREFIX: &str = "magnet:?";
+const BTIH_PREFIX: &str = "urn:btih:";
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MagnetError {
+    NotMagnet,
+    MissingField(&'static str),
+    InvalidField(&'static str),
+}
+
+impl fmt::Display for MagnetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MagnetError::NotMagnet => write!(f, "not a magnet link"),
+            MagnetError::MissingField(field) => write!(f, "magnet link is missing `{}`", field),
+            MagnetError::InvalidField(field) => write!(f, "magnet link has an invalid `{}`", field),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Magnet {
+    pub info_hash: NodeId,
+    pub display_name: Option<String>,
+    pub trackers: Vec<String>,
+}
+
+impl From<NodeId> for Magnet {
+    fn from(info_hash: NodeId) -> Magnet {
+        Magnet{info_hash, display_name: None, trackers: Vec::new()}
+    }
+}
+
+impl Magnet {
+    /// Renders the link with a hex `btih`, which is what most clients emit.
+    pub fn magnet_link(&self) -> String {
+        let mut link = format!("{}xt={}{:x}", MAGNET_PREFIX, BTIH_PREFIX, self.info_hash);
+        if let Some(name) = &self.display_name {
+            link.push_str("&dn=");
+            link.push_str(&percent_encode(name));
+        }
+        for tracker in self.trackers.iter() {
+            link.push_str("&tr=");
+            link.push_str(&percent_encode(tracker));
+        }
+        return link;
+    }
+}
+
+impl MetaInfo {
+    /// A magnet link naming every tracker from every tier of `announce_list`.
+    pub fn magnet_link(&self) -> String {
+        Magnet{
+            info_hash: self.info_hash(),
+            display_name: Some(self.info_name.clone()),
+            trackers: self.announce_list().into_iter().flatten().collect(),
+        }.magnet_link()
+    }
+}
+
+impl FromStr for Magnet {
+    type Err = MagnetError;
+
+    fn from_str(s: &str) -> Result<Magnet, MagnetError> {
+        let params = s.strip_prefix(MAGNET_PREFIX).ok_or(MagnetError::NotMagnet)?;
+        let mut info_hash = None;
+        let mut display_name = None;
+        let mut trackers = Vec::new();
+        for param in params.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = match param.find('=') {
+                Some(n) => (&param[..n], &param[n + 1..]),
+                None => (param, ""),
+            };
+            match key {
+                // other `xt` URNs, such as BEP 52's `btmh`, may sit alongside ours
+                "xt" if info_hash.is_none() => {
+                    let urn = percent_decode(value).ok_or(MagnetError::InvalidField("xt"))?;
+                    if let Some(hash) = urn.strip_prefix(BTIH_PREFIX) {
+                        info_hash = Some(parse_btih(hash)?);
+                    }
+                },
+                "dn" => {
+                    display_name = Some(percent_decode(value).ok_or(MagnetError::InvalidField("dn"))?);
+                },
+                "tr" => {
+                    trackers.push(percent_decode(value).ok_or(MagnetError::InvalidField("tr"))?);
+                },
+                _ => {},
+            }
+        }
+        return Ok(Magnet{
+            info_hash: info_hash.ok_or(MagnetError::MissingField("xt"))?,
+            display_name,
+            trackers,
+        });
+    }
+}
+
+// the info-hash is either 40 hex digits or, in older links, 32 base32 digits
+fn parse_btih(s: &str) -> Result<NodeId, MagnetError> {
+    if s.len() == 32 {
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&decode_base32(s).ok_or(MagnetError::InvalidField("xt"))?);
+        return Ok(NodeId(hash));
+    }
+    NodeId::from_hex(s).map_err(|_| MagnetError::InvalidField("xt"))
+}
+
+fn decode_base32(s: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    return Some(bytes);
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    return encoded;
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut input = s.as_bytes().iter();
+    while let Some(&b) = input.next() {
+        match b {
+            b'%' => {
+                let hi = (*input.next()? as char).to_digit(16)?;
+                let lo = (*input.next()? as char).to_digit(16)?;
+                bytes.push((hi * 16 + lo) as u8);
+            },
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(b),
+        }
+    }
+    return String::from_utf8(bytes).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_TORRENT: &[u8] = include_bytes!("../../tests/fixtures/hello.torrent");
+    const ALBUM_TORRENT: &[u8] = include_bytes!("../../tests/fixtures/album.torrent");
+    const HELLO_INFO_HASH: [u8; 20] = [
+        0xa3, 0xb5, 0x2c, 0xda, 0x8f, 0x3c, 0x8e, 0x17, 0x0f, 0x2f,
+        0xb4, 0x90, 0x97, 0xd0, 0x0c, 0xf8, 0x4d, 0x29, 0x66, 0x2a,
+    ];
+
+    #[test]
+    fn test_magnet_link_from_metainfo() {
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        assert_eq!(
+            "magnet:?xt=urn:btih:a3b52cda8f3c8e170f2fb49097d00cf84d29662a&dn=hello.txt\
+             &tr=http%3A%2F%2Ftracker.example.com%3A6969%2Fannounce",
+            metainfo.magnet_link(),
+        );
+        let metainfo = MetaInfo::from_slice(ALBUM_TORRENT).unwrap();
+        let magnet = Magnet::from_str(&metainfo.magnet_link()).unwrap();
+        assert_eq!(metainfo.info_hash(), magnet.info_hash);
+        assert_eq!(Some("album".to_string()), magnet.display_name);
+        assert_eq!(metainfo.announce_list().concat(), magnet.trackers);
+    }
+
+    #[test]
+    fn test_magnet_link_bare_info_hash() {
+        let magnet = Magnet::from(NodeId(HELLO_INFO_HASH));
+        assert_eq!("magnet:?xt=urn:btih:a3b52cda8f3c8e170f2fb49097d00cf84d29662a", magnet.magnet_link());
+    }
+
+    #[test]
+    fn test_magnet_from_str() {
+        let magnet = Magnet::from_str(
+            "magnet:?xt=urn:btih:A3B52CDA8F3C8E170F2FB49097D00CF84D29662A&dn=hello+world%21\
+             &tr=udp%3A%2F%2Fone.example%3A80&x.pe=10.0.0.1:6881&tr=http://two.example/announce",
+        ).unwrap();
+        assert_eq!(NodeId(HELLO_INFO_HASH), magnet.info_hash);
+        assert_eq!(Some("hello world!".to_string()), magnet.display_name);
+        assert_eq!(vec!["udp://one.example:80", "http://two.example/announce"], magnet.trackers);
+
+        let magnet = Magnet::from_str("magnet:?xt=urn:btih:uo2szwuphshbodzpwsijpuam7bgsszrk").unwrap();
+        assert_eq!(NodeId(HELLO_INFO_HASH), magnet.info_hash);
+        assert_eq!(None, magnet.display_name);
+        assert!(magnet.trackers.is_empty());
+    }
+
+    #[test]
+    fn test_magnet_from_str_errors() {
+        let failure_cases = vec![
+            ("http://example.com/", MagnetError::NotMagnet),
+            ("magnet:?dn=foo", MagnetError::MissingField("xt")),
+            ("magnet:?xt=urn:btmh:1220abcd", MagnetError::MissingField("xt")),
+            ("magnet:?xt=urn:btih:a3b52cda", MagnetError::InvalidField("xt")),
+            ("magnet:?xt=urn:btih:g3b52cda8f3c8e170f2fb49097d00cf84d29662a", MagnetError::InvalidField("xt")),
+            ("magnet:?xt=urn:btih:1o2szwuphshbodzpwsijpuam7bgsszrk", MagnetError::InvalidField("xt")),
+            ("magnet:?xt=urn:btih:a3b52cda8f3c8e170f2fb49097d00cf84d29662a&dn=%zz", MagnetError::InvalidField("dn")),
+        ];
+        for (input, error) in failure_cases.into_iter() {
+            assert_eq!(Some(error), Magnet::from_str(input).err());
+        }
+    }
+}
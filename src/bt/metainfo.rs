@@ -0,0 +1,853 @@
+use std::fmt;
+
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use super::{Bencoding, BencodingParseError, BencodingRef, Dictionary, NodeId};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MetaInfoError {
+    Bencoding(BencodingParseError),
+    MissingField(&'static str),
+    InvalidField(&'static str),
+}
+
+impl fmt::Display for MetaInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetaInfoError::Bencoding(e) => write!(f, "{}", e),
+            MetaInfoError::MissingField(field) => write!(f, "metainfo is missing `{}`", field),
+            MetaInfoError::InvalidField(field) => write!(f, "metainfo has an invalid `{}`", field),
+        }
+    }
+}
+
+impl From<BencodingParseError> for MetaInfoError {
+    fn from(e: BencodingParseError) -> MetaInfoError {
+        MetaInfoError::Bencoding(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FileEntry {
+    pub length: u64,
+    /// Path components relative to the torrent's `info_name` directory.
+    pub path: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MetaInfoFiles {
+    Single { length: u64 },
+    Multi(Vec<FileEntry>),
+}
+
+/// A file in a v2 torrent's `file tree` (BEP 52).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FileTreeEntry {
+    pub length: u64,
+    /// Path components down the `file tree`. Unlike `FileEntry`'s, these
+    /// start with the file's own name in single-file torrents.
+    pub path: Vec<String>,
+    /// The root of the merkle tree over the file's 16 KiB blocks, which empty
+    /// files don't have.
+    pub pieces_root: Option<[u8; 32]>,
+}
+
+/// Which versions of the protocol a torrent's `info` dictionary describes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MetaVersion {
+    V1,
+    V2,
+    /// Both v1 and v2 fields, so that either kind of peer can download it.
+    Hybrid,
+}
+
+/// The contents of a `.torrent` file, as described in BEP 3 and, for v2 and
+/// hybrid torrents, BEP 52.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MetaInfo {
+    pub announce: String,
+    /// The file name for single-file torrents, or the directory name for multi-file ones.
+    pub info_name: String,
+    pub piece_length: u64,
+    /// The SHA-1 hash of each piece, which v2-only torrents don't have.
+    pub pieces: Vec<[u8; 20]>,
+    pub files: MetaInfoFiles,
+    /// Whether peers may only come from the trackers, and not the DHT or
+    /// peer exchange (BEP 27).
+    pub private: bool,
+    pub comment: Option<String>,
+    /// The program that made the torrent.
+    pub created_by: Option<String>,
+    /// When the torrent was made, in seconds since the Unix epoch.
+    pub creation_date: Option<i64>,
+    announce_list: Vec<Vec<String>>,
+    web_seeds: Vec<String>,
+    meta_version: MetaVersion,
+    file_tree: Vec<FileTreeEntry>,
+    /// The `info` dictionary exactly as it appeared in the file.
+    info_bytes: Vec<u8>,
+}
+
+fn get<'a>(dict: &'a Dictionary, key: &'static str) -> Result<&'a Bencoding, MetaInfoError> {
+    dict.get(key).ok_or(MetaInfoError::MissingField(key))
+}
+
+fn get_dict<'a>(dict: &'a Dictionary, key: &'static str) -> Result<&'a Dictionary, MetaInfoError> {
+    get(dict, key)?.as_dict().ok_or(MetaInfoError::InvalidField(key))
+}
+
+fn get_str<'a>(dict: &'a Dictionary, key: &'static str) -> Result<&'a str, MetaInfoError> {
+    get(dict, key)?.as_str().ok_or(MetaInfoError::InvalidField(key))
+}
+
+fn get_bytes<'a>(dict: &'a Dictionary, key: &'static str) -> Result<&'a [u8], MetaInfoError> {
+    get(dict, key)?.as_bytes().ok_or(MetaInfoError::InvalidField(key))
+}
+
+fn get_u64(dict: &Dictionary, key: &'static str) -> Result<u64, MetaInfoError> {
+    get(dict, key)?.as_u64_checked().ok_or(MetaInfoError::InvalidField(key))
+}
+
+fn get_list<'a>(dict: &'a Dictionary, key: &'static str) -> Result<&'a [Bencoding], MetaInfoError> {
+    get(dict, key)?.as_list().ok_or(MetaInfoError::InvalidField(key))
+}
+
+// free text, which isn't always UTF-8
+fn get_text(dict: &Dictionary, key: &'static str) -> Result<Option<String>, MetaInfoError> {
+    match dict.get(key) {
+        None => Ok(None),
+        Some(value) => match value.as_bytes() {
+            Some(bytes) => Ok(Some(String::from_utf8_lossy(bytes).into_owned())),
+            None => Err(MetaInfoError::InvalidField(key)),
+        },
+    }
+}
+
+// BEP 27 only defines `1`, but `0` turns up too
+fn parse_private(info: &Dictionary) -> Result<bool, MetaInfoError> {
+    match info.get("private") {
+        None | Some(Bencoding::Int(0)) => Ok(false),
+        Some(Bencoding::Int(1)) => Ok(true),
+        Some(_) => Err(MetaInfoError::InvalidField("private")),
+    }
+}
+
+fn parse_creation_date(root: &Dictionary) -> Result<Option<i64>, MetaInfoError> {
+    match root.get("creation date") {
+        None => Ok(None),
+        Some(Bencoding::Int(n)) => Ok(Some(*n)),
+        Some(_) => Err(MetaInfoError::InvalidField("creation date")),
+    }
+}
+
+// BEP 12: a list of tiers, each a list of tracker URLs
+fn parse_announce_list(root: &Dictionary) -> Result<Vec<Vec<String>>, MetaInfoError> {
+    if !root.contains_key("announce-list") {
+        return Ok(Vec::new());
+    }
+    let mut tiers = Vec::new();
+    for tier in get_list(root, "announce-list")? {
+        let urls = tier.as_list()
+            .and_then(|urls| urls.iter()
+                .map(|url| url.as_str().map(|u| u.to_string()))
+                .collect::<Option<Vec<String>>>())
+            .ok_or(MetaInfoError::InvalidField("announce-list"))?;
+        if !urls.is_empty() {
+            tiers.push(urls);
+        }
+    }
+    return Ok(tiers);
+}
+
+// BEP 19: one URL on its own, or a list of them
+fn parse_url_list(root: &Dictionary) -> Result<Vec<String>, MetaInfoError> {
+    let urls = match root.get("url-list") {
+        None => return Ok(Vec::new()),
+        Some(Bencoding::String(url)) => vec![url.clone()],
+        Some(Bencoding::List(urls)) => urls.iter()
+            .map(|url| url.as_str().map(|u| u.to_string()))
+            .collect::<Option<Vec<String>>>()
+            .ok_or(MetaInfoError::InvalidField("url-list"))?,
+        Some(_) => return Err(MetaInfoError::InvalidField("url-list")),
+    };
+    // an empty string stands in for no web seeds at all
+    return Ok(urls.into_iter().filter(|url| !url.is_empty()).collect());
+}
+
+fn parse_files(info: &Dictionary) -> Result<MetaInfoFiles, MetaInfoError> {
+    // single-file torrents have `length`, multi-file torrents have `files`
+    if !info.contains_key("files") {
+        return Ok(MetaInfoFiles::Single{length: get_u64(info, "length")?});
+    }
+    let mut files = Vec::new();
+    for file in get_list(info, "files")? {
+        let file = file.as_dict().ok_or(MetaInfoError::InvalidField("files"))?;
+        let path = get_list(file, "path")?.iter()
+            .map(|component| component.as_str().map(|c| c.to_string()))
+            .collect::<Option<Vec<String>>>()
+            .ok_or(MetaInfoError::InvalidField("path"))?;
+        if path.is_empty() {
+            return Err(MetaInfoError::InvalidField("path"));
+        }
+        files.push(FileEntry{length: get_u64(file, "length")?, path});
+    }
+    return Ok(MetaInfoFiles::Multi(files));
+}
+
+// BEP 52 only defines version 2; v1 torrents leave the field out
+fn parse_meta_version(info: &Dictionary) -> Result<bool, MetaInfoError> {
+    match info.get("meta version") {
+        None => Ok(false),
+        Some(Bencoding::Int(2)) => Ok(true),
+        Some(_) => Err(MetaInfoError::InvalidField("meta version")),
+    }
+}
+
+// a file is a dictionary whose only key is the empty string, mapping to its
+// attributes; any other dictionary is a directory
+fn walk_file_tree(
+    node: &Dictionary,
+    path: &mut Vec<String>,
+    files: &mut Vec<FileTreeEntry>,
+) -> Result<(), MetaInfoError> {
+    if let Some(attributes) = node.get("") {
+        let attributes = attributes.as_dict().ok_or(MetaInfoError::InvalidField("file tree"))?;
+        if path.is_empty() || node.len() != 1 {
+            return Err(MetaInfoError::InvalidField("file tree"));
+        }
+        let length = get_u64(attributes, "length")?;
+        let pieces_root = match attributes.get("pieces root") {
+            None if length == 0 => None,
+            None => return Err(MetaInfoError::MissingField("pieces root")),
+            Some(root) => match root.as_bytes() {
+                Some(root) if root.len() == 32 => {
+                    let mut hash = [0u8; 32];
+                    hash.copy_from_slice(root);
+                    Some(hash)
+                },
+                _ => return Err(MetaInfoError::InvalidField("pieces root")),
+            },
+        };
+        files.push(FileTreeEntry{length, path: path.clone(), pieces_root});
+        return Ok(());
+    }
+    for (name, child) in node.iter() {
+        let child = child.as_dict().ok_or(MetaInfoError::InvalidField("file tree"))?;
+        path.push(name.clone());
+        walk_file_tree(child, path, files)?;
+        path.pop();
+    }
+    return Ok(());
+}
+
+fn parse_file_tree(info: &Dictionary) -> Result<Vec<FileTreeEntry>, MetaInfoError> {
+    let mut files = Vec::new();
+    walk_file_tree(get_dict(info, "file tree")?, &mut Vec::new(), &mut files)?;
+    return Ok(files);
+}
+
+// what a v2-only torrent's files would have looked like as v1 fields
+fn files_from_tree(name: &str, file_tree: &[FileTreeEntry]) -> MetaInfoFiles {
+    match file_tree {
+        [file] if file.path == [name] => MetaInfoFiles::Single{length: file.length},
+        _ => MetaInfoFiles::Multi(file_tree.iter()
+            .map(|file| FileEntry{length: file.length, path: file.path.clone()})
+            .collect()),
+    }
+}
+
+/// Splits the `pieces` field into the SHA-1 hash of each piece, failing
+/// unless it's a whole number of 20-byte hashes.
+pub fn parse_pieces(bytes: &[u8]) -> Result<Vec<[u8; 20]>, MetaInfoError> {
+    if !bytes.len().is_multiple_of(20) {
+        return Err(MetaInfoError::InvalidField("pieces"));
+    }
+    let mut pieces = Vec::with_capacity(bytes.len() / 20);
+    for chunk in bytes.chunks(20) {
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(chunk);
+        pieces.push(hash);
+    }
+    return Ok(pieces);
+}
+
+// the sum of every file's length, or `None` if it doesn't fit in a u64
+fn sum_lengths(files: &MetaInfoFiles) -> Option<u64> {
+    match files {
+        MetaInfoFiles::Single{length} => Some(*length),
+        MetaInfoFiles::Multi(files) => files.iter().try_fold(0u64, |total, file| total.checked_add(file.length)),
+    }
+}
+
+impl MetaInfo {
+    pub fn from_slice(input: &[u8]) -> Result<MetaInfo, MetaInfoError> {
+        let bencoding = Bencoding::from_slice(input)?;
+        let root = bencoding.as_dict().ok_or(MetaInfoError::InvalidField("metainfo"))?;
+        let info = get_dict(root, "info")?;
+        let info_span = BencodingRef::dict_value_span(input, b"info").ok_or(MetaInfoError::MissingField("info"))?;
+        let info_name = get_str(info, "name")?.to_string();
+
+        // v2-only torrents have none of the v1 fields, and hybrids have both
+        let v2 = parse_meta_version(info)?;
+        let v1 = !v2 || info.contains_key("pieces");
+        let meta_version = match (v1, v2) {
+            (true, false) => MetaVersion::V1,
+            (false, true) => MetaVersion::V2,
+            _ => MetaVersion::Hybrid,
+        };
+        let file_tree = if v2 { parse_file_tree(info)? } else { Vec::new() };
+        let (pieces, files) = if v1 {
+            (parse_pieces(get_bytes(info, "pieces")?)?, parse_files(info)?)
+        } else {
+            (Vec::new(), files_from_tree(&info_name, &file_tree))
+        };
+        // every offset into the content has to fit in a u64, from the pieces' side as well as the files'
+        if sum_lengths(&files).is_none() {
+            return Err(MetaInfoError::InvalidField("length"));
+        }
+        let piece_length = get_u64(info, "piece length")?;
+        if piece_length.checked_mul(pieces.len() as u64).is_none() {
+            return Err(MetaInfoError::InvalidField("piece length"));
+        }
+
+        return Ok(MetaInfo{
+            announce: get_str(root, "announce")?.to_string(),
+            info_name,
+            piece_length,
+            pieces,
+            files,
+            private: parse_private(info)?,
+            comment: get_text(root, "comment")?,
+            created_by: get_text(root, "created by")?,
+            creation_date: parse_creation_date(root)?,
+            announce_list: parse_announce_list(root)?,
+            web_seeds: parse_url_list(root)?,
+            meta_version,
+            file_tree,
+            info_bytes: input[info_span].to_vec(),
+        });
+    }
+
+    /// The tiers of trackers to try in order, per BEP 12. Torrents without an
+    /// `announce-list` get a single tier holding just `announce`.
+    pub fn announce_list(&self) -> Vec<Vec<String>> {
+        if self.announce_list.is_empty() {
+            return vec![vec![self.announce.clone()]];
+        }
+        return self.announce_list.clone();
+    }
+
+    /// HTTP or FTP URLs the torrent's content can also be fetched from, per BEP 19.
+    pub fn web_seeds(&self) -> Vec<String> {
+        self.web_seeds.clone()
+    }
+
+    /// The size of all of the torrent's content put together. `from_slice`
+    /// rejects torrents whose files add up to more than a u64 holds, so this
+    /// only saturates if `files` has been changed since.
+    pub fn total_length(&self) -> u64 {
+        sum_lengths(&self.files).unwrap_or(u64::MAX)
+    }
+
+    /// How many pieces the content splits into, counting a shorter last one.
+    /// A consistent v1 torrent has a hash in `pieces` for each.
+    pub fn num_pieces(&self) -> usize {
+        if self.piece_length == 0 {
+            return 0;
+        }
+        let total = self.total_length();
+        let whole = total / self.piece_length;
+        return if total.is_multiple_of(self.piece_length) { whole as usize } else { whole as usize + 1 };
+    }
+
+    /// The length of the final piece: whatever's left over after the whole
+    /// pieces, or a full `piece_length` when the content divides evenly.
+    pub fn last_piece_length(&self) -> u64 {
+        let total = self.total_length();
+        if self.piece_length == 0 || total == 0 {
+            return 0;
+        }
+        return match total % self.piece_length {
+            0 => self.piece_length,
+            remainder => remainder,
+        };
+    }
+
+    /// Which file a byte of the content, counting from the start of the
+    /// first file, falls in, and how far into that file it is. Empty files
+    /// hold no bytes, so they're never the answer.
+    pub fn locate(&self, offset: u64) -> Option<(usize, u64)> {
+        let files = match &self.files {
+            MetaInfoFiles::Single{length} => return if offset < *length { Some((0, offset)) } else { None },
+            MetaInfoFiles::Multi(files) => files,
+        };
+        let mut start: u64 = 0;
+        for (index, file) in files.iter().enumerate() {
+            // a file that runs past u64::MAX holds every offset from its start on
+            match start.checked_add(file.length) {
+                Some(end) if offset >= end => start = end,
+                _ => return Some((index, offset - start)),
+            }
+        }
+        return None;
+    }
+
+    /// The piece a byte of the content is in. Pieces run on across file
+    /// boundaries, so the one a file's first byte is in can start in the
+    /// file before it.
+    pub fn piece_for_offset(&self, offset: u64) -> Option<usize> {
+        if self.piece_length == 0 || offset >= self.total_length() {
+            return None;
+        }
+        return Some((offset / self.piece_length) as usize);
+    }
+
+    /// The bencoded `info` dictionary byte for byte as it was in the file,
+    /// whether or not it was canonically encoded.
+    pub fn info_bytes(&self) -> &[u8] {
+        &self.info_bytes
+    }
+
+    /// Whether `data` is piece `index` of the content: the right length, which
+    /// for the last piece is whatever's left over, and the right hash.
+    pub fn verify_piece(&self, index: usize, data: &[u8]) -> bool {
+        let expected = match self.pieces.get(index) {
+            Some(hash) => hash,
+            None => return false,
+        };
+        let start = match self.piece_length.checked_mul(index as u64) {
+            Some(start) => start,
+            None => return false,
+        };
+        let len = self.piece_length.min(self.total_length().saturating_sub(start));
+        if data.len() as u64 != len {
+            return false;
+        }
+        return Sha1::digest(data)[..] == expected[..];
+    }
+
+    /// The SHA-1 hash of `info_bytes`, which identifies the torrent to
+    /// trackers and the DHT.
+    pub fn info_hash(&self) -> NodeId {
+        NodeId(Sha1::digest(&self.info_bytes).into())
+    }
+
+    /// The SHA-256 hash of `info_bytes`, which identifies v2 and hybrid
+    /// torrents to v2 peers (BEP 52). It's only meaningful when
+    /// `meta_version` isn't `V1`.
+    pub fn info_hash_v2(&self) -> [u8; 32] {
+        Sha256::digest(&self.info_bytes).into()
+    }
+
+    pub fn meta_version(&self) -> MetaVersion {
+        self.meta_version
+    }
+
+    /// The files in the v2 `file tree`, depth first in the dictionary's
+    /// order; empty for v1 torrents.
+    pub fn file_tree(&self) -> &[FileTreeEntry] {
+        &self.file_tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bencode, bt::BencodingErrorKind};
+
+    const HELLO_TORRENT: &[u8] = include_bytes!("../../tests/fixtures/hello.torrent");
+    const ALBUM_TORRENT: &[u8] = include_bytes!("../../tests/fixtures/album.torrent");
+    const GREETINGS_V2_TORRENT: &[u8] = include_bytes!("../../tests/fixtures/greetings_v2.torrent");
+
+    #[test]
+    fn test_metainfo_from_slice() {
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        assert_eq!("http://tracker.example.com:6969/announce", metainfo.announce);
+        assert_eq!("hello.txt", metainfo.info_name);
+        assert_eq!(16384, metainfo.piece_length);
+        assert_eq!(MetaInfoFiles::Single{length: 35000}, metainfo.files);
+        assert_eq!(35000, metainfo.total_length());
+        assert_eq!(3, metainfo.pieces.len());
+        let first_piece = [
+            0x40, 0x0e, 0x50, 0xf9, 0x12, 0x01, 0xaf, 0x74, 0xe2, 0x99,
+            0x76, 0xcb, 0x26, 0x9f, 0xc0, 0xcd, 0xa5, 0x46, 0xa4, 0xd8,
+        ];
+        assert_eq!(first_piece, metainfo.pieces[0]);
+    }
+
+    #[test]
+    fn test_metainfo_large_lengths() {
+        // a length past i64::MAX is parsed as a big integer, but still fits a u64
+        let metainfo = MetaInfo::from_slice(
+            b"d8:announce3:url4:infod6:lengthi18446744073709551615e4:name1:a12:piece lengthi1e6:pieces0:ee",
+        ).unwrap();
+        assert_eq!(MetaInfoFiles::Single{length: u64::MAX}, metainfo.files);
+        assert_eq!(u64::MAX, metainfo.total_length());
+        let metainfo = MetaInfo::from_slice(
+            b"d8:announce3:url4:infod6:lengthi0e4:name1:a12:piece lengthi9223372036854775808e6:pieces0:ee",
+        ).unwrap();
+        assert_eq!(1 << 63, metainfo.piece_length);
+
+        assert_eq!(
+            Err(MetaInfoError::InvalidField("length")),
+            MetaInfo::from_slice(
+                b"d8:announce3:url4:infod6:lengthi18446744073709551616e4:name1:a12:piece lengthi1e6:pieces0:ee",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_parse_pieces() {
+        let mut bytes = [0xAB; 40].to_vec();
+        bytes[20] = 0xFF;
+        let mut second = [0xAB; 20];
+        second[0] = 0xFF;
+        assert_eq!(Ok(vec![[0xAB; 20], second]), parse_pieces(&bytes));
+        assert_eq!(Ok(Vec::new()), parse_pieces(b""));
+        assert_eq!(Err(MetaInfoError::InvalidField("pieces")), parse_pieces(&bytes[1..]));
+        assert_eq!(Err(MetaInfoError::InvalidField("pieces")), parse_pieces(&[0; 19]));
+    }
+
+    #[test]
+    fn test_metainfo_multi_file() {
+        let metainfo = MetaInfo::from_slice(ALBUM_TORRENT).unwrap();
+        assert_eq!("album", metainfo.info_name);
+        let expected = MetaInfoFiles::Multi(vec![
+            FileEntry{length: 11000, path: vec!["notes.txt".to_string()]},
+            FileEntry{length: 10240, path: vec!["data".to_string(), "bytes.bin".to_string()]},
+        ]);
+        assert_eq!(expected, metainfo.files);
+        assert_eq!(21240, metainfo.total_length());
+        assert_eq!(2, metainfo.pieces.len());
+    }
+
+    #[test]
+    fn test_metainfo_optional_fields() {
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        assert!(!metainfo.private);
+        assert_eq!(Some("netfun test fixture".to_string()), metainfo.comment);
+        assert_eq!(Some("mktorrent 1.1".to_string()), metainfo.created_by);
+        assert_eq!(Some(1602633600), metainfo.creation_date);
+
+        let metainfo = MetaInfo::from_slice(ALBUM_TORRENT).unwrap();
+        assert!(!metainfo.private);
+        assert_eq!(None, metainfo.comment);
+        assert_eq!(None, metainfo.created_by);
+        assert_eq!(None, metainfo.creation_date);
+
+        let private = b"d8:announce3:url4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:7:privatei1eee";
+        assert!(MetaInfo::from_slice(private).unwrap().private);
+
+        let failure_cases = vec![
+            (&b"d8:announce3:url4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:7:privatei2eee"[..], "private"),
+            (&b"d8:announce3:url7:commenti1e4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee"[..], "comment"),
+            (&b"d8:announce3:url13:creation date3:now4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee"[..], "creation date"),
+        ];
+        for (input, field) in failure_cases.into_iter() {
+            assert_eq!(Err(MetaInfoError::InvalidField(field)), MetaInfo::from_slice(input));
+        }
+    }
+
+    #[test]
+    fn test_metainfo_announce_list() {
+        let metainfo = MetaInfo::from_slice(ALBUM_TORRENT).unwrap();
+        let expected = vec![
+            vec!["udp://tracker.example.org:1337/announce".to_string()],
+            vec![
+                "http://backup1.example.net/announce".to_string(),
+                "http://backup2.example.net/announce".to_string(),
+            ],
+        ];
+        assert_eq!(expected, metainfo.announce_list());
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        assert_eq!(vec![vec![metainfo.announce.clone()]], metainfo.announce_list());
+    }
+
+    #[test]
+    fn test_metainfo_web_seeds() {
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        assert!(metainfo.web_seeds().is_empty());
+
+        let info = "4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:e";
+        let torrent = |url_list: &str| format!("d8:announce3:url{}8:url-list{}e", info, url_list);
+        let metainfo = MetaInfo::from_slice(torrent("21:http://seed.example/a").as_bytes()).unwrap();
+        assert_eq!(vec!["http://seed.example/a".to_string()], metainfo.web_seeds());
+        let metainfo = MetaInfo::from_slice(torrent("l21:http://seed.example/a20:ftp://seed.example/ae").as_bytes()).unwrap();
+        assert_eq!(vec!["http://seed.example/a".to_string(), "ftp://seed.example/a".to_string()], metainfo.web_seeds());
+        let metainfo = MetaInfo::from_slice(torrent("0:").as_bytes()).unwrap();
+        assert!(metainfo.web_seeds().is_empty());
+
+        for url_list in ["i1e", "li1ee"].iter() {
+            assert_eq!(Err(MetaInfoError::InvalidField("url-list")), MetaInfo::from_slice(torrent(url_list).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_metainfo_verify_piece() {
+        let content: Vec<u8> = (0..35000u32).map(|n| (n % 251) as u8).collect();
+        let pieces: Vec<u8> = content.chunks(16384).flat_map(|piece| Sha1::digest(piece).to_vec()).collect();
+        let torrent = bencode!({
+            "announce" => "url",
+            "info" => { "length" => 35000, "name" => "a", "piece length" => 16384, "pieces" => pieces },
+        });
+        let metainfo = MetaInfo::from_slice(&torrent.encode()).unwrap();
+        assert_eq!(3, metainfo.pieces.len());
+        assert!(metainfo.verify_piece(0, &content[..16384]));
+        assert!(metainfo.verify_piece(1, &content[16384..32768]));
+        // the last piece is only what's left
+        assert!(metainfo.verify_piece(2, &content[32768..]));
+
+        let mut corrupted = content[..16384].to_vec();
+        corrupted[100] ^= 1;
+        assert!(!metainfo.verify_piece(0, &corrupted));
+        assert!(!metainfo.verify_piece(1, &content[..16384]));
+        assert!(!metainfo.verify_piece(0, &content[..16383]));
+        let mut padded = content[32768..].to_vec();
+        padded.push(0);
+        assert!(!metainfo.verify_piece(2, &padded));
+        assert!(!metainfo.verify_piece(3, b""));
+
+        // a piece that would start past u64::MAX is never the right one
+        let mut huge = metainfo.clone();
+        huge.piece_length = u64::MAX;
+        assert!(!huge.verify_piece(1, b""));
+    }
+
+    #[test]
+    fn test_metainfo_piece_counts() {
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        assert_eq!(3, metainfo.num_pieces());
+        assert_eq!(35000 - 2 * 16384, metainfo.last_piece_length());
+        let metainfo = MetaInfo::from_slice(ALBUM_TORRENT).unwrap();
+        assert_eq!(metainfo.pieces.len(), metainfo.num_pieces());
+
+        let torrent = |length: u64, piece_length: u64| {
+            let info = format!("d6:lengthi{}e4:name1:a12:piece lengthi{}e6:pieces0:e", length, piece_length);
+            MetaInfo::from_slice(format!("d8:announce3:url4:info{}e", info).as_bytes()).unwrap()
+        };
+        let cases = vec![
+            // (length, piece length, pieces, last piece length)
+            (32768, 16384, 2, 16384),
+            (32769, 16384, 3, 1),
+            (16383, 16384, 1, 16383),
+            (16384, 16384, 1, 16384),
+            (1, 1, 1, 1),
+            (0, 16384, 0, 0),
+            (10, 0, 0, 0),
+        ];
+        for (length, piece_length, pieces, last) in cases.into_iter() {
+            let metainfo = torrent(length, piece_length);
+            assert_eq!(pieces, metainfo.num_pieces(), "{} / {}", length, piece_length);
+            assert_eq!(last, metainfo.last_piece_length(), "{} / {}", length, piece_length);
+        }
+    }
+
+    #[test]
+    fn test_metainfo_locate() {
+        // notes.txt is 11000 bytes and data/bytes.bin 10240, in 16384-byte
+        // pieces, so the first piece holds all of one and the start of the other
+        let metainfo = MetaInfo::from_slice(ALBUM_TORRENT).unwrap();
+        let cases = vec![
+            (0, Some((0, 0)), Some(0)),
+            (10999, Some((0, 10999)), Some(0)),
+            (11000, Some((1, 0)), Some(0)),
+            (16383, Some((1, 5383)), Some(0)),
+            (16384, Some((1, 5384)), Some(1)),
+            (21239, Some((1, 10239)), Some(1)),
+            (21240, None, None),
+        ];
+        for (offset, location, piece) in cases.into_iter() {
+            assert_eq!(location, metainfo.locate(offset), "{}", offset);
+            assert_eq!(piece, metainfo.piece_for_offset(offset), "{}", offset);
+        }
+
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        assert_eq!(Some((0, 34999)), metainfo.locate(34999));
+        assert_eq!(Some(2), metainfo.piece_for_offset(34999));
+        assert_eq!(None, metainfo.locate(35000));
+
+        // empty files take up no offsets
+        let torrent = bencode!({
+            "announce" => "url",
+            "info" => {
+                "files" => [
+                    { "length" => 1, "path" => ["a"] },
+                    { "length" => 0, "path" => ["b"] },
+                    { "length" => 1, "path" => ["c"] },
+                ],
+                "name" => "d", "piece length" => 1, "pieces" => [0u8; 40].to_vec(),
+            },
+        });
+        let metainfo = MetaInfo::from_slice(&torrent.encode()).unwrap();
+        assert_eq!(Some((0, 0)), metainfo.locate(0));
+        assert_eq!(Some((2, 0)), metainfo.locate(1));
+
+        let mut huge = metainfo.clone();
+        huge.files = MetaInfoFiles::Multi(vec![
+            FileEntry{length: u64::MAX - 1, path: vec!["a".to_string()]},
+            FileEntry{length: u64::MAX, path: vec!["b".to_string()]},
+        ]);
+        assert_eq!(u64::MAX, huge.total_length());
+        assert_eq!(Some((1, 1)), huge.locate(u64::MAX));
+    }
+
+    #[test]
+    fn test_metainfo_info_hash() {
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        // sha1 of the fixture's `info` bytes, computed independently with Python's hashlib
+        let expected = [
+            0xa3, 0xb5, 0x2c, 0xda, 0x8f, 0x3c, 0x8e, 0x17, 0x0f, 0x2f,
+            0xb4, 0x90, 0x97, 0xd0, 0x0c, 0xf8, 0x4d, 0x29, 0x66, 0x2a,
+        ];
+        assert_eq!(&expected[..], &metainfo.info_hash()[..]);
+    }
+
+    #[test]
+    fn test_metainfo_v2() {
+        let metainfo = MetaInfo::from_slice(GREETINGS_V2_TORRENT).unwrap();
+        assert_eq!(MetaVersion::V2, metainfo.meta_version());
+        assert_eq!("greetings", metainfo.info_name);
+        assert_eq!(16384, metainfo.piece_length);
+        assert!(metainfo.pieces.is_empty());
+        let pieces_root = [
+            0xa9, 0x48, 0x90, 0x4f, 0x2f, 0x0f, 0x47, 0x9b, 0x8f, 0x81, 0x97, 0x69, 0x4b, 0x30, 0x18, 0x4b,
+            0x0d, 0x2e, 0xd1, 0xc1, 0xcd, 0x2a, 0x1e, 0xc0, 0xfb, 0x85, 0xd2, 0x99, 0xa1, 0x92, 0xa4, 0x47,
+        ];
+        let expected = vec![
+            FileTreeEntry{length: 0, path: vec!["data".to_string(), "empty.bin".to_string()], pieces_root: None},
+            FileTreeEntry{length: 12, path: vec!["hello.txt".to_string()], pieces_root: Some(pieces_root)},
+        ];
+        assert_eq!(&expected[..], metainfo.file_tree());
+        let expected = MetaInfoFiles::Multi(vec![
+            FileEntry{length: 0, path: vec!["data".to_string(), "empty.bin".to_string()]},
+            FileEntry{length: 12, path: vec!["hello.txt".to_string()]},
+        ]);
+        assert_eq!(expected, metainfo.files);
+
+        // sha256 of the fixture's `info` bytes, computed independently with Python's hashlib
+        let expected = [
+            0xf1, 0xdb, 0x1f, 0x33, 0x1b, 0xe8, 0x4f, 0x20, 0x53, 0x21, 0x81, 0xf8, 0xfb, 0x6a, 0x15, 0x18,
+            0xa4, 0x53, 0xe1, 0x2f, 0x54, 0x51, 0x14, 0xdd, 0x88, 0xe0, 0x55, 0xff, 0x96, 0x61, 0x6a, 0x2f,
+        ];
+        assert_eq!(expected, metainfo.info_hash_v2());
+
+        // a single file's tree holds just the torrent's name
+        let torrent = bencode!({
+            "announce" => "url",
+            "info" => {
+                "file tree" => { "a" => { "" => { "length" => 12, "pieces root" => pieces_root.to_vec() } } },
+                "meta version" => 2, "name" => "a", "piece length" => 16384,
+            },
+        });
+        let metainfo = MetaInfo::from_slice(&torrent.encode()).unwrap();
+        assert_eq!(MetaInfoFiles::Single{length: 12}, metainfo.files);
+        assert_eq!(vec!["a".to_string()], metainfo.file_tree()[0].path);
+    }
+
+    #[test]
+    fn test_metainfo_hybrid() {
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        assert_eq!(MetaVersion::V1, metainfo.meta_version());
+        assert!(metainfo.file_tree().is_empty());
+
+        let content = b"hello world\n";
+        let pieces_root = Sha256::digest(content).to_vec();
+        let torrent = bencode!({
+            "announce" => "url",
+            "info" => {
+                "file tree" => { "a" => { "" => { "length" => 12, "pieces root" => pieces_root } } },
+                "length" => 12, "meta version" => 2, "name" => "a", "piece length" => 16384,
+                "pieces" => Sha1::digest(content).to_vec(),
+            },
+        });
+        let metainfo = MetaInfo::from_slice(&torrent.encode()).unwrap();
+        assert_eq!(MetaVersion::Hybrid, metainfo.meta_version());
+        assert!(metainfo.verify_piece(0, content));
+        assert_eq!(1, metainfo.file_tree().len());
+        assert_eq!(&Sha256::digest(metainfo.info_bytes())[..], &metainfo.info_hash_v2()[..]);
+        assert_eq!(&Sha1::digest(metainfo.info_bytes())[..], &metainfo.info_hash()[..]);
+    }
+
+    #[test]
+    fn test_metainfo_v2_errors() {
+        let torrent = |info: &str| format!("d8:announce3:url4:infod{}4:name1:a12:piece lengthi1eee", info);
+        let failure_cases = vec![
+            ("12:meta versioni3e", MetaInfoError::InvalidField("meta version")),
+            ("12:meta versioni2e", MetaInfoError::MissingField("file tree")),
+            ("9:file treei1e12:meta versioni2e", MetaInfoError::InvalidField("file tree")),
+            // a file needs a name
+            ("9:file treed0:d6:lengthi0eee12:meta versioni2e", MetaInfoError::InvalidField("file tree")),
+            ("9:file treed1:ai1ee12:meta versioni2e", MetaInfoError::InvalidField("file tree")),
+            ("9:file treed1:ad0:i1eee12:meta versioni2e", MetaInfoError::InvalidField("file tree")),
+            ("9:file treed1:ad0:d6:lengthi0ee1:bdeee12:meta versioni2e", MetaInfoError::InvalidField("file tree")),
+            ("9:file treed1:ad0:deee12:meta versioni2e", MetaInfoError::MissingField("length")),
+            ("9:file treed1:ad0:d6:lengthi1eeee12:meta versioni2e", MetaInfoError::MissingField("pieces root")),
+            (
+                "9:file treed1:ad0:d6:lengthi1e11:pieces root3:abceee12:meta versioni2e",
+                MetaInfoError::InvalidField("pieces root"),
+            ),
+        ];
+        for (info, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), MetaInfo::from_slice(torrent(info).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_metainfo_info_bytes() {
+        let metainfo = MetaInfo::from_slice(HELLO_TORRENT).unwrap();
+        let start = HELLO_TORRENT.windows(7).position(|w| w == b"4:infod").unwrap() + 6;
+        assert_eq!(&HELLO_TORRENT[start..HELLO_TORRENT.len() - 1], metainfo.info_bytes());
+
+        // a repeated key doesn't survive a parse, so re-encoding would hash
+        // different bytes than the torrent's author did
+        let input = b"d8:announce3:url4:infod6:lengthi1e4:name1:a4:name1:b12:piece lengthi1e6:pieces0:ee";
+        let metainfo = MetaInfo::from_slice(input).unwrap();
+        assert_eq!("b", metainfo.info_name);
+        let info = &input[22..input.len() - 1];
+        assert_eq!(&info[..], metainfo.info_bytes());
+        assert_ne!(info.to_vec(), Bencoding::from_slice(info).unwrap().encode());
+        assert_eq!(&Sha1::digest(info)[..], &metainfo.info_hash()[..]);
+    }
+
+    #[test]
+    fn test_metainfo_errors() {
+        let failure_cases = vec![
+            (&b"i42e"[..], MetaInfoError::InvalidField("metainfo")),
+            (&b"d8:announce3:urle"[..], MetaInfoError::MissingField("info")),
+            (
+                &b"d8:announce3:url4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces3:abcee"[..],
+                MetaInfoError::InvalidField("pieces"),
+            ),
+            (
+                &b"d8:announce3:url4:infod6:lengthi-1e4:name1:a12:piece lengthi1e6:pieces0:ee"[..],
+                MetaInfoError::InvalidField("length"),
+            ),
+            (
+                &b"d8:announce3:url4:infod5:filesld6:lengthi1e4:pathleee4:name1:a12:piece lengthi1e6:pieces0:ee"[..],
+                MetaInfoError::InvalidField("path"),
+            ),
+            (
+                // three files of i64::MAX bytes each are more than a u64 can count
+                &b"d8:announce3:url4:infod5:filesl\
+                    d6:lengthi9223372036854775807e4:pathl1:aee\
+                    d6:lengthi9223372036854775807e4:pathl1:bee\
+                    d6:lengthi9223372036854775807e4:pathl1:ceee\
+                    4:name1:a12:piece lengthi1e6:pieces0:ee"[..],
+                MetaInfoError::InvalidField("length"),
+            ),
+            (
+                &b"d8:announce3:url4:infod6:lengthi1e4:name1:a12:piece lengthi9223372036854775807e6:pieces60:\
+                    012345678901234567890123456789012345678901234567890123456789ee"[..],
+                MetaInfoError::InvalidField("piece length"),
+            ),
+        ];
+        for (input, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), MetaInfo::from_slice(input));
+        }
+        assert_eq!(
+            Err(MetaInfoError::Bencoding(BencodingParseError{offset: 11, kind: BencodingErrorKind::UnexpectedEof})),
+            MetaInfo::from_slice(b"d8:announce"),
+        );
+    }
+}
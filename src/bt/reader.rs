@@ -0,0 +1,173 @@
+//! Decoding back-to-back bencoded values as they arrive on a stream, such as
+//! a peer wire connection.
+
+use std::{fmt, io::{self, Read}};
+
+use super::{Bencoding, BencodingParseError, BencodingRef, ParseOptions};
+
+const READ_CHUNK: usize = 4096;
+
+#[derive(Debug)]
+pub enum BencodingReadError {
+    Io(io::Error),
+    /// A value was malformed, or the stream ended partway through one. The
+    /// offset is from the start of that value.
+    Parse(BencodingParseError),
+}
+
+impl fmt::Display for BencodingReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BencodingReadError::Io(e) => write!(f, "{}", e),
+            BencodingReadError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BencodingReadError {}
+
+impl From<io::Error> for BencodingReadError {
+    fn from(e: io::Error) -> BencodingReadError {
+        BencodingReadError::Io(e)
+    }
+}
+
+impl From<BencodingParseError> for BencodingReadError {
+    fn from(e: BencodingParseError) -> BencodingReadError {
+        BencodingReadError::Parse(e)
+    }
+}
+
+/// Reads one complete value at a time from `R`, keeping whatever it read past
+/// the end of a value for the next call.
+pub struct BencodingReader<R: Read> {
+    inner: R,
+    opts: ParseOptions,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> BencodingReader<R> {
+    pub fn new(inner: R) -> BencodingReader<R> {
+        BencodingReader::with_options(inner, ParseOptions::default())
+    }
+
+    pub fn with_options(inner: R, opts: ParseOptions) -> BencodingReader<R> {
+        BencodingReader{inner, opts, buf: Vec::new()}
+    }
+
+    /// The next value, or `None` if the stream ended cleanly between values.
+    ///
+    /// After an error the stream is out of step, so the reader shouldn't be
+    /// used again.
+    // not `Iterator::next`, since an I/O error isn't the end of the stream
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Bencoding>, BencodingReadError> {
+        loop {
+            if !self.buf.is_empty() {
+                match BencodingRef::parse_prefix(&self.buf, &self.opts) {
+                    Ok((bencoding, len)) => {
+                        let bencoding = bencoding.to_owned_in(&self.buf)?;
+                        self.buf.drain(..len);
+                        return Ok(Some(bencoding));
+                    },
+                    Err(e) if e.is_incomplete() => (),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let mut chunk = [0u8; READ_CHUNK];
+            let len = match self.inner.read(&mut chunk) {
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if len == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                // what's buffered can only be the incomplete start of a value
+                return Err(BencodingRef::parse_prefix(&self.buf, &self.opts).unwrap_err().into());
+            }
+            self.buf.extend_from_slice(&chunk[..len]);
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::bt::BencodingErrorKind;
+
+    // hands out its input a byte at a time, like a very slow socket
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((&byte, rest)) if !buf.is_empty() => {
+                    buf[0] = byte;
+                    self.0 = rest;
+                    Ok(1)
+                },
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bencoding_reader() {
+        let mut dict = HashMap::new();
+        dict.insert("t".to_string(), Bencoding::String("aa".to_string()));
+        dict.insert("y".to_string(), Bencoding::String("q".to_string()));
+        dict.insert("a".to_string(), Bencoding::List(vec![Bencoding::Int(1), Bencoding::Int(-2)]));
+        let dict = Bencoding::from(dict);
+
+        let input = b"d1:ali1ei-2ee1:t2:aa1:y1:qe4:spami42e";
+        let mut reader = BencodingReader::new(Trickle(input));
+        assert_eq!(Some(dict.clone()), reader.next().unwrap());
+        assert_eq!(Some(Bencoding::String("spam".to_string())), reader.next().unwrap());
+        assert_eq!(Some(Bencoding::Int(42)), reader.next().unwrap());
+        assert_eq!(None, reader.next().unwrap());
+
+        // all at once, the leftovers from the first read are kept for later calls
+        let mut reader = BencodingReader::new(&input[..]);
+        assert_eq!(Some(dict), reader.next().unwrap());
+        assert_eq!(Some(Bencoding::String("spam".to_string())), reader.next().unwrap());
+        assert_eq!(Some(Bencoding::Int(42)), reader.next().unwrap());
+        assert_eq!(None, reader.next().unwrap());
+    }
+
+    #[test]
+    fn test_bencoding_reader_errors() {
+        let mut reader = BencodingReader::new(Trickle(b"i1ei2"));
+        assert_eq!(Some(Bencoding::Int(1)), reader.next().unwrap());
+        match reader.next() {
+            Err(BencodingReadError::Parse(e)) => {
+                assert_eq!(BencodingParseError{offset: 2, kind: BencodingErrorKind::UnexpectedEof}, e);
+            },
+            other => panic!("expected the stream to end mid-value, got {:?}", other),
+        }
+
+        // without a limit this would wait for the rest of the string
+        let opts = ParseOptions{max_string_len: 1024, ..ParseOptions::default()};
+        let mut reader = BencodingReader::with_options(Trickle(b"999999999:abc"), opts);
+        match reader.next() {
+            Err(BencodingReadError::Parse(e)) => {
+                assert_eq!(BencodingParseError{offset: 0, kind: BencodingErrorKind::InvalidLength}, e);
+            },
+            other => panic!("expected the length to be refused, got {:?}", other),
+        }
+
+        let mut reader = BencodingReader::new(Trickle(b"l1:ax"));
+        match reader.next() {
+            Err(BencodingReadError::Parse(e)) => {
+                assert_eq!(BencodingParseError{offset: 4, kind: BencodingErrorKind::UnexpectedByte}, e);
+            },
+            other => panic!("expected a malformed list, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! A Kademlia routing table of k-buckets, as used by the BitTorrent DHT (BEP 5).
+
+use std::{net::SocketAddr, time::{Duration, Instant}};
+
+use super::NodeId;
+use crate::clock::{Clock, SystemClock};
+
+/// The most nodes a single bucket holds, `k` in the Kademlia paper.
+pub const BUCKET_SIZE: usize = 8;
+
+/// How long a bucket can go without activity before it should be refreshed.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// A node that turned up for a full bucket. It takes the place of `to_ping`,
+/// the node in that bucket seen least recently, only if `to_ping` doesn't
+/// answer a ping: long-lived nodes are the likeliest to stay up.
+///
+/// Report the outcome with `RoutingTable::mark_alive` or `confirm_dead`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Replacement {
+    pub candidate: (NodeId, SocketAddr),
+    pub to_ping: (NodeId, SocketAddr),
+}
+
+struct Bucket {
+    /// Least recently seen first.
+    nodes: Vec<(NodeId, SocketAddr)>,
+    /// When a node was last added or seen, or the bucket was touched.
+    last_active: Instant,
+    /// The replacement waiting on a ping, if the bucket is full.
+    pending: Option<Replacement>,
+}
+
+impl Bucket {
+    fn new(last_active: Instant) -> Bucket {
+        Bucket{nodes: Vec::new(), last_active, pending: None}
+    }
+
+    fn position(&self, id: &NodeId) -> Option<usize> {
+        self.nodes.iter().position(|(node_id, _)| node_id == id)
+    }
+}
+
+/// Known DHT nodes, bucketed by how many leading bits they share with our own ID.
+///
+/// Bucket `n` holds nodes whose `bucket_index` from `own_id` is exactly `n`,
+/// except the last bucket, which holds everything from its index on and so
+/// always covers `own_id` itself. Only that last bucket is split when it
+/// overflows; a full bucket anywhere else offers a `Replacement` instead.
+///
+/// Each bucket remembers when it last saw activity, going by `C`, so that
+/// quiet ones can be refreshed.
+pub struct RoutingTable<C: Clock = SystemClock> {
+    clock: C,
+    own_id: NodeId,
+    buckets: Vec<Bucket>,
+}
+
+impl RoutingTable<SystemClock> {
+    pub fn new(own_id: NodeId) -> RoutingTable<SystemClock> {
+        RoutingTable::with_clock(own_id, SystemClock)
+    }
+}
+
+impl<C: Clock> RoutingTable<C> {
+    pub fn with_clock(own_id: NodeId, clock: C) -> RoutingTable<C> {
+        let now = clock.now();
+        RoutingTable{clock, own_id, buckets: vec![Bucket::new(now)]}
+    }
+
+    pub fn own_id(&self) -> &NodeId {
+        &self.own_id
+    }
+
+    /// The number of nodes in the table.
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.nodes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.iter().all(|bucket| bucket.nodes.is_empty())
+    }
+
+    pub fn contains(&self, id: &NodeId) -> bool {
+        self.buckets[self.bucket_for(id)].position(id).is_some()
+    }
+
+    /// Inserts a node, or if it's already known, updates its address and
+    /// counts it as just seen. Our own ID is never added.
+    ///
+    /// A node whose bucket is full isn't added either; it's returned as a
+    /// `Replacement` for the bucket's stalest node, which supersedes any
+    /// earlier one still waiting on a ping.
+    pub fn add_node(&mut self, id: NodeId, addr: SocketAddr) -> Option<Replacement> {
+        if id == self.own_id {
+            return None;
+        }
+        loop {
+            let n = self.bucket_for(&id);
+            let splittable = n == self.buckets.len() - 1 && n < 159;
+            let now = self.clock.now();
+            let bucket = &mut self.buckets[n];
+            if let Some(pos) = bucket.position(&id) {
+                bucket.nodes.remove(pos);
+                bucket.nodes.push((id, addr));
+                bucket.last_active = now;
+                return None;
+            }
+            if bucket.nodes.len() < BUCKET_SIZE {
+                bucket.nodes.push((id, addr));
+                bucket.last_active = now;
+                return None;
+            }
+            if !splittable {
+                let replacement = Replacement{candidate: (id, addr), to_ping: bucket.nodes[0].clone()};
+                bucket.pending = Some(replacement.clone());
+                return Some(replacement);
+            }
+            self.split_last_bucket();
+        }
+    }
+
+    /// Records that `id` answered, which makes it the most recently seen node
+    /// in its bucket. If it was the one a `Replacement` was waiting on, the
+    /// candidate is dropped.
+    pub fn mark_alive(&mut self, id: &NodeId) {
+        let now = self.clock.now();
+        let n = self.bucket_for(id);
+        let bucket = &mut self.buckets[n];
+        if let Some(pos) = bucket.position(id) {
+            let node = bucket.nodes.remove(pos);
+            bucket.nodes.push(node);
+            bucket.last_active = now;
+        }
+        if matches!(&bucket.pending, Some(pending) if pending.to_ping.0 == *id) {
+            bucket.pending = None;
+        }
+    }
+
+    /// Removes `id`, which failed to answer. If a `Replacement` was waiting
+    /// on it, the candidate takes its place.
+    pub fn confirm_dead(&mut self, id: &NodeId) {
+        let now = self.clock.now();
+        let n = self.bucket_for(id);
+        let bucket = &mut self.buckets[n];
+        if let Some(pos) = bucket.position(id) {
+            bucket.nodes.remove(pos);
+        }
+        if matches!(&bucket.pending, Some(pending) if pending.to_ping.0 == *id) {
+            let Replacement{candidate, ..} = bucket.pending.take().unwrap();
+            if bucket.position(&candidate.0).is_none() && bucket.nodes.len() < BUCKET_SIZE {
+                bucket.nodes.push(candidate);
+                bucket.last_active = now;
+            }
+        }
+    }
+
+    /// Up to `k` known nodes, nearest to `target` first.
+    pub fn find_closest(&self, target: &NodeId, k: usize) -> Vec<(NodeId, SocketAddr)> {
+        let mut nodes: Vec<&(NodeId, SocketAddr)> = self.buckets.iter().flat_map(|bucket| &bucket.nodes).collect();
+        nodes.sort_unstable_by(|(id1, _), (id2, _)| target.cmp_distance(id1, id2));
+        return nodes.into_iter().take(k).cloned().collect();
+    }
+
+    /// Marks the bucket covering `id` as active, as when a lookup in its
+    /// range has just finished.
+    pub fn touch(&mut self, id: &NodeId) {
+        let n = self.bucket_for(id);
+        self.buckets[n].last_active = self.clock.now();
+    }
+
+    /// A random ID to look up for each bucket that's gone `REFRESH_INTERVAL`
+    /// without activity as of `now`, which fills the bucket with whatever
+    /// nodes the lookup turns up.
+    pub fn buckets_needing_refresh(&self, now: Instant) -> Vec<NodeId> {
+        return (0..self.buckets.len())
+            .filter(|&n| now.saturating_duration_since(self.buckets[n].last_active) >= REFRESH_INTERVAL)
+            .map(|n| self.random_id_in(n))
+            .collect();
+    }
+
+    // a random ID that `bucket_for` puts in bucket `n`
+    fn random_id_in(&self, n: usize) -> NodeId {
+        if n == self.buckets.len() - 1 {
+            return NodeId::random_in_bucket(&self.own_id, n);
+        }
+        // sharing exactly `n` leading bits means differing in the next one
+        let NodeId(mut id) = NodeId::random_in_bucket(&self.own_id, n + 1);
+        id[n / 8] ^= 0x80 >> (n % 8);
+        return NodeId(id);
+    }
+
+    fn bucket_for(&self, id: &NodeId) -> usize {
+        self.own_id.bucket_index(id).min(self.buckets.len() - 1)
+    }
+
+    // moves the nodes that share one more bit with our own ID into a new last
+    // bucket; only a full last bucket is split, and that never has a pending
+    // replacement
+    fn split_last_bucket(&mut self) {
+        let n = self.buckets.len() - 1;
+        let own_id = &self.own_id;
+        let (stay, far): (Vec<_>, Vec<_>) = self.buckets[n].nodes.drain(..)
+            .partition(|(id, _)| own_id.bucket_index(id) == n);
+        self.buckets[n].nodes = stay;
+        let mut next = Bucket::new(self.buckets[n].last_active);
+        next.nodes = far;
+        self.buckets.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::testing::FakeClock;
+
+    // an ID that differs from all-zeroes in the given bit, plus some low-order noise
+    fn node_at_bit(bit: usize, noise: u8) -> NodeId {
+        let mut id = [0u8; 20];
+        id[bit / 8] |= 0x80 >> (bit % 8);
+        id[19] ^= noise;
+        NodeId(id)
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_routing_table_bucket_capacity() {
+        let mut table = RoutingTable::new(NodeId([0u8; 20]));
+        for noise in 0..20 {
+            table.add_node(node_at_bit(0, noise), addr(noise as u16));
+        }
+        assert_eq!(BUCKET_SIZE, table.len());
+        assert!(table.contains(&node_at_bit(0, 0)));
+        assert!(!table.contains(&node_at_bit(0, 19)));
+
+        // re-adding a known node only updates its address
+        table.add_node(node_at_bit(0, 0), addr(6881));
+        assert_eq!(BUCKET_SIZE, table.len());
+        assert_eq!(vec![(node_at_bit(0, 0), addr(6881))], table.find_closest(&node_at_bit(0, 0), 1));
+
+        // the buckets nearer our own ID keep splitting, so they all have room
+        for bit in 1..40 {
+            for noise in 0..BUCKET_SIZE as u8 {
+                table.add_node(node_at_bit(bit, noise), addr(bit as u16));
+            }
+        }
+        assert_eq!(40 * BUCKET_SIZE, table.len());
+
+        table.add_node(NodeId([0u8; 20]), addr(1));
+        assert!(!table.contains(&NodeId([0u8; 20])));
+    }
+
+    #[test]
+    fn test_routing_table_find_closest() {
+        let mut table = RoutingTable::new(NodeId::random());
+        assert!(table.is_empty());
+        let mut ids = Vec::new();
+        for n in 0..200u16 {
+            let id = NodeId::random_in_bucket(table.own_id(), (n % 20) as usize);
+            table.add_node(id.clone(), addr(n));
+            if table.contains(&id) {
+                ids.push(id);
+            }
+        }
+        assert_eq!(ids.len(), table.len());
+        let target = NodeId::random();
+        let closest = table.find_closest(&target, BUCKET_SIZE);
+        let closest_ids: Vec<NodeId> = closest.into_iter().map(|(id, _)| id).collect();
+        assert_eq!(target.k_closest(&ids, BUCKET_SIZE), closest_ids);
+        assert_eq!(ids.len(), table.find_closest(&target, 1000).len());
+    }
+
+    #[test]
+    fn test_routing_table_refresh() {
+        let clock = FakeClock::new();
+        let mut table = RoutingTable::with_clock(NodeId([0u8; 20]), clock.clone());
+        assert!(table.buckets_needing_refresh(clock.now()).is_empty());
+
+        // a full bucket 0 is split off from the rest
+        for noise in 0..=BUCKET_SIZE as u8 {
+            table.add_node(node_at_bit(0, noise), addr(noise as u16));
+        }
+        clock.advance(10 * 60);
+        table.add_node(node_at_bit(1, 0), addr(1));
+        clock.advance(6 * 60);
+
+        // only bucket 0 has been quiet for long enough
+        let targets = table.buckets_needing_refresh(clock.now());
+        assert_eq!(1, targets.len());
+        assert_eq!(0, table.own_id().bucket_index(&targets[0]));
+        table.touch(&targets[0]);
+        assert!(table.buckets_needing_refresh(clock.now()).is_empty());
+
+        // the last bucket covers everything from its index on
+        clock.advance(REFRESH_INTERVAL.as_secs());
+        let targets = table.buckets_needing_refresh(clock.now());
+        assert_eq!(2, targets.len());
+        assert_eq!(0, table.own_id().bucket_index(&targets[0]));
+        assert!(table.own_id().bucket_index(&targets[1]) >= 1);
+        for _ in 0..20 {
+            let target = &table.buckets_needing_refresh(clock.now())[0];
+            assert_eq!(0, table.own_id().bucket_index(target));
+        }
+    }
+
+    #[test]
+    fn test_routing_table_replacement() {
+        let mut table = RoutingTable::new(NodeId([0u8; 20]));
+        for noise in 0..BUCKET_SIZE as u8 {
+            assert_eq!(None, table.add_node(node_at_bit(0, noise), addr(noise as u16)));
+        }
+        // splits off bucket 0, and the new node goes in bucket 1
+        assert_eq!(None, table.add_node(node_at_bit(1, 0), addr(100)));
+
+        // bucket 0 is full, so the newcomer waits on its stalest node
+        let replacement = table.add_node(node_at_bit(0, 100), addr(200));
+        let expected = Replacement{candidate: (node_at_bit(0, 100), addr(200)), to_ping: (node_at_bit(0, 0), addr(0))};
+        assert_eq!(Some(expected), replacement);
+        assert!(!table.contains(&node_at_bit(0, 100)));
+
+        // which answers, and so is kept and moves to the back of the line
+        table.mark_alive(&node_at_bit(0, 0));
+        table.confirm_dead(&node_at_bit(0, 0));
+        assert!(!table.contains(&node_at_bit(0, 100)));
+        assert_eq!(BUCKET_SIZE, table.len());
+        table.add_node(node_at_bit(0, 0), addr(0));
+
+        // seeing a node again also moves it back, so the next to ping is node 2
+        assert_eq!(None, table.add_node(node_at_bit(0, 1), addr(1)));
+        let replacement = table.add_node(node_at_bit(0, 101), addr(201)).unwrap();
+        assert_eq!((node_at_bit(0, 2), addr(2)), replacement.to_ping);
+
+        // a dead node gives up its place to the candidate
+        table.confirm_dead(&replacement.to_ping.0);
+        assert!(!table.contains(&node_at_bit(0, 2)));
+        assert!(table.contains(&node_at_bit(0, 101)));
+        assert_eq!(BUCKET_SIZE + 1, table.len());
+        assert_eq!(
+            Some((node_at_bit(0, 3), addr(3))),
+            table.add_node(node_at_bit(0, 102), addr(202)).map(|replacement| replacement.to_ping),
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use std::{collections::HashMap, convert::TryInto};
+
+use num_bigint::BigInt;
+use serde::ser::{self, Serialize};
+
+use super::{Bencoding, BencodingSerdeError};
+
+/// Converts any `Serialize` value into a `Bencoding`.
+///
+/// Structs and maps become dictionaries, sequences and tuples become lists,
+/// and integers and booleans become integers. `None` and unit values have no
+/// bencoded form; they are omitted from dictionaries and rejected elsewhere.
+pub fn to_bencoding<T: Serialize + ?Sized>(value: &T) -> Result<Bencoding, BencodingSerdeError> {
+    match value.serialize(Serializer)? {
+        Some(bencoding) => Ok(bencoding),
+        None => Err(BencodingSerdeError::new("value has no bencoded representation")),
+    }
+}
+
+impl ser::Error for BencodingSerdeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        BencodingSerdeError::new(msg)
+    }
+}
+
+impl Serialize for Bencoding {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Bencoding::Int(n) => serializer.serialize_i64(*n),
+            Bencoding::Integer(n) => {
+                let n_i128: i128 = match n.try_into() {
+                    Ok(v) => v,
+                    Err(_) => return Err(ser::Error::custom("integer out of range")),
+                };
+                serializer.serialize_i128(n_i128)
+            },
+            Bencoding::String(s) => serializer.serialize_str(s),
+            Bencoding::Bytes(b) => serializer.serialize_bytes(b),
+            Bencoding::List(elems) => serializer.collect_seq(elems),
+            Bencoding::Dictionary(dict) => {
+                let mut keys: Vec<&String> = dict.keys().collect();
+                keys.sort();
+                serializer.collect_map(keys.into_iter().map(|k| (k, &dict[k])))
+            },
+        }
+    }
+}
+
+/// Serializes into `Some(Bencoding)`, or `None` for values that should be
+/// left out of the enclosing dictionary.
+struct Serializer;
+
+type SerializeResult = Result<Option<Bencoding>, BencodingSerdeError>;
+
+fn integer<T: Into<i64>>(v: T) -> SerializeResult {
+    Ok(Some(Bencoding::Int(v.into())))
+}
+
+fn big_integer<T: Into<BigInt>>(v: T) -> SerializeResult {
+    Ok(Some(Bencoding::from_bigint(v.into())))
+}
+
+fn required<T: Serialize + ?Sized>(value: &T) -> Result<Bencoding, BencodingSerdeError> {
+    match value.serialize(Serializer)? {
+        Some(bencoding) => Ok(bencoding),
+        None => Err(BencodingSerdeError::new("list elements must not be None or unit")),
+    }
+}
+
+fn variant_dict(variant: &'static str, value: Bencoding) -> Bencoding {
+    let mut dict = HashMap::new();
+    dict.insert(variant.to_string(), value);
+    Bencoding::from(dict)
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Bencoding>;
+    type Error = BencodingSerdeError;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeList;
+    type SerializeMap = SerializeDictionary;
+    type SerializeStruct = SerializeDictionary;
+    type SerializeStructVariant = SerializeDictionary;
+
+    fn serialize_bool(self, v: bool) -> SerializeResult { integer(v as u8) }
+    fn serialize_i8(self, v: i8) -> SerializeResult { integer(v) }
+    fn serialize_i16(self, v: i16) -> SerializeResult { integer(v) }
+    fn serialize_i32(self, v: i32) -> SerializeResult { integer(v) }
+    fn serialize_i64(self, v: i64) -> SerializeResult { integer(v) }
+    fn serialize_i128(self, v: i128) -> SerializeResult { big_integer(v) }
+    fn serialize_u8(self, v: u8) -> SerializeResult { integer(v) }
+    fn serialize_u16(self, v: u16) -> SerializeResult { integer(v) }
+    fn serialize_u32(self, v: u32) -> SerializeResult { integer(v) }
+    fn serialize_u64(self, v: u64) -> SerializeResult { big_integer(v) }
+    fn serialize_u128(self, v: u128) -> SerializeResult { big_integer(v) }
+
+    fn serialize_f32(self, _v: f32) -> SerializeResult {
+        Err(BencodingSerdeError::new("bencoding has no floating point type"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> SerializeResult {
+        Err(BencodingSerdeError::new("bencoding has no floating point type"))
+    }
+
+    fn serialize_char(self, v: char) -> SerializeResult {
+        Ok(Some(Bencoding::String(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> SerializeResult {
+        Ok(Some(Bencoding::String(v.to_string())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SerializeResult {
+        Ok(Some(Bencoding::from_raw_string(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> SerializeResult { Ok(None) }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> SerializeResult {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SerializeResult { Ok(None) }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> SerializeResult { Ok(None) }
+
+    fn serialize_unit_variant(
+        self, _name: &'static str, _index: u32, variant: &'static str,
+    ) -> SerializeResult {
+        Ok(Some(Bencoding::String(variant.to_string())))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self, _name: &'static str, value: &T,
+    ) -> SerializeResult {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self, _name: &'static str, _index: u32, variant: &'static str, value: &T,
+    ) -> SerializeResult {
+        Ok(Some(variant_dict(variant, required(value)?)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, BencodingSerdeError> {
+        Ok(SerializeList{elems: Vec::with_capacity(len.unwrap_or(0)), variant: None})
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, BencodingSerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self, _name: &'static str, len: usize,
+    ) -> Result<SerializeList, BencodingSerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self, _name: &'static str, _index: u32, variant: &'static str, len: usize,
+    ) -> Result<SerializeList, BencodingSerdeError> {
+        Ok(SerializeList{elems: Vec::with_capacity(len), variant: Some(variant)})
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeDictionary, BencodingSerdeError> {
+        Ok(SerializeDictionary{dict: HashMap::new(), next_key: None, variant: None})
+    }
+
+    fn serialize_struct(
+        self, _name: &'static str, len: usize,
+    ) -> Result<SerializeDictionary, BencodingSerdeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self, _name: &'static str, _index: u32, variant: &'static str, _len: usize,
+    ) -> Result<SerializeDictionary, BencodingSerdeError> {
+        Ok(SerializeDictionary{dict: HashMap::new(), next_key: None, variant: Some(variant)})
+    }
+}
+
+struct SerializeList {
+    elems: Vec<Bencoding>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeList {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodingSerdeError> {
+        self.elems.push(required(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> SerializeResult {
+        let list = Bencoding::List(self.elems);
+        Ok(Some(match self.variant {
+            Some(variant) => variant_dict(variant, list),
+            None => list,
+        }))
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Option<Bencoding>;
+    type Error = BencodingSerdeError;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodingSerdeError> {
+        self.push(value)
+    }
+    fn end(self) -> SerializeResult { self.finish() }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Option<Bencoding>;
+    type Error = BencodingSerdeError;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodingSerdeError> {
+        self.push(value)
+    }
+    fn end(self) -> SerializeResult { self.finish() }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Option<Bencoding>;
+    type Error = BencodingSerdeError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodingSerdeError> {
+        self.push(value)
+    }
+    fn end(self) -> SerializeResult { self.finish() }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+    type Ok = Option<Bencoding>;
+    type Error = BencodingSerdeError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodingSerdeError> {
+        self.push(value)
+    }
+    fn end(self) -> SerializeResult { self.finish() }
+}
+
+struct SerializeDictionary {
+    dict: HashMap<String, Bencoding>,
+    next_key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeDictionary {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), BencodingSerdeError> {
+        // absent values (e.g. `None` fields) are left out of the dictionary entirely
+        if let Some(bencoding) = value.serialize(Serializer)? {
+            self.dict.insert(key, bencoding);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> SerializeResult {
+        let dict = Bencoding::from(self.dict);
+        Ok(Some(match self.variant {
+            Some(variant) => variant_dict(variant, dict),
+            None => dict,
+        }))
+    }
+}
+
+impl ser::SerializeMap for SerializeDictionary {
+    type Ok = Option<Bencoding>;
+    type Error = BencodingSerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), BencodingSerdeError> {
+        self.next_key = match key.serialize(Serializer)? {
+            Some(Bencoding::String(k)) => Some(k),
+            Some(Bencoding::Bytes(k)) => match String::from_utf8(k) {
+                Ok(k) => Some(k),
+                Err(_) => return Err(BencodingSerdeError::new("dictionary keys must be UTF-8")),
+            },
+            _ => return Err(BencodingSerdeError::new("dictionary keys must be strings")),
+        };
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodingSerdeError> {
+        let key = match self.next_key.take() {
+            Some(k) => k,
+            None => return Err(BencodingSerdeError::new("serialize_value called before serialize_key")),
+        };
+        self.insert(key, value)
+    }
+
+    fn end(self) -> SerializeResult { self.finish() }
+}
+
+impl ser::SerializeStruct for SerializeDictionary {
+    type Ok = Option<Bencoding>;
+    type Error = BencodingSerdeError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self, key: &'static str, value: &T,
+    ) -> Result<(), BencodingSerdeError> {
+        self.insert(key.to_string(), value)
+    }
+    fn end(self) -> SerializeResult { self.finish() }
+}
+
+impl ser::SerializeStructVariant for SerializeDictionary {
+    type Ok = Option<Bencoding>;
+    type Error = BencodingSerdeError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self, key: &'static str, value: &T,
+    ) -> Result<(), BencodingSerdeError> {
+        self.insert(key.to_string(), value)
+    }
+    fn end(self) -> SerializeResult { self.finish() }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{bencode, bt::from_bencoding};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct File {
+        length: u64,
+        path: Vec<String>,
+        #[serde(with = "serde_bytes")]
+        md5sum: Vec<u8>,
+        comment: Option<String>,
+    }
+
+    #[test]
+    fn test_to_bencoding_struct() {
+        let file = File {
+            length: 31337,
+            path: vec!["dir".to_string(), "file.txt".to_string()],
+            md5sum: vec![0xFF, 0x00, 0xFE],
+            comment: None,
+        };
+        let bencoding = to_bencoding(&file).unwrap();
+        assert_eq!(
+            &b"d6:lengthi31337e6:md5sum3:\xFF\x00\xFE4:pathl3:dir8:file.txtee"[..],
+            &bencoding.encode()[..],
+        );
+        assert_eq!(file, from_bencoding(bencoding).unwrap());
+    }
+
+    #[test]
+    fn test_to_bencoding_rejects_unrepresentable() {
+        assert!(to_bencoding(&1.5f64).is_err());
+        assert!(to_bencoding(&None::<u8>).is_err());
+        assert!(to_bencoding(&vec![Some(1), None]).is_err());
+
+        // byte string keys are fine as long as they'd survive as a `String`
+        let mut map = std::collections::BTreeMap::new();
+        map.insert(Bencoding::from(b"ok".to_vec()), 1);
+        assert_eq!(Ok(bencode!({ "ok" => 1 })), to_bencoding(&map));
+        map.insert(Bencoding::from(b"\xFF".to_vec()), 2);
+        assert!(to_bencoding(&map).is_err());
+    }
+
+    #[test]
+    fn test_bencoding_serialize_passthrough() {
+        let bencoding = Bencoding::from_slice(b"d3:cowl3:mooi-1ee4:spam2:\xC3(e").unwrap();
+        assert_eq!(bencoding, to_bencoding(&bencoding).unwrap());
+    }
+}
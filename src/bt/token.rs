@@ -0,0 +1,115 @@
+//! The opaque tokens a DHT node hands out in `get_peers` responses and
+//! checks again in `announce_peer`, so that a node can only announce from
+//! the address it asked from (BEP 5).
+
+use std::{net::IpAddr, time::{Duration, Instant}};
+
+use sha1::{Digest, Sha1};
+
+use crate::clock::{Clock, SystemClock};
+
+/// How long a secret is used for before it's replaced. Tokens made with it
+/// are accepted for up to twice as long.
+pub const TOKEN_ROTATION: Duration = Duration::from_secs(5 * 60);
+
+/// Makes tokens as the SHA-1 of a secret and the requester's address, which
+/// needs no per-requester state to check.
+///
+/// The secret is replaced every `TOKEN_ROTATION`, and the one before it is
+/// still accepted, so a token stays good for at least that long after it's
+/// issued.
+pub struct TokenGenerator<C: Clock = SystemClock> {
+    clock: C,
+    secret: [u8; 20],
+    previous: [u8; 20],
+    rotated_at: Instant,
+}
+
+impl TokenGenerator<SystemClock> {
+    pub fn new() -> TokenGenerator<SystemClock> {
+        TokenGenerator::with_clock(SystemClock)
+    }
+}
+
+impl Default for TokenGenerator<SystemClock> {
+    fn default() -> TokenGenerator<SystemClock> {
+        TokenGenerator::new()
+    }
+}
+
+fn make_token(secret: &[u8; 20], ip: IpAddr) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(secret);
+    match ip {
+        IpAddr::V4(ip) => hasher.update(ip.octets()),
+        IpAddr::V6(ip) => hasher.update(ip.octets()),
+    }
+    return hasher.finalize().to_vec();
+}
+
+impl<C: Clock> TokenGenerator<C> {
+    pub fn with_clock(clock: C) -> TokenGenerator<C> {
+        let rotated_at = clock.now();
+        TokenGenerator{clock, secret: rand::random(), previous: rand::random(), rotated_at}
+    }
+
+    // brings the secrets up to date; after two rotations' worth of idling
+    // neither of the old ones should be accepted any more
+    fn rotate(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now - self.rotated_at;
+        if elapsed < TOKEN_ROTATION {
+            return;
+        }
+        self.previous = if elapsed < TOKEN_ROTATION * 2 { self.secret } else { rand::random() };
+        self.secret = rand::random();
+        self.rotated_at = now;
+    }
+
+    /// The token to give a node at `ip` in a `get_peers` response.
+    pub fn issue(&mut self, ip: IpAddr) -> Vec<u8> {
+        self.rotate();
+        return make_token(&self.secret, ip);
+    }
+
+    /// Whether `token` is one recently issued to `ip`.
+    pub fn validate(&mut self, ip: IpAddr, token: &[u8]) -> bool {
+        self.rotate();
+        return token == &make_token(&self.secret, ip)[..] || token == &make_token(&self.previous, ip)[..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::testing::FakeClock;
+
+    #[test]
+    fn test_token_generator() {
+        let clock = FakeClock::new();
+        let mut tokens = TokenGenerator::with_clock(clock.clone());
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let token = tokens.issue(ip);
+        assert!(tokens.validate(ip, &token));
+        assert!(!tokens.validate("192.0.2.2".parse().unwrap(), &token));
+        assert!(!tokens.validate(ip, &token[1..]));
+        assert!(!tokens.validate(ip, b""));
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        let v6_token = tokens.issue(v6);
+        assert!(tokens.validate(v6, &v6_token));
+
+        // still good after one rotation, under the previous secret
+        clock.advance(TOKEN_ROTATION.as_secs());
+        assert!(tokens.validate(ip, &token));
+        assert_ne!(token, tokens.issue(ip));
+
+        // but not after a second
+        clock.advance(TOKEN_ROTATION.as_secs());
+        assert!(!tokens.validate(ip, &token));
+
+        // nor if nothing happened for long enough that both secrets went stale
+        let token = tokens.issue(ip);
+        clock.advance(TOKEN_ROTATION.as_secs() * 2);
+        assert!(!tokens.validate(ip, &token));
+    }
+}
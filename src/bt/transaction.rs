@@ -0,0 +1,181 @@
+//! Bookkeeping for the KRPC queries a DHT node has sent and not yet heard
+//! back about, keyed by the transaction ID `t` that responses echo (BEP 5).
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::clock::{Clock, SystemClock};
+
+/// How long a query waits for its response before it's given up on.
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Pending<T> {
+    addr: SocketAddr,
+    sent_at: Instant,
+    context: T,
+}
+
+/// Outstanding queries, each with a two-byte transaction ID of its own and
+/// whatever `T` the caller needs to pick up where it left off when the
+/// response comes in.
+///
+/// IDs are handed out in sequence from a random start, skipping any still in
+/// use, so one isn't reused until 65536 more queries have gone out. A
+/// response only matches if it comes from the address the query went to, so
+/// another node can't answer in its place by guessing IDs.
+pub struct TransactionTable<T, C: Clock = SystemClock> {
+    clock: C,
+    next_id: u16,
+    pending: HashMap<u16, Pending<T>>,
+}
+
+impl<T> TransactionTable<T, SystemClock> {
+    pub fn new() -> TransactionTable<T, SystemClock> {
+        TransactionTable::with_clock(SystemClock)
+    }
+}
+
+impl<T> Default for TransactionTable<T, SystemClock> {
+    fn default() -> TransactionTable<T, SystemClock> {
+        TransactionTable::new()
+    }
+}
+
+impl<T, C: Clock> TransactionTable<T, C> {
+    pub fn with_clock(clock: C) -> TransactionTable<T, C> {
+        TransactionTable{clock, next_id: rand::random(), pending: HashMap::new()}
+    }
+
+    /// The number of queries awaiting a response, including any that have
+    /// timed out but haven't been `expire`d yet.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Records a query about to be sent to `addr`, returning the transaction
+    /// ID to send it with, or `None` if every ID is already in use.
+    pub fn issue(&mut self, addr: SocketAddr, context: T) -> Option<Vec<u8>> {
+        if self.pending.len() > u16::MAX as usize {
+            return None;
+        }
+        while self.pending.contains_key(&self.next_id) {
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.insert(id, Pending{addr, sent_at: self.clock.now(), context});
+        return Some(id.to_be_bytes().to_vec());
+    }
+
+    /// Takes back the context of the query a response from `addr` with
+    /// `transaction_id` answers. Anything that doesn't match a query still
+    /// in time, from an unknown ID to the wrong sender, gets `None` and
+    /// leaves the table alone.
+    pub fn resolve(&mut self, transaction_id: &[u8], addr: SocketAddr) -> Option<T> {
+        let id = match transaction_id {
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            _ => return None,
+        };
+        let pending = self.pending.get(&id)?;
+        if pending.addr != addr || self.clock.now() - pending.sent_at >= TRANSACTION_TIMEOUT {
+            return None;
+        }
+        return self.pending.remove(&id).map(|pending| pending.context);
+    }
+
+    /// Drops every query that's gone `TRANSACTION_TIMEOUT` without a
+    /// response, returning where each was sent and its context so that the
+    /// caller can count the node as having failed to answer.
+    pub fn expire(&mut self) -> Vec<(SocketAddr, T)> {
+        let now = self.clock.now();
+        let expired: Vec<u16> = self.pending.iter()
+            .filter(|(_, pending)| now - pending.sent_at >= TRANSACTION_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+        return expired.into_iter()
+            .filter_map(|id| self.pending.remove(&id))
+            .map(|pending| (pending.addr, pending.context))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::testing::FakeClock;
+
+    fn table() -> (FakeClock, TransactionTable<&'static str, FakeClock>) {
+        let clock = FakeClock::new();
+        return (clock.clone(), TransactionTable::with_clock(clock));
+    }
+
+    #[test]
+    fn test_transaction_table_resolve() {
+        let (_, mut table) = table();
+        let a: SocketAddr = "192.0.2.1:6881".parse().unwrap();
+        let b: SocketAddr = "[2001:db8::1]:6881".parse().unwrap();
+        let ping = table.issue(a, "ping").unwrap();
+        let find_node = table.issue(b, "find_node").unwrap();
+        assert_eq!(2, ping.len());
+        assert_ne!(ping, find_node);
+        assert_eq!(2, table.len());
+
+        // only the node asked can answer, and only once
+        assert_eq!(None, table.resolve(&ping, b));
+        assert_eq!(None, table.resolve(b"x", a));
+        assert_eq!(None, table.resolve(b"xyz", a));
+        assert_eq!(Some("ping"), table.resolve(&ping, a));
+        assert_eq!(None, table.resolve(&ping, a));
+        assert_eq!(Some("find_node"), table.resolve(&find_node, b));
+        assert!(table.is_empty());
+
+        // IDs still in use are skipped over when the counter comes back round
+        let held = table.issue(a, "held").unwrap();
+        for _ in 0..u16::MAX {
+            let id = table.issue(a, "other").unwrap();
+            assert_ne!(held, id);
+            table.resolve(&id, a).unwrap();
+        }
+        assert_eq!(Some("held"), table.resolve(&held, a));
+    }
+
+    #[test]
+    fn test_transaction_table_full() {
+        let (_, mut table) = table();
+        let a: SocketAddr = "192.0.2.1:6881".parse().unwrap();
+        let ids: Vec<Vec<u8>> = (0..=u16::MAX).map(|_| table.issue(a, "query").unwrap()).collect();
+        assert_eq!(None, table.issue(a, "one too many"));
+        table.resolve(&ids[100], a).unwrap();
+        assert_eq!(Some(ids[100].clone()), table.issue(a, "query"));
+    }
+
+    #[test]
+    fn test_transaction_table_expire() {
+        let (clock, mut table) = table();
+        let a: SocketAddr = "192.0.2.1:6881".parse().unwrap();
+        let old = table.issue(a, "old").unwrap();
+        clock.advance(TRANSACTION_TIMEOUT.as_secs() - 1);
+        let new = table.issue(a, "new").unwrap();
+        assert!(table.expire().is_empty());
+
+        // a late response doesn't count, even before the query is expired
+        clock.advance(1);
+        assert_eq!(None, table.resolve(&old, a));
+        assert_eq!(2, table.len());
+        assert_eq!(vec![(a, "old")], table.expire());
+        assert_eq!(1, table.len());
+        assert_eq!(Some("new"), table.resolve(&new, a));
+
+        table.issue(a, "forgotten").unwrap();
+        clock.advance(TRANSACTION_TIMEOUT.as_secs() * 2);
+        assert_eq!(vec![(a, "forgotten")], table.expire());
+        assert!(table.is_empty());
+    }
+}
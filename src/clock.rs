@@ -0,0 +1,43 @@
+//! Where code that expires things gets the current time from, so that tests
+//! can move it forward instead of sleeping.
+
+use std::time::Instant;
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{cell::Cell, rc::Rc, time::{Duration, Instant}};
+
+    use super::Clock;
+
+    /// A clock that only moves when it's told to. Clones share the one time,
+    /// so a test can keep a clone and advance what it handed out.
+    #[derive(Clone)]
+    pub(crate) struct FakeClock(Rc<Cell<Instant>>);
+
+    impl FakeClock {
+        pub(crate) fn new() -> FakeClock {
+            FakeClock(Rc::new(Cell::new(Instant::now())))
+        }
+
+        pub(crate) fn advance(&self, secs: u64) {
+            self.0.set(self.0.get() + Duration::from_secs(secs));
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+}
@@ -1,37 +1,576 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, convert::TryFrom, fmt, net::{IpAddr, Ipv6Addr}};
 
 use ipnet::Ipv4Net;
 use containers::collections::b_tree::BTree;
 
-type DomainName = String;
-type DomainLabel = String;
+mod cache;
+pub mod hosts;
+mod iterative;
+mod name;
+pub mod resolv_conf;
+mod resolver;
+mod server;
+mod wire;
+pub mod zone;
+
+pub use cache::RecordCache;
+pub use iterative::IterativeResolver;
+pub use name::{DomainName, DomainNameError, MAX_LABEL_LEN, MAX_NAME_LEN};
+pub use resolver::{Resolution, ResolveError, Resolver};
+pub use server::StaticNameServer;
+pub use wire::{DnsEncodeError, DnsParseError};
+
+/// The TTL of the records `DnsMessage::answer` puts in a response.
+pub const ANSWER_TTL: u32 = 3600;
 
 // "RR"
-enum ResourceRecord {
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ResourceRecord {
     HostAddress(Ipv4Net),
-    MailExchanger(DomainName),
+    HostAddressV6(Ipv6Addr),
+    /// Clients try exchangers with a lower `preference` first.
+    MailExchanger { preference: u16, exchange: DomainName },
     NameServer(DomainName),
-    StartOfAuthority(DomainName),
+    StartOfAuthority {
+        /// The zone's primary name server.
+        mname: DomainName,
+        /// The mailbox of the person responsible for the zone, with the `@` as a `.`.
+        rname: DomainName,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        /// The TTL for negative answers, per RFC 2308.
+        minimum: u32,
+    },
     CanonicalName(DomainName),
+    /// The name an address maps back to, owned by its `reverse_name`.
+    Pointer(DomainName),
+    /// The character-strings of a TXT record, in order. They're arbitrary
+    /// octets, though usually text.
+    Text(Vec<Vec<u8>>),
+    Service { priority: u16, weight: u16, port: u16, target: DomainName },
+    /// A public key a zone signs with (RFC 4034 2). Of the `flags`, 256 marks
+    /// a zone key and 1 a key-signing key.
+    DnsKey { flags: u16, protocol: u8, algorithm: u8, public_key: Vec<u8> },
+    /// A signature over its owner's records of type `type_covered` (RFC 4034 3).
+    Signature {
+        type_covered: u16,
+        algorithm: u8,
+        /// The number of labels in the owner name, not counting a leading `*`.
+        labels: u8,
+        original_ttl: u32,
+        /// Seconds since the epoch, modulo 2^32, after which the signature is void.
+        expiration: u32,
+        /// Seconds since the epoch, modulo 2^32, before which the signature is void.
+        inception: u32,
+        key_tag: u16,
+        signer_name: DomainName,
+        signature: Vec<u8>,
+    },
+    /// A digest of one of a child zone's DNSKEYs, held by its parent (RFC 4034 5).
+    DelegationSigner { key_tag: u16, algorithm: u8, digest_type: u8, digest: Vec<u8> },
+    /// The next name in the zone in canonical order, and the types of the
+    /// records there are for this one, in ascending order (RFC 4034 4).
+    NextSecure { next_name: DomainName, types: Vec<u16> },
+    /// RDATA of a type we don't interpret, kept as raw bytes.
+    Unknown { rtype: u16, data: Vec<u8> },
+}
+
+/// The fixed 12-byte header at the front of every DNS message (RFC 1035 4.1.1),
+/// minus the section counts, which are implied by the sections themselves.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DnsHeader {
+    pub id: u16,
+    /// Set on responses, clear on queries.
+    pub qr: bool,
+    pub opcode: u8,
+    pub aa: bool,
+    pub tc: bool,
+    pub rd: bool,
+    pub ra: bool,
+    pub rcode: u8,
+}
+
+impl DnsHeader {
+    pub fn is_response(&self) -> bool {
+        self.qr
+    }
+
+    /// Whether the responding server is an authority for the name asked about.
+    pub fn authoritative(&self) -> bool {
+        self.aa
+    }
+
+    /// Whether the message was cut short to fit the transport.
+    pub fn truncated(&self) -> bool {
+        self.tc
+    }
+
+    pub fn recursion_desired(&self) -> bool {
+        self.rd
+    }
+
+    pub fn recursion_available(&self) -> bool {
+        self.ra
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        Opcode::from(self.opcode)
+    }
+
+    pub fn set_opcode(&mut self, opcode: Opcode) {
+        self.opcode = opcode.into();
+    }
+
+    pub fn rcode(&self) -> Rcode {
+        Rcode::from(self.rcode)
+    }
+
+    pub fn set_rcode(&mut self, rcode: Rcode) {
+        self.rcode = rcode.into();
+    }
+}
+
+/// The kind of message (RFC 1035 4.1.1).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Opcode {
+    /// A standard query, which is almost everything.
+    Query,
+    /// An inverse query, long obsolete (RFC 3425).
+    IQuery,
+    Status,
+    /// A primary telling its secondaries that a zone changed, so that they
+    /// know to check its SOA serial (RFC 1996).
+    Notify,
+    /// A dynamic update to a zone's records (RFC 2136).
+    Update,
+    /// Any of the codes still unassigned.
+    Other(u8),
+}
+
+impl From<u8> for Opcode {
+    /// Only the low four bits are used, as that's all the header has room for.
+    fn from(code: u8) -> Opcode {
+        match code & 0xF {
+            0 => Opcode::Query,
+            1 => Opcode::IQuery,
+            2 => Opcode::Status,
+            4 => Opcode::Notify,
+            5 => Opcode::Update,
+            code => Opcode::Other(code),
+        }
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(opcode: Opcode) -> u8 {
+        match opcode {
+            Opcode::Query => 0,
+            Opcode::IQuery => 1,
+            Opcode::Status => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::Other(code) => code & 0xF,
+        }
+    }
+}
+
+/// A response code (RFC 1035 4.1.1).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Rcode {
+    NoError,
+    /// The server couldn't make sense of the query.
+    FormErr,
+    ServFail,
+    /// The name asked about doesn't exist.
+    NxDomain,
+    NotImp,
+    Refused,
+    /// Any of the codes RFC 1035 leaves reserved, which later RFCs assign.
+    Other(u8),
+}
+
+impl From<u8> for Rcode {
+    /// Only the low four bits are used, as that's all the header has room for.
+    fn from(code: u8) -> Rcode {
+        match code & 0xF {
+            0 => Rcode::NoError,
+            1 => Rcode::FormErr,
+            2 => Rcode::ServFail,
+            3 => Rcode::NxDomain,
+            4 => Rcode::NotImp,
+            5 => Rcode::Refused,
+            code => Rcode::Other(code),
+        }
+    }
+}
+
+impl From<Rcode> for u8 {
+    fn from(rcode: Rcode) -> u8 {
+        match rcode {
+            Rcode::NoError => 0,
+            Rcode::FormErr => 1,
+            Rcode::ServFail => 2,
+            Rcode::NxDomain => 3,
+            Rcode::NotImp => 4,
+            Rcode::Refused => 5,
+            Rcode::Other(code) => code & 0xF,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DnsQuestion {
+    pub name: DomainName,
+    pub qtype: QType,
+    pub qclass: QClass,
+}
+
+/// A resource record as it appears in the answer, authority, or additional section.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DnsRecord {
+    pub name: DomainName,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: ResourceRecord,
+}
+
+/// The EDNS(0) OPT pseudo-record (RFC 6891), which extends the header. On the
+/// wire it's a record owned by the root in the additional section, with these
+/// fields packed into its CLASS and TTL.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EdnsOpt {
+    /// The largest UDP payload the sender can take, in place of 512.
+    pub udp_payload_size: u16,
+    /// The high 8 bits of the 12-bit extended RCODE; the header has the low 4.
+    pub extended_rcode: u8,
+    pub version: u8,
+    /// DO: the sender wants DNSSEC records.
+    pub dnssec_ok: bool,
+    /// Each option's code and data, in order.
+    pub options: Vec<(u16, Vec<u8>)>,
+}
+
+impl EdnsOpt {
+    pub fn new(udp_payload_size: u16) -> EdnsOpt {
+        EdnsOpt{udp_payload_size, extended_rcode: 0, version: 0, dnssec_ok: false, options: Vec::new()}
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DnsMessage {
+    pub header: DnsHeader,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsRecord>,
+    pub authorities: Vec<DnsRecord>,
+    /// The additional section, less the OPT record, if any, which is `edns`.
+    pub additionals: Vec<DnsRecord>,
+    pub edns: Option<EdnsOpt>,
+}
+
+impl DnsMessage {
+    /// A recursive query for the `qtype` records of `name`, under a random ID.
+    pub fn query(name: &str, qtype: QType) -> DnsMessage {
+        DnsMessage{
+            header: DnsHeader{id: rand::random(), rd: true, ..DnsHeader::default()},
+            questions: vec![DnsQuestion{
+                name: name.trim_end_matches('.').into(),
+                qtype,
+                qclass: QClass::In,
+            }],
+            ..DnsMessage::default()
+        }
+    }
+
+    /// An authoritative response to `query` answering it with `records`, or
+    /// NXDOMAIN if there are none. Each record is owned by the name and class
+    /// of the query's first question and lives for `ANSWER_TTL` seconds, so a
+    /// query without a question gets none of them; see `answer_records` to
+    /// give them owners and TTLs of their own.
+    pub fn answer(query: &DnsMessage, records: Vec<ResourceRecord>) -> DnsMessage {
+        let records = match query.questions.first() {
+            Some(question) => records.into_iter()
+                .map(|data| DnsRecord{name: question.name.clone(), class: question.qclass.into(), ttl: ANSWER_TTL, data})
+                .collect(),
+            None => Vec::new(),
+        };
+        return DnsMessage::answer_records(query, records);
+    }
+
+    /// An authoritative response to `query` answering it with `records`, or
+    /// NXDOMAIN if there are none. The ID, opcode, question and RD flag are
+    /// copied over from the query.
+    pub fn answer_records(query: &DnsMessage, records: Vec<DnsRecord>) -> DnsMessage {
+        let mut header = DnsHeader{
+            id: query.header.id,
+            qr: true,
+            opcode: query.header.opcode,
+            aa: true,
+            rd: query.header.rd,
+            ..DnsHeader::default()
+        };
+        header.set_rcode(if records.is_empty() { Rcode::NxDomain } else { Rcode::NoError });
+        return DnsMessage{header, questions: query.questions.clone(), answers: records, ..DnsMessage::default()};
+    }
+
+    /// The largest UDP response the sender of this query can take: 512
+    /// bytes, or more if its OPT record says so (RFC 6891 6.2.5).
+    pub fn max_udp_payload(&self) -> usize {
+        let offered = self.edns.as_ref().map_or(0, |edns| edns.udp_payload_size);
+        return (offered as usize).max(512);
+    }
+
+    /// The full 12-bit RCODE, which only goes past 15 with EDNS.
+    pub fn extended_rcode(&self) -> u16 {
+        let high = self.edns.as_ref().map_or(0, |edns| edns.extended_rcode);
+        return (high as u16) << 4 | (self.header.rcode & 0xF) as u16;
+    }
+}
+
+/// The name a PTR lookup for `ip` asks about (RFC 1035 3.5, RFC 3596 2.5):
+/// the IPv4 octets or IPv6 nibbles in reverse under `in-addr.arpa` or `ip6.arpa`.
+pub fn reverse_name(ip: IpAddr) -> DomainName {
+    let name = match ip {
+        IpAddr::V4(ip) => {
+            let o = ip.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        },
+        IpAddr::V6(ip) => {
+            let mut name = String::with_capacity(72);
+            for byte in ip.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0xF, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        },
+    };
+    return name.into();
 }
 
-type NameServerDb = HashMap<DomainName, ResourceRecord>;
+/// Every record owned by each name, e.g. several A records and an MX, each
+/// with the TTL it's served with.
+pub type NameServerDb = HashMap<DomainName, Vec<(u32, ResourceRecord)>>;
 
 type QName = DomainName;
-enum QType {
-    MailAgent,
-    Glob,
+
+/// A TYPE or QTYPE code (RFC 1035 3.2.2, 3.2.3).
+///
+/// `from_code` takes any code, since a parser has to keep unknown types to
+/// be able to pass them on; `TryFrom<u16>` only takes the ones listed here.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum QType {
+    A,
+    Ns,
+    Cname,
+    Soa,
+    Ptr,
+    Mx,
+    Txt,
+    Aaaa,
+    Srv,
+    Ds,
+    Rrsig,
+    Nsec,
+    Dnskey,
+    /// Asks for a whole zone (RFC 5936); only meaningful in a question, over TCP.
+    Axfr,
+    /// Matches every type; only meaningful in a question.
+    Any,
+    /// Any type not listed here, such as HTTPS (65), kept so that it can be
+    /// asked about and written back out.
+    Other(u16),
+}
+
+/// A CLASS or QCLASS code (RFC 1035 3.2.4, 3.2.5).
+///
+/// As with `QType`, `from_code` takes any code and `TryFrom<u16>` only the
+/// ones listed here.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum QClass {
+    In,
+    Ch,
+    Hs,
+    /// Matches every class; only meaningful in a question.
+    Any,
+    /// Any class not listed here.
+    Other(u16),
+}
+
+/// A TYPE or CLASS code with no variant of its own, which `TryFrom<u16>`
+/// rejects for `QType` and `QClass`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UnknownCodeError(pub u16);
+
+impl fmt::Display for UnknownCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown DNS code {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownCodeError {}
+
+impl QType {
+    /// The type with `code`, or `Other` for any that isn't listed.
+    pub fn from_code(code: u16) -> QType {
+        match code {
+            1 => QType::A,
+            2 => QType::Ns,
+            5 => QType::Cname,
+            6 => QType::Soa,
+            12 => QType::Ptr,
+            15 => QType::Mx,
+            16 => QType::Txt,
+            28 => QType::Aaaa,
+            33 => QType::Srv,
+            43 => QType::Ds,
+            46 => QType::Rrsig,
+            47 => QType::Nsec,
+            48 => QType::Dnskey,
+            252 => QType::Axfr,
+            255 => QType::Any,
+            code => QType::Other(code),
+        }
+    }
+}
+
+impl TryFrom<u16> for QType {
+    type Error = UnknownCodeError;
+    fn try_from(code: u16) -> Result<QType, UnknownCodeError> {
+        match QType::from_code(code) {
+            QType::Other(code) => Err(UnknownCodeError(code)),
+            qtype => Ok(qtype),
+        }
+    }
+}
+
+impl From<QType> for u16 {
+    fn from(qtype: QType) -> u16 {
+        match qtype {
+            QType::A => 1,
+            QType::Ns => 2,
+            QType::Cname => 5,
+            QType::Soa => 6,
+            QType::Ptr => 12,
+            QType::Mx => 15,
+            QType::Txt => 16,
+            QType::Aaaa => 28,
+            QType::Srv => 33,
+            QType::Ds => 43,
+            QType::Rrsig => 46,
+            QType::Nsec => 47,
+            QType::Dnskey => 48,
+            QType::Axfr => 252,
+            QType::Any => 255,
+            QType::Other(code) => code,
+        }
+    }
+}
+
+impl QType {
+    /// The name the type goes by in zone files, e.g. `"AAAA"`, or `None`
+    /// for `Other` types.
+    pub fn mnemonic(&self) -> Option<&'static str> {
+        let mnemonic = match self {
+            QType::A => "A",
+            QType::Ns => "NS",
+            QType::Cname => "CNAME",
+            QType::Soa => "SOA",
+            QType::Ptr => "PTR",
+            QType::Mx => "MX",
+            QType::Txt => "TXT",
+            QType::Aaaa => "AAAA",
+            QType::Srv => "SRV",
+            QType::Ds => "DS",
+            QType::Rrsig => "RRSIG",
+            QType::Nsec => "NSEC",
+            QType::Dnskey => "DNSKEY",
+            QType::Axfr => "AXFR",
+            QType::Any => "ANY",
+            QType::Other(_) => return None,
+        };
+        return Some(mnemonic);
+    }
+}
+
+/// The mnemonic, or `TYPE<n>` (RFC 3597) for types without one.
+impl fmt::Display for QType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mnemonic() {
+            Some(mnemonic) => write!(f, "{}", mnemonic),
+            None => write!(f, "TYPE{}", u16::from(*self)),
+        }
+    }
+}
+
+impl ResourceRecord {
+    /// The record's type mnemonic, or `TYPE<n>` (RFC 3597) for types without one.
+    pub fn type_name(&self) -> String {
+        QType::from_code(self.rtype()).to_string()
+    }
+}
+
+impl QClass {
+    /// The class with `code`, or `Other` for any that isn't listed.
+    pub fn from_code(code: u16) -> QClass {
+        match code {
+            1 => QClass::In,
+            3 => QClass::Ch,
+            4 => QClass::Hs,
+            255 => QClass::Any,
+            code => QClass::Other(code),
+        }
+    }
 }
-enum QClass {
-    Glob,
+
+impl TryFrom<u16> for QClass {
+    type Error = UnknownCodeError;
+    fn try_from(code: u16) -> Result<QClass, UnknownCodeError> {
+        match QClass::from_code(code) {
+            QClass::Other(code) => Err(UnknownCodeError(code)),
+            qclass => Ok(qclass),
+        }
+    }
 }
 
-trait NameServer {
-    fn ord(&self, domain_name: DomainName) -> usize;
+impl From<QClass> for u16 {
+    fn from(qclass: QClass) -> u16 {
+        match qclass {
+            QClass::In => 1,
+            QClass::Ch => 3,
+            QClass::Hs => 4,
+            QClass::Any => 255,
+            QClass::Other(code) => code,
+        }
+    }
+}
+
+pub trait NameServer {
+    /// The number of labels in `domain_name`; the root has none.
+    fn ord(&self, domain_name: DomainName) -> usize {
+        domain_name.labels().count()
+    }
+    /// All of the records owned by `domain_name`, or `None` if it owns none.
     fn findset(&self, domain_name: DomainName) -> Option<Vec<ResourceRecord>>;
+    /// Whether a record whose type is named `type_` answers a `query_type` question.
     fn relevant(&self, query_type: QType, type_: String) -> bool;
-    fn right(&self, name: DomainName, number: usize) -> Option<DomainName>;
+    /// The ancestor of `name` `number` levels up, left once that many of its
+    /// labels are dropped from the left, or `None` if it has fewer: for
+    /// `a.b.c`, 0 gives `a.b.c` itself, 1 gives `b.c`, 2 gives `c` and 3 gives
+    /// the root. Counting down from `ord(name)` walks down from the root
+    /// the way an iterative resolver does.
+    fn right(&self, name: DomainName, number: usize) -> Option<DomainName> {
+        return (0..number).try_fold(name, |name, _| name.parent());
+    }
     fn copy(&self, rr: &ResourceRecord) -> ResourceRecord;
+
+    /// The records owned by `domain_name` that answer a `query_type` question.
+    fn lookup(&self, domain_name: DomainName, query_type: QType) -> Vec<ResourceRecord> {
+        self.findset(domain_name).unwrap_or_default().iter()
+            .filter(|rr| self.relevant(query_type, rr.type_name()))
+            .map(|rr| self.copy(rr))
+            .collect()
+    }
 }
 
 
@@ -44,4 +583,173 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_dns_message_query() {
+        let query = DnsMessage::query("example.com.", QType::Mx);
+        assert!(query.header.rd);
+        assert!(!query.header.qr);
+        assert_eq!(1, query.questions.len());
+        assert!(query.answers.is_empty() && query.authorities.is_empty() && query.additionals.is_empty());
+
+        let bytes = query.to_bytes().unwrap();
+        assert_eq!(&query.header.id.to_be_bytes(), &bytes[..2]);
+        assert_eq!(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], &bytes[2..12]);
+        assert_eq!(&b"\x07example\x03com\x00\x00\x0f\x00\x01"[..], &bytes[12..]);
+        assert_eq!(Ok(query), DnsMessage::from_bytes(&bytes));
+
+        // two queries practically never share an ID
+        let ids: std::collections::HashSet<u16> = (0..8).map(|_| DnsMessage::query("a", QType::A).header.id).collect();
+        assert!(ids.len() > 1);
+    }
+
+    #[test]
+    fn test_dns_header_flags() {
+        let round_trip = |header: DnsHeader| {
+            let message = DnsMessage{header, ..DnsMessage::default()};
+            DnsMessage::from_bytes(&message.to_bytes().unwrap()).unwrap().header
+        };
+        let header = round_trip(DnsHeader::default());
+        assert!(!header.is_response() && !header.authoritative() && !header.truncated());
+        assert!(!header.recursion_desired() && !header.recursion_available());
+        assert_eq!(Rcode::NoError, header.rcode());
+
+        // each flag on its own, so a mixed-up bit would show
+        assert!(round_trip(DnsHeader{qr: true, ..DnsHeader::default()}).is_response());
+        assert!(round_trip(DnsHeader{aa: true, ..DnsHeader::default()}).authoritative());
+        assert!(round_trip(DnsHeader{tc: true, ..DnsHeader::default()}).truncated());
+        assert!(round_trip(DnsHeader{rd: true, ..DnsHeader::default()}).recursion_desired());
+        assert!(round_trip(DnsHeader{ra: true, ..DnsHeader::default()}).recursion_available());
+        let all = DnsHeader{qr: true, aa: true, tc: true, rd: true, ra: true, ..DnsHeader::default()};
+        assert_eq!(all, round_trip(all.clone()));
+
+        let rcodes = vec![
+            (0, Rcode::NoError), (1, Rcode::FormErr), (2, Rcode::ServFail), (3, Rcode::NxDomain),
+            (4, Rcode::NotImp), (5, Rcode::Refused), (9, Rcode::Other(9)),
+        ];
+        for (code, rcode) in rcodes.into_iter() {
+            let mut header = DnsHeader{qr: true, ..DnsHeader::default()};
+            header.set_rcode(rcode);
+            assert_eq!(code, header.rcode);
+            assert_eq!(rcode, round_trip(header).rcode());
+        }
+        assert_eq!(Rcode::NxDomain, Rcode::from(0x13));
+    }
+
+    #[test]
+    fn test_dns_header_opcode() {
+        assert_eq!(Opcode::Query, DnsMessage::query("example.com", QType::A).header.opcode());
+        let opcodes = vec![
+            (0, Opcode::Query), (1, Opcode::IQuery), (2, Opcode::Status), (4, Opcode::Notify),
+            (5, Opcode::Update), (3, Opcode::Other(3)), (15, Opcode::Other(15)),
+        ];
+        for (code, opcode) in opcodes.into_iter() {
+            let mut header = DnsHeader::default();
+            header.set_opcode(opcode);
+            assert_eq!(code, header.opcode);
+            let message = DnsMessage{header, ..DnsMessage::default()};
+            let bytes = message.to_bytes().unwrap();
+            assert_eq!(code, bytes[2] >> 3);
+            assert_eq!(opcode, DnsMessage::from_bytes(&bytes).unwrap().header.opcode());
+        }
+        assert_eq!(Opcode::Notify, Opcode::from(0x14));
+
+        // a primary's NOTIFY for a zone, which is answered in kind
+        let mut notify = DnsMessage::query("example.com", QType::Soa);
+        notify.header.rd = false;
+        notify.header.aa = true;
+        notify.header.set_opcode(Opcode::Notify);
+        let bytes = notify.to_bytes().unwrap();
+        assert_eq!(0x24, bytes[2]);
+        assert_eq!(Ok(notify.clone()), DnsMessage::from_bytes(&bytes));
+        let response = DnsMessage::from_bytes(&DnsMessage::answer(&notify, Vec::new()).to_bytes().unwrap()).unwrap();
+        assert_eq!(Opcode::Notify, response.header.opcode());
+    }
+
+    #[test]
+    fn test_dns_message_answer() {
+        let query = DnsMessage::query("example.com", QType::A);
+        let records: Vec<DnsRecord> = ["192.0.2.1/32", "192.0.2.2/32"].iter()
+            .map(|addr| DnsRecord{
+                name: "example.com".into(),
+                class: QClass::In.into(),
+                ttl: 300,
+                data: ResourceRecord::HostAddress(addr.parse().unwrap()),
+            })
+            .collect();
+        let response = DnsMessage::answer_records(&query, records.clone());
+        let bytes = response.to_bytes().unwrap();
+        // QDCOUNT, ANCOUNT, NSCOUNT, ARCOUNT
+        assert_eq!(&[0, 1, 0, 2, 0, 0, 0, 0], &bytes[4..12]);
+        let response = DnsMessage::from_bytes(&bytes).unwrap();
+        assert_eq!(query.header.id, response.header.id);
+        assert!(response.header.is_response() && response.header.authoritative());
+        assert!(response.header.recursion_desired() && !response.header.recursion_available());
+        assert_eq!(Rcode::NoError, response.header.rcode());
+        assert_eq!(query.questions, response.questions);
+        assert_eq!(records, response.answers);
+
+        let response = DnsMessage::answer(&query, Vec::new());
+        assert_eq!(query.header.id, response.header.id);
+        assert_eq!(Rcode::NxDomain, response.header.rcode());
+        assert_eq!(&[0, 1, 0, 0, 0, 0, 0, 0], &response.to_bytes().unwrap()[4..12]);
+
+        // bare records are owned by the question's name
+        let data: Vec<ResourceRecord> = records.iter().map(|record| record.data.clone()).collect();
+        let response = DnsMessage::answer(&query, data);
+        assert_eq!(Rcode::NoError, response.header.rcode());
+        let expected: Vec<DnsRecord> = records.into_iter().map(|record| DnsRecord{ttl: ANSWER_TTL, ..record}).collect();
+        assert_eq!(expected, response.answers);
+        let unasked = DnsMessage::answer(&DnsMessage::default(), vec![expected[0].data.clone()]);
+        assert!(unasked.answers.is_empty());
+        assert_eq!(Rcode::NxDomain, unasked.header.rcode());
+    }
+
+    #[test]
+    fn test_reverse_name() {
+        assert_eq!("5.2.0.192.in-addr.arpa", reverse_name("192.0.2.5".parse().unwrap()));
+        assert_eq!("1.0.0.127.in-addr.arpa", reverse_name("127.0.0.1".parse().unwrap()));
+        assert_eq!(
+            "b.a.9.8.7.6.5.0.4.0.0.0.3.0.0.0.2.0.0.0.1.0.0.0.0.0.0.0.1.2.3.4.ip6.arpa",
+            reverse_name("4321:0:1:2:3:4:567:89ab".parse().unwrap()),
+        );
+        assert_eq!(
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa",
+            reverse_name("::1".parse().unwrap()),
+        );
+        // well inside the limits, so it can go straight into a query
+        assert!(reverse_name("2001:db8::ff".parse().unwrap()).validate().is_ok());
+    }
+
+    #[test]
+    fn test_qtype_qclass_codes() {
+        let qtypes = vec![
+            (1, QType::A), (2, QType::Ns), (5, QType::Cname), (6, QType::Soa), (12, QType::Ptr),
+            (15, QType::Mx), (16, QType::Txt), (28, QType::Aaaa), (33, QType::Srv), (43, QType::Ds),
+            (46, QType::Rrsig), (47, QType::Nsec), (48, QType::Dnskey), (252, QType::Axfr),
+            (255, QType::Any), (65, QType::Other(65)), (0, QType::Other(0)), (257, QType::Other(257)),
+        ];
+        for (code, qtype) in qtypes.into_iter() {
+            assert_eq!(qtype, QType::from_code(code));
+            assert_eq!(code, u16::from(qtype));
+            match qtype {
+                QType::Other(_) => assert_eq!(Err(UnknownCodeError(code)), QType::try_from(code)),
+                _ => assert_eq!(Ok(qtype), QType::try_from(code)),
+            }
+        }
+        let qclasses = vec![
+            (1, QClass::In), (3, QClass::Ch), (4, QClass::Hs), (255, QClass::Any), (2, QClass::Other(2)),
+        ];
+        for (code, qclass) in qclasses.into_iter() {
+            assert_eq!(qclass, QClass::from_code(code));
+            assert_eq!(code, u16::from(qclass));
+            match qclass {
+                QClass::Other(_) => assert_eq!(Err(UnknownCodeError(code)), QClass::try_from(code)),
+                _ => assert_eq!(Ok(qclass), QClass::try_from(code)),
+            }
+        }
+        assert_eq!("AAAA", QType::Aaaa.to_string());
+        assert_eq!("TYPE65", QType::Other(65).to_string());
+        assert_eq!(None, QType::Other(65).mnemonic());
+    }
+
 }
@@ -0,0 +1,181 @@
+//! A cache of resource records that forgets each one once its TTL runs out,
+//! and of names that don't exist (RFC 2308).
+
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use super::{DnsRecord, DomainName, QType, ResourceRecord};
+use crate::clock::{Clock, SystemClock};
+
+struct CachedRecord {
+    record: DnsRecord,
+    inserted_at: Instant,
+}
+
+impl CachedRecord {
+    fn expires_at(&self) -> Instant {
+        self.inserted_at + Duration::from_secs(self.record.ttl as u64)
+    }
+}
+
+/// Records keyed by owner name, any number per name, each expiring on its own TTL.
+pub struct RecordCache<C: Clock = SystemClock> {
+    clock: C,
+    records: HashMap<DomainName, Vec<CachedRecord>>,
+    /// The SOA that came with each NXDOMAIN, its TTL cut to the negative TTL.
+    negatives: HashMap<(DomainName, QType), CachedRecord>,
+}
+
+impl RecordCache<SystemClock> {
+    pub fn new() -> RecordCache<SystemClock> {
+        RecordCache::with_clock(SystemClock)
+    }
+}
+
+impl Default for RecordCache<SystemClock> {
+    fn default() -> RecordCache<SystemClock> {
+        RecordCache::new()
+    }
+}
+
+impl<C: Clock> RecordCache<C> {
+    pub fn with_clock(clock: C) -> RecordCache<C> {
+        RecordCache{clock, records: HashMap::new(), negatives: HashMap::new()}
+    }
+
+    /// Caches `record` for its TTL, replacing an identical record if there is one.
+    pub fn insert(&mut self, record: DnsRecord) {
+        let inserted_at = self.clock.now();
+        let records = self.records.entry(record.name.clone()).or_default();
+        records.retain(|cached| cached.record.class != record.class || cached.record.data != record.data);
+        records.push(CachedRecord{record, inserted_at});
+    }
+
+    /// The unexpired records for `name`, with their TTLs counted down to the
+    /// number of seconds they have left. Expired records are evicted.
+    pub fn get(&mut self, name: &str) -> Vec<DnsRecord> {
+        let now = self.clock.now();
+        let name = DomainName::from(name);
+        let records = match self.records.get_mut(&name) {
+            Some(records) => records,
+            None => return Vec::new(),
+        };
+        records.retain(|cached| cached.expires_at() > now);
+        let live = records.iter()
+            .map(|cached| {
+                let remaining = cached.expires_at() - now;
+                DnsRecord{ttl: remaining.as_secs() as u32, ..cached.record.clone()}
+            })
+            .collect();
+        if records.is_empty() {
+            self.records.remove(&name);
+        }
+        return live;
+    }
+
+    /// Remembers that `name` doesn't exist, as an NXDOMAIN for a `qtype`
+    /// question said, with `soa` from its authority section. That lasts for
+    /// the lesser of the SOA's TTL and its MINIMUM (RFC 2308 5). Anything other
+    /// than an SOA isn't cached, since without one there's no TTL to go by.
+    pub fn insert_negative(&mut self, name: &str, qtype: QType, soa: DnsRecord) {
+        let ttl = match soa.data {
+            ResourceRecord::StartOfAuthority{minimum, ..} => soa.ttl.min(minimum),
+            _ => return,
+        };
+        let inserted_at = self.clock.now();
+        let cached = CachedRecord{record: DnsRecord{ttl, ..soa}, inserted_at};
+        self.negatives.insert((DomainName::from(name), qtype), cached);
+    }
+
+    /// The SOA for a cached NXDOMAIN for `name` and `qtype`, with its TTL
+    /// counted down, or `None` if there isn't one or it has expired.
+    pub fn get_negative(&mut self, name: &str, qtype: QType) -> Option<DnsRecord> {
+        let now = self.clock.now();
+        let key = (DomainName::from(name), qtype);
+        let cached = self.negatives.get(&key)?;
+        if cached.expires_at() <= now {
+            self.negatives.remove(&key);
+            return None;
+        }
+        let remaining = cached.expires_at() - now;
+        return Some(DnsRecord{ttl: remaining.as_secs() as u32, ..cached.record.clone()});
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::testing::FakeClock;
+    use crate::dns::ResourceRecord;
+
+    fn a_record(addr: &str, ttl: u32) -> DnsRecord {
+        DnsRecord{
+            name: "example.com".into(),
+            class: 1,
+            ttl,
+            data: ResourceRecord::HostAddress(addr.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_record_cache_expiry() {
+        let clock = FakeClock::new();
+        let mut cache = RecordCache::with_clock(clock.clone());
+        cache.insert(a_record("192.0.2.1/32", 60));
+        cache.insert(a_record("192.0.2.2/32", 300));
+        assert_eq!(vec![a_record("192.0.2.1/32", 60), a_record("192.0.2.2/32", 300)], cache.get("example.com"));
+        assert!(cache.get("example.org").is_empty());
+        assert_eq!(2, cache.get("EXAMPLE.com").len());
+
+        clock.advance(59);
+        assert_eq!(vec![a_record("192.0.2.1/32", 1), a_record("192.0.2.2/32", 241)], cache.get("example.com"));
+        clock.advance(1);
+        assert_eq!(vec![a_record("192.0.2.2/32", 240)], cache.get("example.com"));
+
+        // re-inserting a record restarts its TTL rather than duplicating it
+        cache.insert(a_record("192.0.2.2/32", 300));
+        assert_eq!(vec![a_record("192.0.2.2/32", 300)], cache.get("example.com"));
+        clock.advance(300);
+        assert!(cache.get("example.com").is_empty());
+        assert!(cache.records.is_empty());
+    }
+
+    #[test]
+    fn test_record_cache_negative() {
+        let clock = FakeClock::new();
+        let mut cache = RecordCache::with_clock(clock.clone());
+        let soa = |ttl, minimum| DnsRecord{
+            name: "example.com".into(),
+            class: 1,
+            ttl,
+            data: ResourceRecord::StartOfAuthority{
+                mname: "ns.example.com".into(),
+                rname: "hostmaster.example.com".into(),
+                serial: 1,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum,
+            },
+        };
+        // the SOA's MINIMUM is shorter than its TTL, so it's used
+        cache.insert_negative("nowhere.example.com", QType::A, soa(3600, 300));
+        assert_eq!(Some(soa(300, 300)), cache.get_negative("nowhere.example.com", QType::A));
+        assert_eq!(Some(soa(300, 300)), cache.get_negative("NoWhere.example.com", QType::A));
+        assert_eq!(None, cache.get_negative("nowhere.example.com", QType::Aaaa));
+        assert!(cache.get("nowhere.example.com").is_empty());
+
+        // and here its TTL is
+        cache.insert_negative("gone.example.com", QType::A, soa(60, 300));
+        clock.advance(59);
+        assert_eq!(Some(soa(241, 300)), cache.get_negative("nowhere.example.com", QType::A));
+        assert_eq!(Some(soa(1, 300)), cache.get_negative("gone.example.com", QType::A));
+        clock.advance(1);
+        assert_eq!(None, cache.get_negative("gone.example.com", QType::A));
+        clock.advance(240);
+        assert_eq!(None, cache.get_negative("nowhere.example.com", QType::A));
+        assert!(cache.negatives.is_empty());
+
+        cache.insert_negative("nowhere.example.com", QType::A, a_record("192.0.2.1/32", 60));
+        assert_eq!(None, cache.get_negative("nowhere.example.com", QType::A));
+    }
+}
@@ -0,0 +1,58 @@
+//! Reading static host names from `/etc/hosts`, in the format described by
+//! hosts(5): an address, then the names it goes by, separated by whitespace.
+
+use std::{collections::HashMap, fs, io, net::IpAddr};
+
+use super::DomainName;
+
+pub const HOSTS_PATH: &str = "/etc/hosts";
+
+/// Reads and parses `/etc/hosts`.
+pub fn load() -> io::Result<HashMap<DomainName, Vec<IpAddr>>> {
+    Ok(parse(&fs::read_to_string(HOSTS_PATH)?))
+}
+
+/// Maps each name to its addresses, in the order they appear. Lines whose
+/// address doesn't parse are skipped, as are names over the length limits.
+pub fn parse(input: &str) -> HashMap<DomainName, Vec<IpAddr>> {
+    let mut hosts: HashMap<DomainName, Vec<IpAddr>> = HashMap::new();
+    for line in input.lines() {
+        let line = line.split('#').next().unwrap();
+        let mut words = line.split_whitespace();
+        let ip = match words.next().map(str::parse::<IpAddr>) {
+            Some(Ok(ip)) => ip,
+            _ => continue,
+        };
+        for name in words.filter_map(|name| name.parse::<DomainName>().ok()) {
+            let ips = hosts.entry(name).or_default();
+            if !ips.contains(&ip) {
+                ips.push(ip);
+            }
+        }
+    }
+    return hosts;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hosts() {
+        let hosts = parse(include_str!("../../tests/fixtures/hosts"));
+        let ips = |name: &str| hosts.get(&DomainName::from(name)).cloned().unwrap_or_default();
+        let localhost: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+        assert_eq!(localhost, ips("localhost"));
+        assert_eq!(localhost, ips("LocalHost"));
+        assert_eq!(vec!["::1".parse::<IpAddr>().unwrap()], ips("ip6-localhost"));
+        let intranet: Vec<IpAddr> = vec!["192.0.2.10".parse().unwrap()];
+        assert_eq!(intranet, ips("intranet.example.com"));
+        assert_eq!(intranet, ips("intranet"));
+        assert_eq!(intranet, ips("wiki"));
+        assert_eq!(vec!["2001:db8::10".parse::<IpAddr>().unwrap()], ips("v6only.example.com"));
+        // commented out, or on a line without a valid address
+        assert!(ips("old.example.com").is_empty());
+        assert!(ips("broken.example.com").is_empty());
+        assert_eq!(6, hosts.len());
+    }
+}
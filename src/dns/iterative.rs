@@ -0,0 +1,308 @@
+//! A resolver that does the recursion itself, starting at the root servers and
+//! following referrals down the delegation chain, instead of leaning on a
+//! recursive server.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use super::{
+    resolver::{DEFAULT_TIMEOUT, MAX_UDP_RESPONSE},
+    DnsMessage, DomainName, EdnsOpt, QType, Rcode, ResolveError, Resolver, ResourceRecord,
+};
+
+/// The most referrals followed for one lookup, counting those taken to find
+/// the addresses of name servers that came without glue.
+pub(super) const MAX_REFERRALS: usize = 16;
+
+const DNS_PORT: u16 = 53;
+
+pub struct IterativeResolver {
+    /// The root hints.
+    roots: Vec<SocketAddr>,
+    port: u16,
+    timeout: Duration,
+}
+
+impl IterativeResolver {
+    /// Starts every lookup from `roots`, the addresses of the root servers.
+    pub fn new(roots: Vec<SocketAddr>) -> IterativeResolver {
+        IterativeResolver{roots, port: DNS_PORT, timeout: DEFAULT_TIMEOUT}
+    }
+
+    /// The port to ask the servers found through referrals on, since a
+    /// referral only gives their addresses.
+    pub fn with_port(self, port: u16) -> IterativeResolver {
+        IterativeResolver{port, ..self}
+    }
+
+    /// How long to wait for each attempt at each server, as with `Resolver`.
+    pub fn with_timeout(self, timeout: Duration) -> IterativeResolver {
+        IterativeResolver{timeout, ..self}
+    }
+
+    /// Asks a root server about `name`, then the servers each referral points
+    /// to in turn, until one of them answers with authority. That answer is
+    /// returned as it is, even if it's NXDOMAIN or has no records.
+    ///
+    /// Name servers inside the zone they serve come with their addresses as
+    /// glue; the addresses of the rest are looked up from the root first.
+    /// The servers for each zone are tried in order until one of them gives
+    /// an answer or a referral further down.
+    pub fn resolve(&self, name: &str, qtype: QType) -> Result<DnsMessage, ResolveError> {
+        let mut referrals = 0;
+        return self.resolve_from_root(name.trim_end_matches('.').into(), qtype, &mut referrals);
+    }
+
+    fn resolve_from_root(
+        &self,
+        name: DomainName,
+        qtype: QType,
+        referrals: &mut usize,
+    ) -> Result<DnsMessage, ResolveError> {
+        let mut query = DnsMessage::query(&name, qtype);
+        query.header.rd = false;
+        query.edns = Some(EdnsOpt::new(MAX_UDP_RESPONSE as u16));
+        let mut zone = DomainName::default();
+        let mut servers = self.roots.clone();
+        loop {
+            let response = self.ask(&servers, &zone, &query)?;
+            let (child, name_servers) = match delegation(&response, &zone, &name) {
+                Some(delegation) => delegation,
+                None => return Ok(response),
+            };
+            *referrals += 1;
+            if *referrals > MAX_REFERRALS {
+                return Err(ResolveError::TooManyReferrals);
+            }
+            servers = self.glue(&response, &zone, &name_servers);
+            if servers.is_empty() {
+                servers = self.look_up_servers(&name_servers, referrals)?;
+            }
+            if servers.is_empty() {
+                return Err(ResolveError::LameDelegation(child));
+            }
+            zone = child;
+        }
+    }
+
+    // the first response from `servers` that either answers the question or
+    // refers it further down than `zone`
+    fn ask(&self, servers: &[SocketAddr], zone: &DomainName, query: &DnsMessage) -> Result<DnsMessage, ResolveError> {
+        let name = &query.questions[0].name;
+        let mut error = ResolveError::LameDelegation(zone.clone());
+        for &server in servers {
+            match Resolver::new(server).with_timeout(self.timeout).send(query) {
+                Ok(response) if is_answer(&response) || delegation(&response, zone, name).is_some() => {
+                    return Ok(response);
+                },
+                Ok(_) => (),
+                Err(e) => error = e,
+            }
+        }
+        return Err(error);
+    }
+
+    // the addresses the referral gave for `name_servers`, ignoring any for
+    // names outside `zone`, which its servers have no say over
+    fn glue(&self, response: &DnsMessage, zone: &DomainName, name_servers: &[DomainName]) -> Vec<SocketAddr> {
+        return response.additionals.iter()
+            .filter(|record| name_servers.contains(&record.name) && is_within(&record.name, zone))
+            .filter_map(|record| match &record.data {
+                ResourceRecord::HostAddress(net) => Some(IpAddr::V4(net.addr())),
+                ResourceRecord::HostAddressV6(addr) => Some(IpAddr::V6(*addr)),
+                _ => None,
+            })
+            .map(|ip| SocketAddr::new(ip, self.port))
+            .collect();
+    }
+
+    // the addresses of the first of `name_servers` that has any
+    fn look_up_servers(
+        &self,
+        name_servers: &[DomainName],
+        referrals: &mut usize,
+    ) -> Result<Vec<SocketAddr>, ResolveError> {
+        for ns in name_servers {
+            let response = self.resolve_from_root(ns.clone(), QType::A, referrals)?;
+            let addresses: Vec<SocketAddr> = response.answers.iter()
+                .filter(|record| record.name == *ns)
+                .filter_map(|record| match &record.data {
+                    ResourceRecord::HostAddress(net) => Some(SocketAddr::new(IpAddr::V4(net.addr()), self.port)),
+                    _ => None,
+                })
+                .collect();
+            if !addresses.is_empty() {
+                return Ok(addresses);
+            }
+        }
+        return Ok(Vec::new());
+    }
+}
+
+// an authoritative answer, possibly a negative one, or records from a server
+// that didn't bother setting AA
+fn is_answer(response: &DnsMessage) -> bool {
+    match response.header.rcode() {
+        Rcode::NoError => response.header.authoritative() || !response.answers.is_empty(),
+        Rcode::NxDomain => response.header.authoritative(),
+        _ => false,
+    }
+}
+
+// the zone `response` delegates `name` to and its name servers, if it's a
+// referral to somewhere strictly below `zone`, so that every step gets closer
+fn delegation(response: &DnsMessage, zone: &DomainName, name: &DomainName) -> Option<(DomainName, Vec<DomainName>)> {
+    if is_answer(response) || response.header.rcode() != Rcode::NoError {
+        return None;
+    }
+    let child = response.authorities.iter()
+        .filter(|record| matches!(record.data, ResourceRecord::NameServer(_)))
+        .map(|record| &record.name)
+        .find(|child| is_within(name, child) && is_within(child, zone) && *child != zone)?;
+    let name_servers = response.authorities.iter()
+        .filter(|record| record.name == *child)
+        .filter_map(|record| match &record.data {
+            ResourceRecord::NameServer(ns) => Some(ns.clone()),
+            _ => None,
+        })
+        .collect();
+    return Some((child.clone(), name_servers));
+}
+
+// whether `name` is `ancestor` or below it
+fn is_within(name: &DomainName, ancestor: &DomainName) -> bool {
+    let name: Vec<&str> = name.labels().collect();
+    let ancestor: Vec<&str> = ancestor.labels().collect();
+    return name.len() >= ancestor.len()
+        && name[name.len() - ancestor.len()..].iter().zip(ancestor).all(|(a, b)| a.eq_ignore_ascii_case(b));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::UdpSocket, thread};
+
+    use super::*;
+    use crate::dns::DnsRecord;
+
+    /// Answers the first `queries` queries sent to `addr` with what `respond`
+    /// makes of each.
+    fn serve_on<F>(addr: SocketAddr, queries: usize, mut respond: F) -> (SocketAddr, thread::JoinHandle<()>)
+    where
+        F: FnMut(DnsMessage) -> DnsMessage + Send + 'static,
+    {
+        let socket = UdpSocket::bind(addr).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            for _ in 0..queries {
+                let (len, peer) = socket.recv_from(&mut buf).unwrap();
+                let query = DnsMessage::from_bytes(&buf[..len]).unwrap();
+                assert!(!query.header.recursion_desired());
+                socket.send_to(&respond(query).to_bytes().unwrap(), peer).unwrap();
+            }
+        });
+        return (addr, handle);
+    }
+
+    fn a(name: &str, ip: &str) -> DnsRecord {
+        let data = ResourceRecord::HostAddress(format!("{}/32", ip).parse().unwrap());
+        return DnsRecord{name: name.into(), class: 1, ttl: 60, data};
+    }
+
+    /// A server for the zones that aren't in `delegations`: a referral for any
+    /// name under one of those, naming its server and giving its address as
+    /// glue if there is one, and an authoritative answer from `records` for
+    /// the rest.
+    fn zone_server(
+        delegations: Vec<(&'static str, &'static str, Option<&'static str>)>,
+        records: Vec<DnsRecord>,
+    ) -> impl FnMut(DnsMessage) -> DnsMessage {
+        move |query| {
+            let name = query.questions[0].name.clone();
+            let delegation = delegations.iter().find(|(zone, _, _)| is_within(&name, &(*zone).into()));
+            let mut response = match delegation {
+                Some(&(zone, ns, glue)) => {
+                    let mut response = DnsMessage::answer(&query, Vec::new());
+                    response.header.aa = false;
+                    response.header.set_rcode(Rcode::NoError);
+                    let ns_record = DnsRecord{data: ResourceRecord::NameServer(ns.into()), ..a(zone, "0.0.0.0")};
+                    response.authorities.push(ns_record);
+                    response.additionals.extend(glue.map(|ip| a(ns, ip)));
+                    response
+                },
+                None => {
+                    let answers = records.iter().filter(|record| record.name == name).cloned().collect();
+                    DnsMessage::answer_records(&query, answers)
+                },
+            };
+            response.edns = query.edns;
+            return response;
+        }
+    }
+
+    #[test]
+    fn test_iterative_resolver() {
+        // a root, a server for com and org, and one for example.com and
+        // example.org, each on its own loopback address but the same port
+        let www_com = a("www.example.com", "192.0.2.1");
+        let www_org = a("www.example.org", "192.0.2.2");
+        let root = zone_server(vec![("com", "a.gtld.com", Some("127.0.0.2")), ("org", "a.gtld.com", None)], vec![]);
+        let (root_addr, root_handle) = serve_on("127.0.0.1:0".parse().unwrap(), 4, root);
+        let port = root_addr.port();
+        let tld = zone_server(
+            vec![
+                ("example.com", "ns.example.com", Some("127.0.0.3")),
+                // not org's to give, and there's nothing listening there
+                ("example.org", "ns.example.com", Some("127.0.0.9")),
+            ],
+            vec![a("a.gtld.com", "127.0.0.2")],
+        );
+        let (_, tld_handle) = serve_on(SocketAddr::new([127, 0, 0, 2].into(), port), 4, tld);
+        let leaf = zone_server(vec![], vec![www_com.clone(), www_org.clone(), a("ns.example.com", "127.0.0.3")]);
+        let (_, leaf_handle) = serve_on(SocketAddr::new([127, 0, 0, 3].into(), port), 3, leaf);
+
+        let resolver = IterativeResolver::new(vec![root_addr]).with_port(port);
+        let response = resolver.resolve("www.example.com.", QType::A).unwrap();
+        assert!(response.header.authoritative());
+        assert_eq!(vec![www_com], response.answers);
+
+        // the root has no glue for org's server, so a.gtld.com is looked up
+        // from the root first; then the glue for ns.example.com that comes
+        // with example.org's referral is outside org, so it's ignored and
+        // ns.example.com is looked up from the root as well
+        let response = resolver.resolve("www.example.org", QType::A).unwrap();
+        assert_eq!(vec![www_org], response.answers);
+        root_handle.join().unwrap();
+        tld_handle.join().unwrap();
+        leaf_handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_iterative_resolver_lame() {
+        // a server that keeps referring com back to itself never gets any closer
+        let server = zone_server(vec![("com", "a.root", Some("127.0.0.1"))], vec![]);
+        let (addr, handle) = serve_on("127.0.0.1:0".parse().unwrap(), 2, server);
+        let resolver = IterativeResolver::new(vec![addr]).with_port(addr.port());
+        match resolver.resolve("www.example.com", QType::A) {
+            Err(ResolveError::LameDelegation(zone)) => assert_eq!("com", zone),
+            other => panic!("expected a lame delegation, got {:?}", other),
+        }
+        handle.join().unwrap();
+
+        // a root server that refuses is passed over for the next one
+        let (refusing, refusing_handle) = serve_on("127.0.0.1:0".parse().unwrap(), 1, |query| {
+            let mut response = DnsMessage::answer(&query, Vec::new());
+            response.header.set_rcode(Rcode::Refused);
+            response
+        });
+        let www = a("www.example.com", "192.0.2.1");
+        let root = zone_server(vec![], vec![www.clone()]);
+        let (root_addr, root_handle) = serve_on(SocketAddr::new([127, 0, 0, 2].into(), refusing.port()), 1, root);
+        let resolver = IterativeResolver::new(vec![refusing, root_addr]);
+        assert_eq!(vec![www], resolver.resolve("www.example.com", QType::A).unwrap().answers);
+        refusing_handle.join().unwrap();
+        root_handle.join().unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+//! Domain names, which compare case-insensitively (RFC 4343) but keep the
+//! case they were written in, so 0x20-mixed names survive a round trip.
+
+use std::{fmt, hash::{Hash, Hasher}, ops::Deref, str::FromStr};
+
+/// The most octets a single label can have (RFC 1035 2.3.4).
+pub const MAX_LABEL_LEN: usize = 63;
+/// The most octets a whole name can take on the wire, length bytes included.
+pub const MAX_NAME_LEN: usize = 255;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DomainNameError {
+    /// Two dots in a row, or a dot at the start of a name other than the root.
+    EmptyLabel,
+    /// A label had the given length, over `MAX_LABEL_LEN`.
+    LabelTooLong(usize),
+    /// The name would take the given number of octets on the wire, over `MAX_NAME_LEN`.
+    NameTooLong(usize),
+}
+
+impl fmt::Display for DomainNameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DomainNameError::EmptyLabel => write!(f, "domain name has an empty label"),
+            DomainNameError::LabelTooLong(len) => {
+                write!(f, "domain name label is {} octets, over the limit of {}", len, MAX_LABEL_LEN)
+            },
+            DomainNameError::NameTooLong(len) => {
+                write!(f, "domain name is {} octets, over the limit of {}", len, MAX_NAME_LEN)
+            },
+        }
+    }
+}
+
+impl std::error::Error for DomainNameError {}
+
+/// A domain name without its trailing dot, e.g. `"www.example.com"`.
+///
+/// Equality and hashing ignore ASCII case, so `Example.COM` and `example.com`
+/// are the same key in a map; `Display` and `as_str` give back the original.
+///
+/// The name is kept as the string it was written as rather than a list of
+/// labels, so that it can be borrowed as a `&str` wherever one is taken and
+/// `labels` splits it without copying. That means `From` doesn't check it:
+/// `FromStr` and `validate` do, and `to_bytes` validates every name before
+/// it goes on the wire.
+#[derive(Clone, Default)]
+pub struct DomainName(String);
+
+impl DomainName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The name in lowercase, for when a canonical spelling is needed.
+    pub fn to_lowercase(&self) -> String {
+        self.0.to_ascii_lowercase()
+    }
+
+    /// The name's labels from left to right; the root has none.
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.0.split('.').filter(|label| !label.is_empty())
+    }
+
+    /// The name with its leftmost label dropped, so `example.com` for
+    /// `www.example.com`, and the root for a top-level domain. The root itself
+    /// has no parent.
+    pub fn parent(&self) -> Option<DomainName> {
+        let mut labels = self.labels();
+        labels.next()?;
+        return Some(labels.collect::<Vec<&str>>().join(".").into());
+    }
+
+    /// Whether this is the root, `""`.
+    pub fn is_root(&self) -> bool {
+        self.labels().next().is_none()
+    }
+
+    /// The number of octets the name takes on the wire, uncompressed.
+    pub fn wire_len(&self) -> usize {
+        self.labels().map(|label| label.len() + 1).sum::<usize>() + 1
+    }
+
+    /// Checks the name against the RFC 1035 length limits, and for empty
+    /// labels, neither of which names built with `From` are held to.
+    pub fn validate(&self) -> Result<(), DomainNameError> {
+        if !self.0.is_empty() && self.0.split('.').any(str::is_empty) {
+            return Err(DomainNameError::EmptyLabel);
+        }
+        if let Some(label) = self.labels().find(|label| label.len() > MAX_LABEL_LEN) {
+            return Err(DomainNameError::LabelTooLong(label.len()));
+        }
+        let len = self.wire_len();
+        if len > MAX_NAME_LEN {
+            return Err(DomainNameError::NameTooLong(len));
+        }
+        return Ok(());
+    }
+}
+
+/// Parses a name as it would be typed, with or without the trailing dot,
+/// rejecting it if it has an empty label or is over the length limits.
+impl FromStr for DomainName {
+    type Err = DomainNameError;
+    fn from_str(s: &str) -> Result<DomainName, DomainNameError> {
+        let name = DomainName::from(s.strip_suffix('.').unwrap_or(s));
+        name.validate()?;
+        return Ok(name);
+    }
+}
+
+impl From<String> for DomainName {
+    fn from(name: String) -> DomainName {
+        DomainName(name)
+    }
+}
+
+impl From<&str> for DomainName {
+    fn from(name: &str) -> DomainName {
+        DomainName(name.to_string())
+    }
+}
+
+impl From<DomainName> for String {
+    fn from(name: DomainName) -> String {
+        name.0
+    }
+}
+
+impl Deref for DomainName {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for DomainName {
+    fn eq(&self, other: &DomainName) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+impl Eq for DomainName {}
+
+impl PartialEq<str> for DomainName {
+    fn eq(&self, other: &str) -> bool {
+        self.0.eq_ignore_ascii_case(other)
+    }
+}
+
+impl PartialEq<&str> for DomainName {
+    fn eq(&self, other: &&str) -> bool {
+        self.0.eq_ignore_ascii_case(other)
+    }
+}
+
+impl PartialEq<DomainName> for &str {
+    fn eq(&self, other: &DomainName) -> bool {
+        other.0.eq_ignore_ascii_case(self)
+    }
+}
+
+impl Hash for DomainName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for byte in self.0.bytes() {
+            state.write_u8(byte.to_ascii_lowercase());
+        }
+        // like str's Hash, so that "ab" + "c" and "a" + "bc" differ in a tuple
+        state.write_u8(0xff);
+    }
+}
+
+impl fmt::Display for DomainName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for DomainName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{hash_map::DefaultHasher, HashMap};
+
+    use super::*;
+
+    fn hash(name: &DomainName) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_domain_name_case_insensitive() {
+        let lower = DomainName::from("www.example.com");
+        let mixed = DomainName::from("wWw.ExAmPle.COM");
+        assert_eq!(lower, mixed);
+        assert_eq!(hash(&lower), hash(&mixed));
+        assert_eq!(mixed, "WWW.EXAMPLE.COM");
+        assert_ne!(lower, DomainName::from("www.example.org"));
+        // only ASCII folds; other letters are just bytes in a label
+        assert_ne!(DomainName::from("é.example"), DomainName::from("É.example"));
+
+        let mut db = HashMap::new();
+        db.insert(mixed.clone(), 1);
+        assert_eq!(Some(&1), db.get(&lower));
+
+        // but the original spelling is kept
+        assert_eq!("wWw.ExAmPle.COM", mixed.as_str());
+        assert_eq!("wWw.ExAmPle.COM", mixed.to_string());
+        assert_eq!("www.example.com", mixed.to_lowercase());
+    }
+
+    #[test]
+    fn test_domain_name_limits() {
+        let label = |len| "x".repeat(len);
+        // four 63-octet labels would take 4 * 64 + 1 = 257 octets, so the
+        // last is two shorter to come to exactly 255
+        let longest = format!("{}.{}.{}.{}", label(63), label(63), label(63), label(61));
+        assert_eq!(255, DomainName::from(&longest[..]).wire_len());
+        assert_eq!(1, DomainName::from("").wire_len());
+
+        let max_label = label(63);
+        let success_cases = vec![
+            "example.com",
+            "example.com.",
+            "",
+            ".",
+            &longest[..],
+            &max_label[..],
+        ];
+        for name in success_cases.into_iter() {
+            assert_eq!(Ok(DomainName::from(name.trim_end_matches('.'))), name.parse());
+        }
+
+        let long_label = format!("{}.example.com", label(64));
+        let long_name = format!("{}.x", longest);
+        let failure_cases = vec![
+            (&long_label[..], DomainNameError::LabelTooLong(64)),
+            (&long_name[..], DomainNameError::NameTooLong(257)),
+        ];
+        for (name, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), name.parse::<DomainName>());
+        }
+    }
+
+    #[test]
+    fn test_domain_name_labels() {
+        let name: DomainName = "www.Example.com.".parse().unwrap();
+        assert_eq!(vec!["www", "Example", "com"], name.labels().collect::<Vec<&str>>());
+        assert_eq!("www.Example.com", name.to_string());
+        let root: DomainName = ".".parse().unwrap();
+        assert!(root.is_root());
+        assert_eq!(0, root.labels().count());
+        assert_eq!("", root.to_string());
+
+        let mut ancestors = Vec::new();
+        let mut name = Some(name);
+        while let Some(current) = name {
+            name = current.parent();
+            ancestors.push(current.to_string());
+        }
+        assert_eq!(vec!["www.Example.com", "Example.com", "com", ""], ancestors);
+
+        let failure_cases = vec!["www..example.com", ".example.com", "example.com..", ".."];
+        for name in failure_cases.into_iter() {
+            assert_eq!(Err(DomainNameError::EmptyLabel), name.parse::<DomainName>());
+        }
+    }
+}
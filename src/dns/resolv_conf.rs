@@ -0,0 +1,113 @@
+//! Reading the system resolver's configuration from `/etc/resolv.conf`, in
+//! the format described by resolv.conf(5).
+//!
+//! Only `nameserver`, `search`, `domain`, and the `ndots` option are
+//! understood. Anything else, including lines that don't parse, is skipped,
+//! as the system resolver does.
+
+use std::{fs, io, net::{IpAddr, SocketAddr}};
+
+use super::{DomainName, Resolver};
+
+pub const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+const DNS_PORT: u16 = 53;
+const DEFAULT_NDOTS: u8 = 1;
+// larger values are clamped to this, as in glibc
+const MAX_NDOTS: u8 = 15;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ResolvConf {
+    /// The servers to ask, in order of preference.
+    pub nameservers: Vec<SocketAddr>,
+    /// The domains to try appending to names that aren't fully qualified.
+    pub search: Vec<DomainName>,
+    /// A name with at least this many dots is tried as-is before the search
+    /// domains are appended.
+    pub ndots: u8,
+}
+
+impl Default for ResolvConf {
+    fn default() -> ResolvConf {
+        ResolvConf{nameservers: Vec::new(), search: Vec::new(), ndots: DEFAULT_NDOTS}
+    }
+}
+
+impl ResolvConf {
+    /// Reads and parses `/etc/resolv.conf`.
+    pub fn load() -> io::Result<ResolvConf> {
+        Ok(parse(&fs::read_to_string(RESOLV_CONF_PATH)?))
+    }
+
+    /// A resolver for the first name server, if there is one.
+    pub fn resolver(&self) -> Option<Resolver> {
+        self.nameservers.first().map(|&server| Resolver::new(server))
+    }
+}
+
+pub fn parse(input: &str) -> ResolvConf {
+    let mut conf = ResolvConf::default();
+    for line in input.lines() {
+        // comments take up the whole line, so `#` or `;` elsewhere is part of a word
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => {
+                if let Some(Ok(ip)) = words.next().map(str::parse::<IpAddr>) {
+                    conf.nameservers.push(SocketAddr::new(ip, DNS_PORT));
+                }
+            },
+            // `domain` and `search` each replace whichever came before
+            Some("domain") => {
+                if let Some(domain) = words.next() {
+                    conf.search = domain.parse().into_iter().collect();
+                }
+            },
+            Some("search") => conf.search = words.filter_map(|domain| domain.parse().ok()).collect(),
+            Some("options") => {
+                for option in words {
+                    if let Some(Ok(ndots)) = option.strip_prefix("ndots:").map(str::parse::<u8>) {
+                        conf.ndots = ndots.min(MAX_NDOTS);
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+    return conf;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let input = "\
+# Generated by NetworkManager
+domain old.example.com
+search corp.example.com example.com.
+nameserver 192.0.2.53
+; a comment in the other style
+nameserver   2001:db8::53
+nameserver not-an-address
+nameserver fe80::1%eth0
+nameserver
+options rotate ndots:2 timeout:1
+options ndots:x
+sortlist 130.155.160.0/255.255.240.0
+";
+        let conf = parse(input);
+        let nameservers: Vec<SocketAddr> = vec!["192.0.2.53:53".parse().unwrap(), "[2001:db8::53]:53".parse().unwrap()];
+        assert_eq!(nameservers, conf.nameservers);
+        assert_eq!(vec![DomainName::from("corp.example.com"), DomainName::from("example.com")], conf.search);
+        assert_eq!(2, conf.ndots);
+        assert!(conf.resolver().is_some());
+
+        assert_eq!(ResolvConf{ndots: 1, ..ResolvConf::default()}, parse(""));
+        assert!(parse("").resolver().is_none());
+        assert_eq!(15, parse("options ndots:30").ndots);
+        assert_eq!(vec![DomainName::from("example.org")], parse("search example.com\ndomain example.org").search);
+    }
+}
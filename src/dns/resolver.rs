@@ -0,0 +1,733 @@
+//! A stub resolver that asks a single recursive server over UDP, falling back
+//! to TCP for answers too big for a datagram.
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, UdpSocket},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ipnet::Ipv4Net;
+
+use super::{
+    iterative::MAX_REFERRALS,
+    DnsEncodeError, DnsHeader, DnsMessage, DnsParseError, DnsRecord, DomainName, DomainNameError, EdnsOpt, NameServerDb, QClass,
+    QType, Rcode, RecordCache, ResourceRecord,
+};
+
+pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_CNAME_HOPS: usize = 8;
+// advertised with EDNS, since otherwise servers keep to 512 bytes over UDP
+pub(super) const MAX_UDP_RESPONSE: usize = 4096;
+
+#[derive(Debug)]
+pub enum ResolveError {
+    Io(io::Error),
+    /// The server didn't answer any of our attempts in time.
+    Timeout,
+    Parse(DnsParseError),
+    /// The name asked about is over the RFC 1035 length limits.
+    InvalidName(DomainNameError),
+    /// The query couldn't be encoded for some other reason.
+    Encode(DnsEncodeError),
+    /// The server set TC even over TCP, so the response is incomplete. The
+    /// partial response is included.
+    Truncated(Box<DnsMessage>),
+    /// Following CNAMEs led back to a name already seen.
+    CnameLoop(DomainName),
+    /// Following CNAMEs took more than the allowed number of hops.
+    TooManyCnames,
+    /// The server answered a zone transfer with this error, as when it isn't
+    /// authoritative for the zone or won't hand it out.
+    TransferFailed(Rcode),
+    /// A zone transfer didn't start with the zone's SOA, or the connection
+    /// closed before the SOA that ends it.
+    InvalidTransfer,
+    /// None of the servers for this zone gave an answer or a referral further
+    /// down towards the name.
+    LameDelegation(DomainName),
+    /// Following referrals down from the root took more than the allowed
+    /// number of steps.
+    TooManyReferrals,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolveError::Io(e) => write!(f, "{}", e),
+            ResolveError::Timeout => write!(f, "DNS server didn't respond in time"),
+            ResolveError::Parse(e) => write!(f, "{}", e),
+            ResolveError::InvalidName(e) => write!(f, "{}", e),
+            ResolveError::Encode(e) => write!(f, "{}", e),
+            ResolveError::Truncated(_) => write!(f, "DNS response was truncated"),
+            ResolveError::CnameLoop(name) => write!(f, "CNAME chain loops back to {}", name),
+            ResolveError::TooManyCnames => write!(f, "CNAME chain is longer than {} hops", MAX_CNAME_HOPS),
+            ResolveError::TransferFailed(rcode) => write!(f, "zone transfer failed with {:?}", rcode),
+            ResolveError::InvalidTransfer => write!(f, "zone transfer wasn't bracketed by the zone's SOA"),
+            ResolveError::LameDelegation(zone) => write!(f, "no usable answer from the servers for {:?}", zone),
+            ResolveError::TooManyReferrals => write!(f, "more than {} referrals from the root", MAX_REFERRALS),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+impl From<io::Error> for ResolveError {
+    fn from(e: io::Error) -> ResolveError {
+        ResolveError::Io(e)
+    }
+}
+
+impl From<DnsParseError> for ResolveError {
+    fn from(e: DnsParseError) -> ResolveError {
+        ResolveError::Parse(e)
+    }
+}
+
+impl From<DomainNameError> for ResolveError {
+    fn from(e: DomainNameError) -> ResolveError {
+        ResolveError::InvalidName(e)
+    }
+}
+
+impl From<DnsEncodeError> for ResolveError {
+    fn from(e: DnsEncodeError) -> ResolveError {
+        match e {
+            DnsEncodeError::InvalidName(e) => ResolveError::InvalidName(e),
+            e => ResolveError::Encode(e),
+        }
+    }
+}
+
+/// The outcome of `Resolver::resolve`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Resolution {
+    /// Each name that turned out to be a CNAME, in the order they were followed.
+    pub aliases: Vec<DomainName>,
+    /// The name the answers are actually owned by.
+    pub canonical_name: DomainName,
+    /// The records of the requested type, which may be none.
+    pub answers: Vec<DnsRecord>,
+}
+
+pub struct Resolver {
+    server: SocketAddr,
+    timeout: Duration,
+    hosts: HashMap<DomainName, Vec<IpAddr>>,
+    /// Names the server said don't exist, so they aren't asked about again
+    /// until the negative TTL runs out.
+    negative_cache: Mutex<RecordCache>,
+}
+
+impl Resolver {
+    pub fn new(server: SocketAddr) -> Resolver {
+        Resolver{
+            server,
+            timeout: DEFAULT_TIMEOUT,
+            hosts: HashMap::new(),
+            negative_cache: Mutex::new(RecordCache::new()),
+        }
+    }
+
+    /// Static addresses, as from `hosts::load`, for `resolve` to answer A and
+    /// AAAA questions from before asking the server.
+    pub fn with_hosts(self, hosts: HashMap<DomainName, Vec<IpAddr>>) -> Resolver {
+        Resolver{hosts, ..self}
+    }
+
+    /// How long to wait for each attempt; there are two attempts in all over
+    /// UDP, and over TCP, each of connecting, sending, and receiving gets this long.
+    pub fn with_timeout(self, timeout: Duration) -> Resolver {
+        Resolver{timeout, ..self}
+    }
+
+    /// Asks over UDP, then again over TCP if the response was truncated.
+    ///
+    /// An NXDOMAIN is remembered for as long as the SOA that came with it
+    /// allows, and asking again in that time gets a copy of it, with the SOA's
+    /// TTL counted down, without asking the server.
+    pub fn query(&self, name: &str, qtype: QType) -> Result<DnsMessage, ResolveError> {
+        let mut query = DnsMessage::query(name, qtype);
+        let name = query.questions[0].name.clone();
+        if let Some(soa) = self.negative_cache.lock().unwrap().get_negative(&name, qtype) {
+            return Ok(DnsMessage{
+                header: DnsHeader{qr: true, ra: true, rcode: Rcode::NxDomain.into(), ..query.header},
+                questions: query.questions,
+                authorities: vec![soa],
+                ..DnsMessage::default()
+            });
+        }
+        query.edns = Some(EdnsOpt::new(MAX_UDP_RESPONSE as u16));
+        let response = self.send(&query)?;
+        if response.header.rcode() == Rcode::NxDomain {
+            let soa = response.authorities.iter()
+                .find(|record| matches!(record.data, ResourceRecord::StartOfAuthority{..}));
+            if let Some(soa) = soa {
+                self.negative_cache.lock().unwrap().insert_negative(&name, qtype, soa.clone());
+            }
+        }
+        return Ok(response);
+    }
+
+    /// Sends `query` as it is over UDP, then again over TCP if the response
+    /// was truncated.
+    pub(super) fn send(&self, query: &DnsMessage) -> Result<DnsMessage, ResolveError> {
+        let bytes = query.to_bytes()?;
+        let response = self.exchange_udp(query, &bytes)?;
+        if response.header.truncated() {
+            return self.exchange_tcp(query, &bytes);
+        }
+        return Ok(response);
+    }
+
+    /// Like `query`, but straight over TCP, for answers known to be large.
+    pub fn query_tcp(&self, name: &str, qtype: QType) -> Result<DnsMessage, ResolveError> {
+        let query = DnsMessage::query(name, qtype);
+        let bytes = query.to_bytes()?;
+        return self.exchange_tcp(&query, &bytes);
+    }
+
+    /// Fetches all of `zone` over TCP with AXFR (RFC 5936), as a secondary
+    /// server would. The SOA is in there along with everything else.
+    pub fn transfer(&self, zone: &str) -> Result<NameServerDb, ResolveError> {
+        let mut query = DnsMessage::query(zone, QType::Axfr);
+        query.header.rd = false;
+        let mut stream = self.connect_tcp()?;
+        write_tcp_message(&mut stream, &query.to_bytes()?).map_err(timeout_or_io)?;
+        let mut db = NameServerDb::new();
+        let mut started = false;
+        loop {
+            let bytes = match read_tcp_message(&mut stream) {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(ResolveError::InvalidTransfer),
+                Err(e) => return Err(timeout_or_io(e)),
+            };
+            let response = DnsMessage::from_bytes(&bytes)?;
+            // only the first message has to repeat the question
+            if !response.header.is_response() || response.header.id != query.header.id {
+                continue;
+            }
+            if response.header.rcode() != Rcode::NoError {
+                return Err(ResolveError::TransferFailed(response.header.rcode()));
+            }
+            for record in response.answers {
+                let is_soa = matches!(record.data, ResourceRecord::StartOfAuthority{..});
+                if started && is_soa {
+                    return Ok(db);
+                }
+                if !started && !is_soa {
+                    return Err(ResolveError::InvalidTransfer);
+                }
+                started = true;
+                db.entry(record.name).or_default().push((record.ttl, record.data));
+            }
+        }
+    }
+
+    fn lookup_hosts(&self, name: &DomainName, qtype: QType) -> Vec<DnsRecord> {
+        let ips = match self.hosts.get(name) {
+            Some(ips) => ips,
+            None => return Vec::new(),
+        };
+        return ips.iter()
+            .filter_map(|ip| match ip {
+                IpAddr::V4(ip) if qtype == QType::A || qtype == QType::Any => {
+                    Some(ResourceRecord::HostAddress(Ipv4Net::new(*ip, 32).unwrap()))
+                },
+                IpAddr::V6(ip) if qtype == QType::Aaaa || qtype == QType::Any => Some(ResourceRecord::HostAddressV6(*ip)),
+                _ => None,
+            })
+            .map(|data| DnsRecord{name: name.clone(), class: QClass::In.into(), ttl: 0, data})
+            .collect();
+    }
+
+    fn exchange_udp(&self, query: &DnsMessage, bytes: &[u8]) -> Result<DnsMessage, ResolveError> {
+        let local: SocketAddr = match self.server {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        // a connected socket drops datagrams from anyone but the server
+        socket.connect(self.server)?;
+        for _ in 0..2 {
+            socket.send(bytes)?;
+            if let Some(response) = self.await_response(&socket, query)? {
+                return Ok(response);
+            }
+        }
+        return Err(ResolveError::Timeout);
+    }
+
+    fn connect_tcp(&self) -> Result<TcpStream, ResolveError> {
+        let stream = TcpStream::connect_timeout(&self.server, self.timeout).map_err(timeout_or_io)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        return Ok(stream);
+    }
+
+    fn exchange_tcp(&self, query: &DnsMessage, bytes: &[u8]) -> Result<DnsMessage, ResolveError> {
+        let mut stream = self.connect_tcp()?;
+        write_tcp_message(&mut stream, bytes).map_err(timeout_or_io)?;
+        loop {
+            let response = DnsMessage::from_bytes(&read_tcp_message(&mut stream).map_err(timeout_or_io)?)?;
+            if !is_answer_to(&response, query) {
+                continue;
+            }
+            if response.header.truncated() {
+                return Err(ResolveError::Truncated(Box::new(response)));
+            }
+            return Ok(response);
+        }
+    }
+
+    /// Like `query`, but follows CNAMEs until it finds records of type `qtype`.
+    /// Aliases the server already chased within a response are followed from
+    /// there; otherwise the alias's target is queried in turn.
+    ///
+    /// Names with addresses of the right family in the hosts table are
+    /// answered from it without asking the server at all.
+    pub fn resolve(&self, name: &str, qtype: QType) -> Result<Resolution, ResolveError> {
+        let mut resolution = Resolution{
+            aliases: Vec::new(),
+            canonical_name: name.trim_end_matches('.').into(),
+            answers: Vec::new(),
+        };
+        resolution.answers = self.lookup_hosts(&resolution.canonical_name, qtype);
+        if !resolution.answers.is_empty() {
+            return Ok(resolution);
+        }
+        loop {
+            let response = self.query(&resolution.canonical_name, qtype)?;
+            loop {
+                let owned = |record: &&DnsRecord| record.name == resolution.canonical_name;
+                let answers: Vec<DnsRecord> = response.answers.iter().filter(owned)
+                    .filter(|record| qtype == QType::Any || record.data.rtype() == u16::from(qtype))
+                    .cloned()
+                    .collect();
+                let target = response.answers.iter().filter(owned)
+                    .find_map(|record| match &record.data {
+                        ResourceRecord::CanonicalName(target) => Some(target.clone()),
+                        _ => None,
+                    });
+                let target = match target {
+                    Some(target) if answers.is_empty() => target,
+                    _ => {
+                        resolution.answers = answers;
+                        return Ok(resolution);
+                    },
+                };
+                let seen = resolution.aliases.iter().chain(Some(&resolution.canonical_name))
+                    .any(|alias| *alias == target);
+                if seen {
+                    return Err(ResolveError::CnameLoop(target));
+                }
+                if resolution.aliases.len() == MAX_CNAME_HOPS {
+                    return Err(ResolveError::TooManyCnames);
+                }
+                let alias = std::mem::replace(&mut resolution.canonical_name, target);
+                resolution.aliases.push(alias);
+                // the server may not have chased the new name in this response
+                if !response.answers.iter().any(|record| record.name == resolution.canonical_name) {
+                    break;
+                }
+            }
+        }
+    }
+
+    // waits out one attempt's timeout, skipping datagrams that aren't the
+    // answer to `query`; returns `None` if the timeout runs out
+    fn await_response(&self, socket: &UdpSocket, query: &DnsMessage) -> Result<Option<DnsMessage>, ResolveError> {
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0u8; MAX_UDP_RESPONSE];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            socket.set_read_timeout(Some(deadline - now))?;
+            let len = match socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                    return Ok(None);
+                },
+                Err(e) => return Err(e.into()),
+            };
+            let response = match DnsMessage::from_bytes(&buf[..len]) {
+                Ok(response) => response,
+                // someone else's garbage, unless it claims our ID
+                Err(e) if len >= 2 && buf[..2] == query.header.id.to_be_bytes() => return Err(e.into()),
+                Err(_) => continue,
+            };
+            if is_answer_to(&response, query) {
+                return Ok(Some(response));
+            }
+        }
+    }
+}
+
+fn is_answer_to(response: &DnsMessage, query: &DnsMessage) -> bool {
+    response.header.is_response() && response.header.id == query.header.id && response.questions == query.questions
+}
+
+fn timeout_or_io(e: io::Error) -> ResolveError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ResolveError::Timeout,
+        _ => ResolveError::Io(e),
+    }
+}
+
+// over TCP, each message is preceded by its length as a u16 (RFC 1035 4.2.2),
+// so a longer one is an error rather than a length that throws off the stream
+pub(super) fn write_tcp_message<W: Write>(stream: &mut W, bytes: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(bytes.len()).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("DNS message is {} bytes, over the TCP limit", bytes.len()))
+    })?;
+    let mut framed = Vec::with_capacity(2 + bytes.len());
+    framed.extend_from_slice(&len.to_be_bytes());
+    framed.extend_from_slice(bytes);
+    return stream.write_all(&framed);
+}
+
+pub(super) fn read_tcp_message<R: Read>(stream: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut buf)?;
+    return Ok(buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+    use crate::dns::{DnsRecord, ResourceRecord, StaticNameServer};
+
+    /// Answers each of the first `queries` queries it receives with whatever
+    /// messages `respond` returns for it, if any.
+    fn serve<F>(queries: usize, mut respond: F) -> (SocketAddr, thread::JoinHandle<()>)
+    where
+        F: FnMut(usize, DnsMessage) -> Vec<DnsMessage> + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            for n in 0..queries {
+                let (len, peer) = socket.recv_from(&mut buf).unwrap();
+                let query = DnsMessage::from_bytes(&buf[..len]).unwrap();
+                for response in respond(n, query) {
+                    socket.send_to(&response.to_bytes().unwrap(), peer).unwrap();
+                }
+            }
+        });
+        return (addr, handle);
+    }
+
+    /// Like `serve`, but over TCP on `addr`, with one query per connection.
+    fn serve_tcp<F>(addr: SocketAddr, queries: usize, mut respond: F) -> (SocketAddr, thread::JoinHandle<()>)
+    where
+        F: FnMut(usize, DnsMessage) -> Vec<DnsMessage> + Send + 'static,
+    {
+        let listener = TcpListener::bind(addr).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            for n in 0..queries {
+                let (mut stream, _) = listener.accept().unwrap();
+                let query = DnsMessage::from_bytes(&read_tcp_message(&mut stream).unwrap()).unwrap();
+                for response in respond(n, query) {
+                    write_tcp_message(&mut stream, &response.to_bytes().unwrap()).unwrap();
+                }
+            }
+        });
+        return (addr, handle);
+    }
+
+    // 40 A records, which come to well over 512 bytes
+    fn big_answer(query: DnsMessage) -> DnsMessage {
+        let mut response = answer(query);
+        for n in 2..=40 {
+            let mut record = response.answers[0].clone();
+            record.data = ResourceRecord::HostAddress(format!("192.0.2.{}/32", n).parse().unwrap());
+            response.answers.push(record);
+        }
+        return response;
+    }
+
+    fn answer(mut query: DnsMessage) -> DnsMessage {
+        query.header.qr = true;
+        query.header.ra = true;
+        query.answers.push(DnsRecord{
+            name: query.questions[0].name.clone(),
+            class: 1,
+            ttl: 60,
+            data: ResourceRecord::HostAddress("192.0.2.1/32".parse().unwrap()),
+        });
+        return query;
+    }
+
+    #[test]
+    fn test_resolver_query() {
+        let (addr, handle) = serve(1, |_, query| {
+            assert_eq!(Some(EdnsOpt::new(MAX_UDP_RESPONSE as u16)), query.edns);
+            vec![answer(query)]
+        });
+        let response = Resolver::new(addr).query("example.com", QType::A).unwrap();
+        handle.join().unwrap();
+        assert_eq!("example.com", response.answers[0].name);
+        assert_eq!(ResourceRecord::HostAddress("192.0.2.1/32".parse().unwrap()), response.answers[0].data);
+    }
+
+    #[test]
+    fn test_resolver_retries_once() {
+        // the first query is dropped, and a stray response with the wrong ID
+        // goes out before the real one
+        let (addr, handle) = serve(2, |n, query| match n {
+            0 => Vec::new(),
+            _ => {
+                let mut stray = answer(query.clone());
+                stray.header.id = stray.header.id.wrapping_add(1);
+                stray.answers.clear();
+                vec![stray, answer(query)]
+            },
+        });
+        let resolver = Resolver::new(addr).with_timeout(Duration::from_millis(200));
+        assert_eq!(1, resolver.query("example.com", QType::A).unwrap().answers.len());
+        handle.join().unwrap();
+
+        let (addr, handle) = serve(2, |_, _| Vec::new());
+        let resolver = Resolver::new(addr).with_timeout(Duration::from_millis(50));
+        assert!(matches!(resolver.query("example.com", QType::A), Err(ResolveError::Timeout)));
+        handle.join().unwrap();
+    }
+
+    fn record(name: &str, data: ResourceRecord) -> DnsRecord {
+        DnsRecord{name: name.into(), class: 1, ttl: 60, data}
+    }
+
+    fn cname(name: &str, target: &str) -> DnsRecord {
+        record(name, ResourceRecord::CanonicalName(target.into()))
+    }
+
+    // answers from a fixed set of records, without chasing any CNAMEs itself
+    fn answer_from(records: Vec<DnsRecord>) -> impl FnMut(usize, DnsMessage) -> Vec<DnsMessage> {
+        move |_, mut query| {
+            query.header.qr = true;
+            query.answers = records.iter()
+                .filter(|record| record.name == query.questions[0].name)
+                .cloned()
+                .collect();
+            vec![query]
+        }
+    }
+
+    #[test]
+    fn test_resolver_follows_cnames() {
+        let a = record("host.example.net", ResourceRecord::HostAddress("192.0.2.7/32".parse().unwrap()));
+        let records = vec![
+            cname("www.example.com", "web.example.org"),
+            cname("web.example.org", "host.example.net"),
+            a.clone(),
+        ];
+        let (addr, handle) = serve(3, answer_from(records.clone()));
+        let resolution = Resolver::new(addr).resolve("www.example.com", QType::A).unwrap();
+        handle.join().unwrap();
+        let expected = Resolution{
+            aliases: vec!["www.example.com".into(), "web.example.org".into()],
+            canonical_name: "host.example.net".into(),
+            answers: vec![a.clone()],
+        };
+        assert_eq!(expected, resolution);
+
+        // a server that chased the chain itself only needs asking once
+        let (addr, handle) = serve(1, move |_, query| {
+            let mut response = answer(query);
+            response.answers = records.clone();
+            vec![response]
+        });
+        assert_eq!(expected, Resolver::new(addr).resolve("www.example.com", QType::A).unwrap());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_resolver_hosts() {
+        let hosts = crate::dns::hosts::parse("192.0.2.99 example.com\n2001:db8::99 v6.example.com\n");
+        // only the names missing from the hosts table, or without an address
+        // of the right family there, get as far as the server
+        let (addr, handle) = serve(2, |n, query| {
+            let expected = vec!["www.example.com", "v6.example.com"];
+            assert_eq!(expected[n], query.questions[0].name);
+            vec![answer(query)]
+        });
+        let resolver = Resolver::new(addr).with_hosts(hosts);
+        let resolution = resolver.resolve("example.com.", QType::A).unwrap();
+        let a = record("example.com", ResourceRecord::HostAddress("192.0.2.99/32".parse().unwrap()));
+        assert_eq!(vec![DnsRecord{ttl: 0, ..a}], resolution.answers);
+        let resolution = resolver.resolve("V6.Example.com", QType::Aaaa).unwrap();
+        assert_eq!(ResourceRecord::HostAddressV6("2001:db8::99".parse().unwrap()), resolution.answers[0].data);
+
+        let a = ResourceRecord::HostAddress("192.0.2.1/32".parse().unwrap());
+        assert_eq!(a, resolver.resolve("www.example.com", QType::A).unwrap().answers[0].data);
+        assert_eq!(a, resolver.resolve("v6.example.com", QType::A).unwrap().answers[0].data);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_resolver_negative_cache() {
+        let soa = record("example.com", ResourceRecord::StartOfAuthority{
+            mname: "ns.example.com".into(),
+            rname: "hostmaster.example.com".into(),
+            serial: 1,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        });
+        let nxdomain = soa.clone();
+        // only the first A query and then the AAAA query reach the server
+        let (addr, handle) = serve(2, move |n, mut query| {
+            assert_eq!(vec![QType::A, QType::Aaaa][n], query.questions[0].qtype);
+            query.header.qr = true;
+            query.header.set_rcode(Rcode::NxDomain);
+            query.authorities = vec![DnsRecord{ttl: 3600, ..nxdomain.clone()}];
+            vec![query]
+        });
+        let resolver = Resolver::new(addr).with_timeout(Duration::from_millis(200));
+        let response = resolver.query("nowhere.example.com", QType::A).unwrap();
+        assert_eq!(Rcode::NxDomain, response.header.rcode());
+
+        let cached = resolver.query("NOWHERE.example.com.", QType::A).unwrap();
+        assert!(cached.header.is_response());
+        assert_eq!(Rcode::NxDomain, cached.header.rcode());
+        assert_eq!("NOWHERE.example.com", cached.questions[0].name.as_str());
+        // the negative TTL is the SOA's MINIMUM, some fraction of a second of which has passed
+        assert_eq!(1, cached.authorities.len());
+        assert_eq!(soa.data, cached.authorities[0].data);
+        assert!(299 <= cached.authorities[0].ttl && cached.authorities[0].ttl <= 300);
+
+        let response = resolver.query("nowhere.example.com", QType::Aaaa).unwrap();
+        assert_eq!(Rcode::NxDomain, response.header.rcode());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_resolver_cname_loops() {
+        let (addr, handle) = serve(1, answer_from(vec![cname("loop.example.com", "LOOP.example.com")]));
+        match Resolver::new(addr).resolve("loop.example.com", QType::A) {
+            Err(ResolveError::CnameLoop(name)) => assert_eq!("LOOP.example.com", name),
+            other => panic!("expected a CNAME loop, got {:?}", other),
+        }
+        handle.join().unwrap();
+
+        // a long chain that never loops still gives up eventually
+        let chain: Vec<DnsRecord> = (0..10)
+            .map(|n| cname(&format!("{}.example.com", n), &format!("{}.example.com", n + 1)))
+            .collect();
+        let (addr, handle) = serve(1, move |_, query| {
+            let mut response = answer(query);
+            response.answers = chain.clone();
+            vec![response]
+        });
+        assert!(matches!(Resolver::new(addr).resolve("0.example.com", QType::A), Err(ResolveError::TooManyCnames)));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_resolver_query_tcp() {
+        let (addr, handle) = serve_tcp("127.0.0.1:0".parse().unwrap(), 1, |_, query| {
+            // a stray response with the wrong ID first, as over UDP
+            let mut stray = answer(query.clone());
+            stray.header.id = stray.header.id.wrapping_add(1);
+            vec![stray, big_answer(query)]
+        });
+        let response = Resolver::new(addr).query_tcp("example.com", QType::A).unwrap();
+        handle.join().unwrap();
+        assert!(response.to_bytes().unwrap().len() > 512);
+        assert_eq!(40, response.answers.len());
+    }
+
+    #[test]
+    fn test_tcp_message_too_long() {
+        let mut stream = Vec::new();
+        write_tcp_message(&mut stream, &vec![1; 65535]).unwrap();
+        assert_eq!(vec![1; 65535], read_tcp_message(&mut &stream[..]).unwrap());
+        let mut stream = Vec::new();
+        let error = write_tcp_message(&mut stream, &vec![1; 65536]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn test_resolver_truncated() {
+        // the truncated UDP response has no answers, and the TCP one has them all
+        let (addr, udp) = serve(1, |_, mut query| {
+            query.header.qr = true;
+            query.header.tc = true;
+            vec![query]
+        });
+        let (_, tcp) = serve_tcp(addr, 1, |_, query| vec![big_answer(query)]);
+        let response = Resolver::new(addr).query("example.com", QType::A).unwrap();
+        udp.join().unwrap();
+        tcp.join().unwrap();
+        assert!(!response.header.truncated());
+        assert_eq!(40, response.answers.len());
+
+        // truncated even over TCP
+        let (addr, handle) = serve_tcp("127.0.0.1:0".parse().unwrap(), 1, |_, query| {
+            let mut response = answer(query);
+            response.header.tc = true;
+            vec![response]
+        });
+        match Resolver::new(addr).query_tcp("example.com", QType::A) {
+            Err(ResolveError::Truncated(response)) => assert!(response.header.tc),
+            other => panic!("expected a truncated response, got {:?}", other),
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_resolver_transfer() {
+        let a = |n| (300, ResourceRecord::HostAddress(format!("192.0.2.{}/32", n).parse().unwrap()));
+        let soa = ResourceRecord::StartOfAuthority{
+            mname: "ns.example.com".into(),
+            rname: "hostmaster.example.com".into(),
+            serial: 2020101301,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        };
+        let mut db = NameServerDb::new();
+        let ns = ResourceRecord::NameServer("ns.example.com".into());
+        db.insert("example.com".into(), vec![(3600, soa), (86400, ns), a(1)]);
+        db.insert("ns.example.com".into(), vec![a(53)]);
+        // enough records to need more than one message, and enough bytes for
+        // some of them to be split up by size
+        db.insert("www.example.com".into(), (1..=100).map(a).collect());
+        db.insert("txt.example.com".into(), (0..64).map(|n| (300, ResourceRecord::Text(vec![vec![n; 255]; 4]))).collect());
+        let zone = db.clone();
+        db.insert("example.org".into(), vec![a(2)]);
+        let server = StaticNameServer::new(db);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                server.serve_transfer(&mut stream).unwrap();
+            }
+        });
+        let resolver = Resolver::new(addr);
+        let transferred = resolver.transfer("example.com").unwrap();
+        assert_eq!(zone, transferred);
+        match resolver.transfer("example.org") {
+            Err(ResolveError::TransferFailed(Rcode::Refused)) => (),
+            other => panic!("expected the transfer to be refused, got {:?}", other),
+        }
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,461 @@
+//! A name server that answers from a fixed set of records.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    sync::Mutex,
+};
+
+use super::{
+    resolver::{read_tcp_message, write_tcp_message},
+    DnsEncodeError, DnsMessage, DnsRecord, DomainName, NameServer, NameServerDb, QClass, QType, Rcode, ResourceRecord,
+};
+
+// the most records per message in a zone transfer
+const TRANSFER_CHUNK: usize = 64;
+// the most a message in a zone transfer can take up, which is as long as the
+// length in front of it over TCP can say
+const TRANSFER_MAX_LEN: usize = u16::MAX as usize;
+
+fn record(name: &DomainName, ttl: u32, data: ResourceRecord) -> DnsRecord {
+    DnsRecord{name: name.clone(), class: QClass::In.into(), ttl, data}
+}
+
+/// Records of the same type are handed out in a different order each time
+/// their name is looked up, rotating one place per lookup as BIND does, so
+/// that clients taking the first address spread out over all of them.
+pub struct StaticNameServer {
+    db: NameServerDb,
+    /// How many times each name has been looked up.
+    rotations: Mutex<HashMap<DomainName, usize>>,
+}
+
+impl StaticNameServer {
+    pub fn new(db: NameServerDb) -> StaticNameServer {
+        StaticNameServer{db, rotations: Mutex::new(HashMap::new())}
+    }
+
+    /// An authoritative response to the first question in `query`, with
+    /// each record at its TTL from the database.
+    ///
+    /// NS records in the answer bring the addresses of any of their name
+    /// servers that are in the zone along in the additional section, as glue:
+    /// without it, a resolver couldn't look up a server inside the zone it
+    /// serves.
+    pub fn respond(&self, query: &DnsMessage) -> DnsMessage {
+        let question = match query.questions.first() {
+            Some(question) => question,
+            None => {
+                let mut response = DnsMessage::answer(query, Vec::new());
+                response.header.set_rcode(Rcode::FormErr);
+                return response;
+            },
+        };
+        let answers: Vec<DnsRecord> = self.find_records(question.name.clone()).unwrap_or_default().into_iter()
+            .filter(|(_, rr)| self.relevant(question.qtype, rr.type_name()))
+            .map(|(ttl, data)| record(&question.name, ttl, data))
+            .collect();
+        let mut response = DnsMessage::answer_records(query, answers);
+        if response.answers.is_empty() && self.source_of(&question.name).is_some() {
+            // the name is there, just without records of that type
+            response.header.set_rcode(Rcode::NoError);
+        }
+        for answer in response.answers.iter() {
+            let ns = match &answer.data {
+                ResourceRecord::NameServer(ns) => ns,
+                _ => continue,
+            };
+            let glue = self.db.get(ns).into_iter().flatten()
+                .filter(|(_, rr)| matches!(rr, ResourceRecord::HostAddress(_) | ResourceRecord::HostAddressV6(_)));
+            for (ttl, data) in glue {
+                let glue = record(ns, *ttl, data.clone());
+                if !response.additionals.contains(&glue) {
+                    response.additionals.push(glue);
+                }
+            }
+        }
+        return response;
+    }
+
+    /// `respond`'s response to `query`, encoded to fit in a UDP datagram the
+    /// client can take, and marked truncated if it had to lose any answers.
+    pub fn respond_udp(&self, query: &DnsMessage) -> Result<Vec<u8>, DnsEncodeError> {
+        self.respond(query).to_bytes_limited(query.max_udp_payload())
+    }
+
+    /// The messages answering an AXFR `query` for a zone (RFC 5936): its
+    /// SOA, every other record at or below it, and the SOA again, spread over
+    /// as many messages as it takes to keep each one to `TRANSFER_CHUNK`
+    /// records and `TRANSFER_MAX_LEN` bytes. A zone without an SOA here is
+    /// refused.
+    pub fn transfer(&self, query: &DnsMessage) -> Vec<DnsMessage> {
+        let refuse = |rcode| {
+            let mut response = DnsMessage::answer(query, Vec::new());
+            response.header.set_rcode(rcode);
+            vec![response]
+        };
+        let zone = match query.questions.first() {
+            Some(question) if question.qtype == QType::Axfr => &question.name,
+            _ => return refuse(Rcode::FormErr),
+        };
+        let is_soa = |rr: &ResourceRecord| matches!(rr, ResourceRecord::StartOfAuthority{..});
+        let soa = match self.db.get(zone).into_iter().flatten().find(|(_, rr)| is_soa(rr)) {
+            Some((ttl, soa)) => record(zone, *ttl, soa.clone()),
+            None => return refuse(Rcode::Refused),
+        };
+        let zone_labels = labels(zone);
+        let mut names: Vec<&DomainName> = self.db.keys().filter(|name| is_within(name, &zone_labels)).collect();
+        // parents before children, starting with the zone itself
+        names.sort_unstable_by_key(|name| {
+            labels(name).iter().rev().map(|label| label.to_ascii_lowercase()).collect::<Vec<String>>()
+        });
+        let mut records = vec![soa.clone()];
+        for name in names {
+            for (ttl, rr) in self.db[name].iter() {
+                if !(name == zone && is_soa(rr)) {
+                    records.push(record(name, *ttl, rr.clone()));
+                }
+            }
+        }
+        records.push(soa);
+
+        // sizes are counted without compression, so the encoded messages can
+        // only come out smaller
+        let header_len = 12 + query.questions.iter().map(|question| question.name.wire_len() + 4).sum::<usize>();
+        let mut messages = Vec::new();
+        let mut chunk = Vec::new();
+        let mut len = header_len;
+        for record in records {
+            let record_len = record.name.wire_len() + 10 + record.data.rdata_bytes().map_or(0, |rdata| rdata.len());
+            if !chunk.is_empty() && (chunk.len() == TRANSFER_CHUNK || len + record_len > TRANSFER_MAX_LEN) {
+                messages.push(DnsMessage::answer_records(query, std::mem::take(&mut chunk)));
+                len = header_len;
+            }
+            len += record_len;
+            chunk.push(record);
+        }
+        messages.push(DnsMessage::answer_records(query, chunk));
+        return messages;
+    }
+
+    /// Reads an AXFR query off `stream`, a TCP connection, and writes back
+    /// the zone transfer.
+    pub fn serve_transfer<S: Read + Write>(&self, stream: &mut S) -> io::Result<()> {
+        let query = DnsMessage::from_bytes(&read_tcp_message(stream)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for response in self.transfer(&query) {
+            let bytes = response.to_bytes().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            write_tcp_message(stream, &bytes)?;
+        }
+        return Ok(());
+    }
+
+    // whether any name in the zone is `name` or below it, which counts an
+    // empty non-terminal like the `b.example` in a zone with `a.b.example`
+    fn exists(&self, name: &[&str]) -> bool {
+        self.db.keys().any(|key| is_within(key, name))
+    }
+
+    // the name whose records answer for `name`: itself if it exists, or
+    // otherwise the wildcard just below its closest encloser, the nearest
+    // ancestor that does (RFC 4592 3.3.1)
+    fn source_of(&self, domain_name: &DomainName) -> Option<DomainName> {
+        let name = labels(domain_name);
+        if self.exists(&name) {
+            return Some(domain_name.clone());
+        }
+        let encloser = (0..name.len()).rev()
+            .map(|n| &name[name.len() - n..])
+            .find(|&ancestor| self.exists(ancestor))?;
+        let mut wildcard = vec!["*"];
+        wildcard.extend_from_slice(encloser);
+        let wildcard: DomainName = wildcard.join(".").into();
+        return Some(wildcard).filter(|wildcard| self.db.contains_key(wildcard));
+    }
+
+    // `findset`, keeping each record's TTL
+    fn find_records(&self, domain_name: DomainName) -> Option<Vec<(u32, ResourceRecord)>> {
+        let source = self.source_of(&domain_name)?;
+        let records = self.db.get(&source).filter(|records| !records.is_empty())?;
+        let mut rotations = self.rotations.lock().unwrap();
+        let rotation = rotations.entry(source).or_insert(0);
+        let records = rotate_rrsets(records, *rotation);
+        *rotation = rotation.wrapping_add(1);
+        return Some(records);
+    }
+}
+
+// each type's records moved `by` places towards the front, within the
+// positions that type already takes up, so other types stay where they are
+fn rotate_rrsets(records: &[(u32, ResourceRecord)], by: usize) -> Vec<(u32, ResourceRecord)> {
+    let mut rotated = records.to_vec();
+    let mut rtypes: Vec<u16> = records.iter().map(|(_, rr)| rr.rtype()).collect();
+    rtypes.sort_unstable();
+    rtypes.dedup();
+    for rtype in rtypes {
+        let slots: Vec<usize> = (0..records.len()).filter(|&n| records[n].1.rtype() == rtype).collect();
+        for (n, &slot) in slots.iter().enumerate() {
+            rotated[slot] = records[slots[(n + by) % slots.len()]].clone();
+        }
+    }
+    return rotated;
+}
+
+fn labels(name: &str) -> Vec<&str> {
+    name.split('.').filter(|label| !label.is_empty()).collect()
+}
+
+// whether `name` is `ancestor` or below it
+fn is_within(name: &str, ancestor: &[&str]) -> bool {
+    let name = labels(name);
+    return name.len() >= ancestor.len()
+        && name[name.len() - ancestor.len()..].iter().zip(ancestor).all(|(a, b)| a.eq_ignore_ascii_case(b));
+}
+
+impl NameServer for StaticNameServer {
+    /// Names with no records of their own are answered by a wildcard (`*`)
+    /// record set, if one covers them.
+    fn findset(&self, domain_name: DomainName) -> Option<Vec<ResourceRecord>> {
+        let records = self.find_records(domain_name)?;
+        return Some(records.into_iter().map(|(_, rr)| rr).collect());
+    }
+
+    fn relevant(&self, query_type: QType, type_: String) -> bool {
+        query_type == QType::Any || query_type.to_string() == type_
+    }
+
+    fn copy(&self, rr: &ResourceRecord) -> ResourceRecord {
+        rr.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::EdnsOpt;
+
+    // records at an hour's TTL, for tests that aren't about TTLs
+    fn hourly(records: Vec<ResourceRecord>) -> Vec<(u32, ResourceRecord)> {
+        records.into_iter().map(|rr| (3600, rr)).collect()
+    }
+
+    fn zone() -> StaticNameServer {
+        let mut db = NameServerDb::new();
+        db.insert("example.com".into(), hourly(vec![
+            ResourceRecord::HostAddress("192.0.2.1/32".parse().unwrap()),
+            ResourceRecord::HostAddress("192.0.2.2/32".parse().unwrap()),
+            ResourceRecord::MailExchanger{preference: 10, exchange: "mail.example.com".into()},
+        ]));
+        db.insert("www.example.com".into(), hourly(vec![
+            ResourceRecord::CanonicalName("example.com".into()),
+        ]));
+        db.insert("empty.example.com".into(), Vec::new());
+        StaticNameServer::new(db)
+    }
+
+    #[test]
+    fn test_static_name_server_lookup() {
+        let server = zone();
+        let expected = vec![
+            ResourceRecord::HostAddress("192.0.2.1/32".parse().unwrap()),
+            ResourceRecord::HostAddress("192.0.2.2/32".parse().unwrap()),
+        ];
+        assert_eq!(expected, server.lookup("example.com".into(), QType::A));
+        assert_eq!(3, server.lookup("example.com".into(), QType::Any).len());
+        assert!(server.lookup("example.com".into(), QType::Aaaa).is_empty());
+        assert!(server.lookup("nowhere.example.com".into(), QType::A).is_empty());
+        assert!(server.lookup("example.com".into(), QType::Other(65)).is_empty());
+        assert_eq!(None, server.findset("empty.example.com".into()));
+        assert_eq!(1, server.findset("www.example.com".into()).unwrap().len());
+    }
+
+    #[test]
+    fn test_static_name_server_rotation() {
+        let a = |n| ResourceRecord::HostAddress(format!("192.0.2.{}/32", n).parse().unwrap());
+        let mx = ResourceRecord::MailExchanger{preference: 10, exchange: "mail.example.com".into()};
+        let mut db = NameServerDb::new();
+        db.insert("example.com".into(), hourly(vec![a(1), mx.clone(), a(2), a(3)]));
+        let server = StaticNameServer::new(db);
+
+        let expected = vec![
+            vec![a(1), a(2), a(3)],
+            vec![a(2), a(3), a(1)],
+            vec![a(3), a(1), a(2)],
+            vec![a(1), a(2), a(3)],
+        ];
+        for addresses in expected.into_iter() {
+            assert_eq!(addresses, server.lookup("example.com".into(), QType::A));
+        }
+        // the MX keeps its place between the A records, and names are counted
+        // case-insensitively
+        assert_eq!(vec![a(2), mx, a(3), a(1)], server.findset("EXAMPLE.com".into()).unwrap());
+    }
+
+    #[test]
+    fn test_static_name_server_wildcard() {
+        let a = |n| ResourceRecord::HostAddress(format!("192.0.2.{}/32", n).parse().unwrap());
+        let mut db = NameServerDb::new();
+        db.insert("example.com".into(), hourly(vec![a(1)]));
+        db.insert("*.example.com".into(), hourly(vec![a(99)]));
+        db.insert("www.example.com".into(), hourly(vec![a(2)]));
+        db.insert("mail.example.com".into(), hourly(vec![ResourceRecord::Text(vec![b"no address".to_vec()])]));
+        db.insert("host.sub.example.com".into(), hourly(vec![a(3)]));
+        let server = StaticNameServer::new(db);
+
+        assert_eq!(vec![a(99)], server.lookup("anything.example.com".into(), QType::A));
+        assert_eq!(vec![a(99)], server.lookup("two.labels.example.com".into(), QType::A));
+        assert_eq!(vec![a(99)], server.lookup("*.example.com".into(), QType::A));
+        // names that exist aren't covered, even without records of the type
+        assert_eq!(vec![a(2)], server.lookup("www.example.com".into(), QType::A));
+        assert!(server.lookup("mail.example.com".into(), QType::A).is_empty());
+        assert_eq!(vec![a(1)], server.lookup("example.com".into(), QType::A));
+        // `sub.example.com` exists for having a name below it, so it's the
+        // closest encloser, and there's no `*.sub.example.com`
+        assert!(server.lookup("sub.example.com".into(), QType::A).is_empty());
+        assert!(server.lookup("other.sub.example.com".into(), QType::A).is_empty());
+        assert!(server.lookup("example.org".into(), QType::A).is_empty());
+
+        let response = server.respond(&DnsMessage::query("Anything.Example.com", QType::A));
+        assert_eq!(Rcode::NoError, response.header.rcode());
+        assert_eq!("Anything.Example.com", &*response.answers[0].name);
+        let response = server.respond(&DnsMessage::query("anything.example.com", QType::Mx));
+        assert_eq!(Rcode::NoError, response.header.rcode());
+        assert!(response.answers.is_empty());
+        let response = server.respond(&DnsMessage::query("other.sub.example.com", QType::A));
+        assert_eq!(Rcode::NxDomain, response.header.rcode());
+    }
+
+    #[test]
+    fn test_static_name_server_glue() {
+        let mut db = NameServerDb::new();
+        db.insert("sub.example.com".into(), hourly(vec![
+            ResourceRecord::NameServer("ns1.sub.example.com".into()),
+            ResourceRecord::NameServer("ns.elsewhere.example".into()),
+        ]));
+        db.insert("ns1.sub.example.com".into(), hourly(vec![
+            ResourceRecord::HostAddress("192.0.2.53/32".parse().unwrap()),
+            ResourceRecord::HostAddressV6("2001:db8::53".parse().unwrap()),
+            ResourceRecord::Text(vec![b"not glue".to_vec()]),
+        ]));
+        let server = StaticNameServer::new(db);
+
+        let query = DnsMessage::query("sub.example.com", QType::Ns);
+        let response = server.respond(&query);
+        assert_eq!(query.header.id, response.header.id);
+        assert_eq!(Rcode::NoError, response.header.rcode());
+        assert_eq!(2, response.answers.len());
+        // only the in-zone name server has glue
+        let glue: Vec<(&str, &ResourceRecord)> = response.additionals.iter()
+            .map(|record| (&*record.name, &record.data))
+            .collect();
+        assert_eq!(vec![
+            ("ns1.sub.example.com", &ResourceRecord::HostAddress("192.0.2.53/32".parse().unwrap())),
+            ("ns1.sub.example.com", &ResourceRecord::HostAddressV6("2001:db8::53".parse().unwrap())),
+        ], glue);
+
+        // no glue for anything but NS answers
+        let response = server.respond(&DnsMessage::query("ns1.sub.example.com", QType::A));
+        assert_eq!(1, response.answers.len());
+        assert!(response.additionals.is_empty());
+
+        let response = server.respond(&DnsMessage::query("ns1.sub.example.com", QType::Mx));
+        assert_eq!(Rcode::NoError, response.header.rcode());
+        assert!(response.answers.is_empty());
+        let response = server.respond(&DnsMessage::query("nowhere.example.com", QType::A));
+        assert_eq!(Rcode::NxDomain, response.header.rcode());
+        let response = server.respond(&DnsMessage::default());
+        assert_eq!(Rcode::FormErr, response.header.rcode());
+    }
+
+    #[test]
+    fn test_static_name_server_ttls() {
+        let a = |n| ResourceRecord::HostAddress(format!("192.0.2.{}/32", n).parse().unwrap());
+        let mut db = NameServerDb::new();
+        db.insert("example.com".into(), vec![
+            (86400, ResourceRecord::NameServer("ns.example.com".into())),
+            (300, a(1)),
+            (600, a(2)),
+        ]);
+        db.insert("ns.example.com".into(), vec![(60, a(53))]);
+        let server = StaticNameServer::new(db);
+
+        // each record keeps its own TTL as the set rotates
+        let served = |response: DnsMessage| -> Vec<(u32, ResourceRecord)> {
+            response.answers.into_iter().map(|record| (record.ttl, record.data)).collect()
+        };
+        let query = DnsMessage::query("example.com", QType::A);
+        assert_eq!(vec![(300, a(1)), (600, a(2))], served(server.respond(&query)));
+        assert_eq!(vec![(600, a(2)), (300, a(1))], served(server.respond(&query)));
+        let response = server.respond(&DnsMessage::query("example.com", QType::Ns));
+        assert_eq!(86400, response.answers[0].ttl);
+        assert_eq!(60, response.additionals[0].ttl);
+    }
+
+    #[test]
+    fn test_static_name_server_respond_udp() {
+        let mut db = NameServerDb::new();
+        let addresses = (1..=40).map(|n| ResourceRecord::HostAddress(format!("192.0.2.{}/32", n).parse().unwrap()));
+        db.insert("example.com".into(), hourly(addresses.collect()));
+        let server = StaticNameServer::new(db);
+        let mut query = DnsMessage::query("example.com", QType::A);
+        assert_eq!(512, query.max_udp_payload());
+
+        let bytes = server.respond_udp(&query).unwrap();
+        assert!(bytes.len() <= 512);
+        let response = DnsMessage::from_bytes(&bytes).unwrap();
+        assert!(response.header.truncated());
+        assert!(response.answers.len() < 40);
+
+        // asking for smaller than the minimum still gets 512
+        query.edns = Some(EdnsOpt::new(100));
+        assert_eq!(512, query.max_udp_payload());
+        query.edns = Some(EdnsOpt::new(4096));
+        assert_eq!(4096, query.max_udp_payload());
+        let response = DnsMessage::from_bytes(&server.respond_udp(&query).unwrap()).unwrap();
+        assert!(!response.header.truncated());
+        assert_eq!(40, response.answers.len());
+    }
+
+    #[test]
+    fn test_static_name_server_transfer_size() {
+        // 64 records of over a KiB each, which don't all fit in one message
+        let txt = |n: u8| (3600, ResourceRecord::Text(vec![vec![n; 255]; 4]));
+        let mut db = NameServerDb::new();
+        db.insert("example.com".into(), hourly(vec![ResourceRecord::StartOfAuthority{
+            mname: "ns.example.com".into(),
+            rname: "hostmaster.example.com".into(),
+            serial: 1,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        }]));
+        db.insert("txt.example.com".into(), (0..64).map(txt).collect());
+        let server = StaticNameServer::new(db);
+
+        let messages = server.transfer(&DnsMessage::query("example.com", QType::Axfr));
+        assert_eq!(2, messages.len());
+        for message in messages.iter() {
+            assert!(message.to_bytes().unwrap().len() <= TRANSFER_MAX_LEN);
+        }
+        assert_eq!(66, messages.iter().map(|message| message.answers.len()).sum::<usize>());
+    }
+
+    #[test]
+    fn test_static_name_server_names() {
+        let server = zone();
+        assert_eq!(3, server.ord("www.example.com".into()));
+        assert_eq!(0, server.ord("".into()));
+        let levels: Vec<Option<DomainName>> = (0..5).map(|n| server.right("a.b.c".into(), n)).collect();
+        let expected = vec![Some("a.b.c".into()), Some("b.c".into()), Some("c".into()), Some("".into()), None];
+        assert_eq!(expected, levels);
+        // the spelling of the labels kept is left alone
+        assert_eq!("Example.COM", server.right("www.Example.COM".into(), 1).unwrap().as_str());
+        assert_eq!(Some("".into()), server.right("".into(), 0));
+        assert_eq!(None, server.right("".into(), 1));
+        assert_eq!(None, server.right("www.example.com".into(), usize::MAX));
+        assert!(server.relevant(QType::Mx, "MX".to_string()));
+        assert!(server.relevant(QType::Any, "TYPE99".to_string()));
+        assert!(!server.relevant(QType::A, "AAAA".to_string()));
+    }
+}
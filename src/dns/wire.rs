@@ -0,0 +1,1346 @@
+//! Reading and writing DNS messages in the RFC 1035 wire format.
+//!
+//! Domain names are returned without a trailing dot, so the root is `""`.
+
+use std::{collections::HashMap, convert::TryFrom, fmt, net::{Ipv4Addr, Ipv6Addr}};
+
+use ipnet::Ipv4Net;
+
+use super::{
+    DnsHeader, DnsMessage, DnsQuestion, DnsRecord, DomainName, DomainNameError, EdnsOpt, QClass, QType,
+    ResourceRecord, MAX_NAME_LEN,
+};
+
+// as plain numbers so they can be matched against the TYPE of a record
+pub(super) const TYPE_A: u16 = 1;
+pub(super) const TYPE_NS: u16 = 2;
+pub(super) const TYPE_CNAME: u16 = 5;
+pub(super) const TYPE_SOA: u16 = 6;
+pub(super) const TYPE_PTR: u16 = 12;
+pub(super) const TYPE_MX: u16 = 15;
+pub(super) const TYPE_TXT: u16 = 16;
+pub(super) const TYPE_AAAA: u16 = 28;
+pub(super) const TYPE_SRV: u16 = 33;
+pub(super) const TYPE_DS: u16 = 43;
+pub(super) const TYPE_RRSIG: u16 = 46;
+pub(super) const TYPE_NSEC: u16 = 47;
+pub(super) const TYPE_DNSKEY: u16 = 48;
+// only ever sent as `EdnsOpt`, so there's no `QType` for it
+pub(super) const TYPE_OPT: u16 = 41;
+
+const HEADER_LEN: usize = 12;
+const MAX_POINTER: usize = 0x3FFF;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DnsParseError {
+    /// The message ended in the middle of a field.
+    UnexpectedEof,
+    /// A label length byte used reserved or unsupported high bits, or the
+    /// label wasn't valid UTF-8. The high bits also keep labels from being
+    /// over 63 octets.
+    InvalidLabel,
+    /// A name's labels added up to more than 255 octets.
+    NameTooLong,
+    /// A compression pointer to the given offset pointed forwards or back into
+    /// the name it was part of.
+    InvalidPointer(usize),
+    /// A record's RDATA didn't fit the layout its type requires.
+    InvalidRdata(u16),
+    /// The additional section had more than one OPT record, or one that wasn't
+    /// owned by the root.
+    InvalidOpt,
+    /// There were bytes left over after the last section.
+    TrailingData,
+}
+
+impl fmt::Display for DnsParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DnsParseError::UnexpectedEof => write!(f, "DNS message ended unexpectedly"),
+            DnsParseError::InvalidLabel => write!(f, "DNS message has an invalid label"),
+            DnsParseError::NameTooLong => write!(f, "DNS message has a name over {} octets", MAX_NAME_LEN),
+            DnsParseError::InvalidPointer(offset) => {
+                write!(f, "DNS message has an invalid compression pointer to offset {}", offset)
+            },
+            DnsParseError::InvalidRdata(rtype) => {
+                write!(f, "DNS message has invalid RDATA for record type {}", rtype)
+            },
+            DnsParseError::InvalidOpt => write!(f, "DNS message has an invalid or repeated OPT record"),
+            DnsParseError::TrailingData => write!(f, "DNS message has trailing data"),
+        }
+    }
+}
+
+impl std::error::Error for DnsParseError {}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DnsEncodeError {
+    /// A name was over the RFC 1035 length limits, so it couldn't be read back.
+    InvalidName(DomainNameError),
+    /// A record's RDATA, or an OPT record's options, came to the given number
+    /// of octets, more than its 16-bit RDLENGTH can hold.
+    RdataTooLong(usize),
+    /// A section had the given number of entries, more than its 16-bit count
+    /// in the header can hold.
+    TooManyRecords(usize),
+}
+
+impl fmt::Display for DnsEncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DnsEncodeError::InvalidName(e) => write!(f, "{}", e),
+            DnsEncodeError::RdataTooLong(len) => write!(f, "RDATA is {} octets, over the limit of 65535", len),
+            DnsEncodeError::TooManyRecords(count) => {
+                write!(f, "DNS message section has {} entries, over the limit of 65535", count)
+            },
+        }
+    }
+}
+
+impl std::error::Error for DnsEncodeError {}
+
+impl From<DomainNameError> for DnsEncodeError {
+    fn from(e: DomainNameError) -> DnsEncodeError {
+        DnsEncodeError::InvalidName(e)
+    }
+}
+
+/// A cursor over a whole message.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DnsParseError> {
+        if self.buf.len() - self.pos < len {
+            return Err(DnsParseError::UnexpectedEof);
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        return Ok(bytes);
+    }
+
+    // whatever is left before `end`, or `None` if reading has already gone past it
+    fn take_until(&mut self, end: usize) -> Option<&'a [u8]> {
+        let len = end.checked_sub(self.pos)?;
+        return self.take(len).ok();
+    }
+
+    fn read_u8(&mut self) -> Result<u8, DnsParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, DnsParseError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DnsParseError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a name made of literal labels, possibly ending in a compression
+    /// pointer to the rest of the name elsewhere in the message.
+    ///
+    /// Each pointer has to land before the start of the labels that led to
+    /// it, which rules out loops, since every jump moves strictly backwards.
+    fn read_name(&mut self) -> Result<DomainName, DnsParseError> {
+        let mut labels = Vec::new();
+        // counting the root's zero length byte up front
+        let mut wire_len = 1;
+        let mut segment_start = self.pos;
+        // where reading resumes once the name is done, set by the first pointer
+        let mut resume_at = None;
+        loop {
+            let len = self.read_u8()? as usize;
+            if len == 0 {
+                break;
+            }
+            match len & 0xC0 {
+                0x00 => {
+                    wire_len += len + 1;
+                    if wire_len > MAX_NAME_LEN {
+                        return Err(DnsParseError::NameTooLong);
+                    }
+                    let label = std::str::from_utf8(self.take(len)?).map_err(|_| DnsParseError::InvalidLabel)?;
+                    labels.push(label);
+                },
+                0xC0 => {
+                    let offset = (len & 0x3F) << 8 | self.read_u8()? as usize;
+                    if offset >= segment_start {
+                        return Err(DnsParseError::InvalidPointer(offset));
+                    }
+                    resume_at.get_or_insert(self.pos);
+                    self.pos = offset;
+                    segment_start = offset;
+                },
+                _ => return Err(DnsParseError::InvalidLabel),
+            }
+        }
+        if let Some(pos) = resume_at {
+            self.pos = pos;
+        }
+        return Ok(labels.join(".").into());
+    }
+
+    fn read_header(&mut self) -> Result<DnsHeader, DnsParseError> {
+        let id = self.read_u16()?;
+        let flags = self.read_u16()?;
+        Ok(DnsHeader{
+            id,
+            qr: flags & 0x8000 != 0,
+            opcode: ((flags >> 11) & 0xF) as u8,
+            aa: flags & 0x0400 != 0,
+            tc: flags & 0x0200 != 0,
+            rd: flags & 0x0100 != 0,
+            ra: flags & 0x0080 != 0,
+            rcode: (flags & 0xF) as u8,
+        })
+    }
+
+    fn read_question(&mut self) -> Result<DnsQuestion, DnsParseError> {
+        let name = self.read_name()?;
+        let qtype = self.read_u16()?;
+        let qclass = self.read_u16()?;
+        Ok(DnsQuestion{
+            name,
+            qtype: QType::from_code(qtype),
+            qclass: QClass::from_code(qclass),
+        })
+    }
+
+    fn read_record(&mut self) -> Result<DnsRecord, DnsParseError> {
+        let name = self.read_name()?;
+        let rtype = self.read_u16()?;
+        let class = self.read_u16()?;
+        let ttl = self.read_u32()?;
+        let rdlength = self.read_u16()? as usize;
+        let end = self.pos + rdlength;
+        if end > self.buf.len() {
+            return Err(DnsParseError::UnexpectedEof);
+        }
+        let data = self.read_rdata(rtype, rdlength)?;
+        if self.pos != end {
+            return Err(DnsParseError::InvalidRdata(rtype));
+        }
+        return Ok(DnsRecord{name, class, ttl, data});
+    }
+
+    fn read_rdata(&mut self, rtype: u16, rdlength: usize) -> Result<ResourceRecord, DnsParseError> {
+        let end = self.pos + rdlength;
+        let data = match rtype {
+            TYPE_A => {
+                if rdlength != 4 {
+                    return Err(DnsParseError::InvalidRdata(rtype));
+                }
+                let octets = self.take(4)?;
+                let addr = Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]);
+                ResourceRecord::HostAddress(Ipv4Net::new(addr, 32).unwrap())
+            },
+            TYPE_AAAA => {
+                if rdlength != 16 {
+                    return Err(DnsParseError::InvalidRdata(rtype));
+                }
+                let octets = <[u8; 16]>::try_from(self.take(16)?).unwrap();
+                ResourceRecord::HostAddressV6(Ipv6Addr::from(octets))
+            },
+            TYPE_NS => ResourceRecord::NameServer(self.read_name()?),
+            TYPE_CNAME => ResourceRecord::CanonicalName(self.read_name()?),
+            TYPE_PTR => ResourceRecord::Pointer(self.read_name()?),
+            TYPE_SOA => ResourceRecord::StartOfAuthority{
+                mname: self.read_name()?,
+                rname: self.read_name()?,
+                serial: self.read_u32()?,
+                refresh: self.read_u32()?,
+                retry: self.read_u32()?,
+                expire: self.read_u32()?,
+                minimum: self.read_u32()?,
+            },
+            TYPE_MX => ResourceRecord::MailExchanger{preference: self.read_u16()?, exchange: self.read_name()?},
+            // any number of strings, each prefixed by its length
+            TYPE_TXT => {
+                let mut strings = Vec::new();
+                while self.pos < end {
+                    let len = self.read_u8()? as usize;
+                    if self.pos + len > end {
+                        return Err(DnsParseError::InvalidRdata(rtype));
+                    }
+                    strings.push(self.take(len)?.to_vec());
+                }
+                ResourceRecord::Text(strings)
+            },
+            TYPE_SRV => ResourceRecord::Service{
+                priority: self.read_u16()?,
+                weight: self.read_u16()?,
+                port: self.read_u16()?,
+                target: self.read_name()?,
+            },
+            TYPE_DNSKEY => ResourceRecord::DnsKey{
+                flags: self.read_u16()?,
+                protocol: self.read_u8()?,
+                algorithm: self.read_u8()?,
+                public_key: self.take_until(end).ok_or(DnsParseError::InvalidRdata(rtype))?.to_vec(),
+            },
+            TYPE_RRSIG => ResourceRecord::Signature{
+                type_covered: self.read_u16()?,
+                algorithm: self.read_u8()?,
+                labels: self.read_u8()?,
+                original_ttl: self.read_u32()?,
+                expiration: self.read_u32()?,
+                inception: self.read_u32()?,
+                key_tag: self.read_u16()?,
+                signer_name: self.read_name()?,
+                signature: self.take_until(end).ok_or(DnsParseError::InvalidRdata(rtype))?.to_vec(),
+            },
+            TYPE_DS => ResourceRecord::DelegationSigner{
+                key_tag: self.read_u16()?,
+                algorithm: self.read_u8()?,
+                digest_type: self.read_u8()?,
+                digest: self.take_until(end).ok_or(DnsParseError::InvalidRdata(rtype))?.to_vec(),
+            },
+            TYPE_NSEC => {
+                let next_name = self.read_name()?;
+                let bitmap = self.take_until(end).ok_or(DnsParseError::InvalidRdata(rtype))?;
+                let types = read_type_bitmap(bitmap).ok_or(DnsParseError::InvalidRdata(rtype))?;
+                ResourceRecord::NextSecure{next_name, types}
+            },
+            _ => ResourceRecord::Unknown{rtype, data: self.take(rdlength)?.to_vec()},
+        };
+        return Ok(data);
+    }
+}
+
+// the types set in an NSEC type bitmap (RFC 4034 4.1.2): windows of up to
+// 256 types, in increasing order, each with up to 32 octets of bits
+fn read_type_bitmap(mut bitmap: &[u8]) -> Option<Vec<u16>> {
+    let mut types = Vec::new();
+    let mut last_window = None;
+    while !bitmap.is_empty() {
+        let window = bitmap[0];
+        let len = *bitmap.get(1)? as usize;
+        if len == 0 || len > 32 || bitmap.len() < 2 + len || last_window.is_some_and(|last| window <= last) {
+            return None;
+        }
+        for (n, byte) in bitmap[2..2 + len].iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    types.push((window as u16) << 8 | (n * 8 + bit) as u16);
+                }
+            }
+        }
+        last_window = Some(window);
+        bitmap = &bitmap[2 + len..];
+    }
+    return Some(types);
+}
+
+impl EdnsOpt {
+    // unpacks an OPT record that was read like any other, as `Unknown`
+    fn from_record(record: DnsRecord) -> Result<EdnsOpt, DnsParseError> {
+        if !record.name.is_empty() {
+            return Err(DnsParseError::InvalidOpt);
+        }
+        let data = match record.data {
+            ResourceRecord::Unknown{data, ..} => data,
+            _ => return Err(DnsParseError::InvalidRdata(TYPE_OPT)),
+        };
+        let mut reader = Reader{buf: &data, pos: 0};
+        let mut options = Vec::new();
+        while reader.pos < data.len() {
+            let code = reader.read_u16().map_err(|_| DnsParseError::InvalidRdata(TYPE_OPT))?;
+            let len = reader.read_u16().map_err(|_| DnsParseError::InvalidRdata(TYPE_OPT))?;
+            let option = reader.take(len as usize).map_err(|_| DnsParseError::InvalidRdata(TYPE_OPT))?;
+            options.push((code, option.to_vec()));
+        }
+        return Ok(EdnsOpt{
+            udp_payload_size: record.class,
+            extended_rcode: (record.ttl >> 24) as u8,
+            version: (record.ttl >> 16) as u8,
+            dnssec_ok: record.ttl & 0x8000 != 0,
+            options,
+        });
+    }
+}
+
+impl ResourceRecord {
+    /// The TYPE code this record is sent with.
+    pub fn rtype(&self) -> u16 {
+        match self {
+            ResourceRecord::HostAddress(_) => TYPE_A,
+            ResourceRecord::HostAddressV6(_) => TYPE_AAAA,
+            ResourceRecord::MailExchanger{..} => TYPE_MX,
+            ResourceRecord::NameServer(_) => TYPE_NS,
+            ResourceRecord::StartOfAuthority{..} => TYPE_SOA,
+            ResourceRecord::CanonicalName(_) => TYPE_CNAME,
+            ResourceRecord::Pointer(_) => TYPE_PTR,
+            ResourceRecord::Text(_) => TYPE_TXT,
+            ResourceRecord::Service{..} => TYPE_SRV,
+            ResourceRecord::DnsKey{..} => TYPE_DNSKEY,
+            ResourceRecord::Signature{..} => TYPE_RRSIG,
+            ResourceRecord::DelegationSigner{..} => TYPE_DS,
+            ResourceRecord::NextSecure{..} => TYPE_NSEC,
+            ResourceRecord::Unknown{rtype, ..} => *rtype,
+        }
+    }
+
+    /// Reads the RDATA of a record of type `qtype`.
+    ///
+    /// Names in NS, CNAME, PTR, SOA and MX records can be compressed, so `rdata`
+    /// should be a slice of `msg`, the whole message it came in, for the
+    /// pointers to be followed. Taken on its own, `rdata` can only hold
+    /// uncompressed names.
+    pub fn parse_rdata(qtype: QType, rdata: &[u8], msg: &[u8]) -> Result<ResourceRecord, DnsParseError> {
+        let rtype = u16::from(qtype);
+        if let QType::Any | QType::Axfr = qtype {
+            return Err(DnsParseError::InvalidRdata(rtype));
+        }
+        let msg_start = msg.as_ptr() as usize;
+        let rdata_start = rdata.as_ptr() as usize;
+        let mut reader = if rdata_start >= msg_start && rdata_start + rdata.len() <= msg_start + msg.len() {
+            // stopping at the end of the RDATA, so nothing can be read past it
+            let start = rdata_start - msg_start;
+            Reader{buf: &msg[..start + rdata.len()], pos: start}
+        } else {
+            Reader{buf: rdata, pos: 0}
+        };
+        let data = reader.read_rdata(rtype, rdata.len())?;
+        if reader.pos != reader.buf.len() {
+            return Err(DnsParseError::InvalidRdata(rtype));
+        }
+        return Ok(data);
+    }
+
+    /// The RDATA this record is sent with, with every name written out in
+    /// full since there's no message for pointers to refer into.
+    pub fn rdata_bytes(&self) -> Result<Vec<u8>, DomainNameError> {
+        let mut writer = Writer{buf: Vec::new(), names: HashMap::new(), compress: false};
+        writer.write_rdata(self)?;
+        return Ok(writer.buf);
+    }
+}
+
+/// Accumulates a message, remembering where each name it has written starts so
+/// that later names sharing a suffix can point back to it.
+struct Writer {
+    buf: Vec<u8>,
+    names: HashMap<String, usize>,
+    compress: bool,
+}
+
+impl Writer {
+    fn write_u16(&mut self, n: u16) {
+        self.buf.extend_from_slice(&n.to_be_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.buf.extend_from_slice(&n.to_be_bytes());
+    }
+
+    fn write_name(&mut self, name: &DomainName) -> Result<(), DomainNameError> {
+        if !self.compress {
+            return self.write_name_uncompressed(name);
+        }
+        name.validate()?;
+        let labels: Vec<&str> = name.labels().collect();
+        for n in 0..labels.len() {
+            let suffix = labels[n..].join(".");
+            if let Some(&offset) = self.names.get(&suffix) {
+                self.write_u16(0xC000 | offset as u16);
+                return Ok(());
+            }
+            // pointers only have 14 bits, so names past that can't be pointed to
+            if self.buf.len() <= MAX_POINTER {
+                self.names.insert(suffix, self.buf.len());
+            }
+            self.buf.push(labels[n].len() as u8);
+            self.buf.extend_from_slice(labels[n].as_bytes());
+        }
+        self.buf.push(0);
+        return Ok(());
+    }
+
+    // for names that RFC 2782 and friends say must never be compressed
+    fn write_name_uncompressed(&mut self, name: &DomainName) -> Result<(), DomainNameError> {
+        name.validate()?;
+        for label in name.labels() {
+            self.buf.push(label.len() as u8);
+            self.buf.extend_from_slice(label.as_bytes());
+        }
+        self.buf.push(0);
+        return Ok(());
+    }
+
+    fn write_header(&mut self, header: &DnsHeader) {
+        let flags = (header.qr as u16) << 15
+            | (header.opcode as u16 & 0xF) << 11
+            | (header.aa as u16) << 10
+            | (header.tc as u16) << 9
+            | (header.rd as u16) << 8
+            | (header.ra as u16) << 7
+            | header.rcode as u16 & 0xF;
+        self.write_u16(header.id);
+        self.write_u16(flags);
+    }
+
+    fn write_rdata(&mut self, data: &ResourceRecord) -> Result<(), DomainNameError> {
+        match data {
+            ResourceRecord::HostAddress(net) => self.buf.extend_from_slice(&net.addr().octets()),
+            ResourceRecord::HostAddressV6(addr) => self.buf.extend_from_slice(&addr.octets()),
+            ResourceRecord::NameServer(name) | ResourceRecord::CanonicalName(name) | ResourceRecord::Pointer(name) => {
+                self.write_name(name)?
+            },
+            ResourceRecord::MailExchanger{preference, exchange} => {
+                self.write_u16(*preference);
+                self.write_name(exchange)?;
+            },
+            ResourceRecord::StartOfAuthority{mname, rname, serial, refresh, retry, expire, minimum} => {
+                self.write_name(mname)?;
+                self.write_name(rname)?;
+                for n in [serial, refresh, retry, expire, minimum].iter() {
+                    self.write_u32(**n);
+                }
+            },
+            // character-strings max out at 255 bytes, so longer strings are
+            // split into several
+            ResourceRecord::Text(strings) => {
+                for string in strings.iter() {
+                    let mut rest = &string[..];
+                    loop {
+                        let len = rest.len().min(255);
+                        self.buf.push(len as u8);
+                        self.buf.extend_from_slice(&rest[..len]);
+                        rest = &rest[len..];
+                        if rest.is_empty() {
+                            break;
+                        }
+                    }
+                }
+            },
+            ResourceRecord::Service{priority, weight, port, target} => {
+                self.write_u16(*priority);
+                self.write_u16(*weight);
+                self.write_u16(*port);
+                self.write_name_uncompressed(target)?;
+            },
+            ResourceRecord::DnsKey{flags, protocol, algorithm, public_key} => {
+                self.write_u16(*flags);
+                self.buf.push(*protocol);
+                self.buf.push(*algorithm);
+                self.buf.extend_from_slice(public_key);
+            },
+            ResourceRecord::Signature{
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer_name,
+                signature,
+            } => {
+                self.write_u16(*type_covered);
+                self.buf.push(*algorithm);
+                self.buf.push(*labels);
+                self.write_u32(*original_ttl);
+                self.write_u32(*expiration);
+                self.write_u32(*inception);
+                self.write_u16(*key_tag);
+                self.write_name_uncompressed(signer_name)?;
+                self.buf.extend_from_slice(signature);
+            },
+            ResourceRecord::DelegationSigner{key_tag, algorithm, digest_type, digest} => {
+                self.write_u16(*key_tag);
+                self.buf.push(*algorithm);
+                self.buf.push(*digest_type);
+                self.buf.extend_from_slice(digest);
+            },
+            ResourceRecord::NextSecure{next_name, types} => {
+                self.write_name_uncompressed(next_name)?;
+                self.write_type_bitmap(types);
+            },
+            ResourceRecord::Unknown{data, ..} => self.buf.extend_from_slice(data),
+        }
+        return Ok(());
+    }
+
+    fn write_type_bitmap(&mut self, types: &[u16]) {
+        let mut types = types.to_vec();
+        types.sort_unstable();
+        types.dedup();
+        let mut n = 0;
+        while n < types.len() {
+            let window = types[n] >> 8;
+            let mut bits = [0u8; 32];
+            let mut len = 0;
+            while n < types.len() && types[n] >> 8 == window {
+                let low = (types[n] & 0xFF) as usize;
+                bits[low / 8] |= 0x80 >> (low % 8);
+                len = low / 8 + 1;
+                n += 1;
+            }
+            self.buf.push(window as u8);
+            self.buf.push(len as u8);
+            self.buf.extend_from_slice(&bits[..len]);
+        }
+    }
+
+    fn write_record(&mut self, record: &DnsRecord) -> Result<(), DnsEncodeError> {
+        self.write_name(&record.name)?;
+        self.write_u16(record.data.rtype());
+        self.write_u16(record.class);
+        self.write_u32(record.ttl);
+        // RDLENGTH isn't known until the RDATA has been written
+        let rdlength_pos = self.buf.len();
+        self.write_u16(0);
+        self.write_rdata(&record.data)?;
+        let rdlength = self.buf.len() - rdlength_pos - 2;
+        let rdlength = u16::try_from(rdlength).map_err(|_| DnsEncodeError::RdataTooLong(rdlength))?;
+        self.buf[rdlength_pos..rdlength_pos + 2].copy_from_slice(&rdlength.to_be_bytes());
+        return Ok(());
+    }
+
+    fn write_opt(&mut self, opt: &EdnsOpt) -> Result<(), DnsEncodeError> {
+        // owned by the root
+        self.buf.push(0);
+        self.write_u16(TYPE_OPT);
+        self.write_u16(opt.udp_payload_size);
+        self.write_u32((opt.extended_rcode as u32) << 24 | (opt.version as u32) << 16 | (opt.dnssec_ok as u32) << 15);
+        let rdlength: usize = opt.options.iter().map(|(_, data)| 4 + data.len()).sum();
+        self.write_u16(u16::try_from(rdlength).map_err(|_| DnsEncodeError::RdataTooLong(rdlength))?);
+        // so each option's data is short enough for its own length too
+        for (code, data) in opt.options.iter() {
+            self.write_u16(*code);
+            self.write_u16(data.len() as u16);
+            self.buf.extend_from_slice(data);
+        }
+        return Ok(());
+    }
+}
+
+impl DnsMessage {
+    pub fn from_bytes(input: &[u8]) -> Result<DnsMessage, DnsParseError> {
+        if input.len() < HEADER_LEN {
+            return Err(DnsParseError::UnexpectedEof);
+        }
+        let mut reader = Reader{buf: input, pos: 0};
+        let header = reader.read_header()?;
+        let qdcount = reader.read_u16()?;
+        let ancount = reader.read_u16()?;
+        let nscount = reader.read_u16()?;
+        let arcount = reader.read_u16()?;
+
+        let questions = (0..qdcount).map(|_| reader.read_question())
+            .collect::<Result<Vec<DnsQuestion>, DnsParseError>>()?;
+        let mut read_records = |count| (0..count).map(|_| reader.read_record())
+            .collect::<Result<Vec<DnsRecord>, DnsParseError>>();
+        let answers = read_records(ancount)?;
+        let authorities = read_records(nscount)?;
+        let mut additionals = read_records(arcount)?;
+        if reader.pos != input.len() {
+            return Err(DnsParseError::TrailingData);
+        }
+        let mut edns = None;
+        for record in additionals.iter() {
+            if record.data.rtype() == TYPE_OPT {
+                if edns.is_some() {
+                    return Err(DnsParseError::InvalidOpt);
+                }
+                edns = Some(EdnsOpt::from_record(record.clone())?);
+            }
+        }
+        additionals.retain(|record| record.data.rtype() != TYPE_OPT);
+        return Ok(DnsMessage{header, questions, answers, authorities, additionals, edns});
+    }
+
+    /// Encodes the message, compressing every repeated name suffix into a
+    /// pointer to its first occurrence. Fails if any name is over the RFC 1035
+    /// length limits, or anything is too long or too many for the 16-bit
+    /// field that counts it, since it couldn't be read back.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnsEncodeError> {
+        self.to_bytes_limited(usize::MAX)
+    }
+
+    /// Encodes the message in at most `max_len` bytes, as a response over UDP
+    /// has to be: 512, or whatever the query's OPT record offered (see
+    /// `max_udp_payload`). Records that don't fit are dropped from the end,
+    /// and if any of them were answers or authorities, TC is set so that the
+    /// client knows to ask again over TCP; missing additionals are only
+    /// hints, so they don't count (RFC 2181 9). The OPT record is always kept.
+    pub fn to_bytes_limited(&self, max_len: usize) -> Result<Vec<u8>, DnsEncodeError> {
+        let mut writer = Writer{buf: Vec::with_capacity(512), names: HashMap::new(), compress: true};
+        writer.write_header(&self.header);
+        // the section counts go in once it's known what fit
+        writer.buf.extend_from_slice(&[0; 8]);
+        for question in self.questions.iter() {
+            writer.write_name(&question.name)?;
+            writer.write_u16(question.qtype.into());
+            writer.write_u16(question.qclass.into());
+        }
+        let opt_len = self.edns.as_ref().map_or(0, |opt| {
+            11 + opt.options.iter().map(|(_, data)| 4 + data.len()).sum::<usize>()
+        });
+        let mut counts = [self.questions.len(), 0, 0, 0];
+        let sections = [&self.answers, &self.authorities, &self.additionals];
+        'sections: for (n, section) in sections.iter().enumerate() {
+            for record in section.iter() {
+                let start = writer.buf.len();
+                writer.write_record(record)?;
+                if writer.buf.len().saturating_add(opt_len) > max_len {
+                    writer.buf.truncate(start);
+                    // a name in the dropped record can't be pointed to now
+                    writer.names.retain(|_, offset| *offset < start);
+                    if n < 2 {
+                        writer.buf[2] |= 0x02;
+                    }
+                    break 'sections;
+                }
+                counts[n + 1] += 1;
+            }
+        }
+        if let Some(opt) = &self.edns {
+            writer.write_opt(opt)?;
+            counts[3] += 1;
+        }
+        for (n, &count) in counts.iter().enumerate() {
+            let count = u16::try_from(count).map_err(|_| DnsEncodeError::TooManyRecords(count))?;
+            writer.buf[4 + 2 * n..6 + 2 * n].copy_from_slice(&count.to_be_bytes());
+        }
+        return Ok(writer.buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `dig example.com A` and its answer, with the response's names written out in full
+    const QUERY: &[u8] = &[
+        0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x01, 0x00, 0x01,
+    ];
+    const RESPONSE: &[u8] = &[
+        0x12, 0x34, 0x85, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00,
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x01, 0x00, 0x01,
+        // example.com. 3600 IN A 93.184.216.34
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04, 93, 184, 216, 34,
+        // example.com. 86400 IN NS a.iana-servers.net.
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x02, 0x00, 0x01, 0x00, 0x01, 0x51, 0x80, 0x00, 0x14,
+        0x01, b'a', 0x0c, b'i', b'a', b'n', b'a', b'-', b's', b'e', b'r', b'v', b'e', b'r', b's',
+        0x03, b'n', b'e', b't', 0x00,
+    ];
+
+    // the same response as a server would really send it, with every repeat
+    // of `example.com` replaced by a pointer back to the question
+    const COMPRESSED_RESPONSE: &[u8] = &[
+        0x12, 0x34, 0x85, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00,
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x01, 0x00, 0x01,
+        0xc0, 0x0c,
+        0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04, 93, 184, 216, 34,
+        0xc0, 0x0c,
+        0x00, 0x02, 0x00, 0x01, 0x00, 0x01, 0x51, 0x80, 0x00, 0x14,
+        0x01, b'a', 0x0c, b'i', b'a', b'n', b'a', b'-', b's', b'e', b'r', b'v', b'e', b'r', b's',
+        0x03, b'n', b'e', b't', 0x00,
+    ];
+
+    fn question() -> DnsQuestion {
+        DnsQuestion{name: "example.com".into(), qtype: QType::A, qclass: QClass::In}
+    }
+
+    #[test]
+    fn test_dns_message_from_bytes_query() {
+        let expected = DnsMessage{
+            header: DnsHeader{id: 0x1234, rd: true, ..DnsHeader::default()},
+            questions: vec![question()],
+            ..DnsMessage::default()
+        };
+        assert_eq!(Ok(expected), DnsMessage::from_bytes(QUERY));
+
+        // types and classes we have no name for still make it through, e.g.
+        // an HTTPS query in the unassigned class 2
+        let mut https_query = QUERY.to_vec();
+        https_query[26] = 65;
+        https_query[28] = 2;
+        let message = DnsMessage::from_bytes(&https_query).unwrap();
+        assert_eq!(QType::Other(65), message.questions[0].qtype);
+        assert_eq!(QClass::Other(2), message.questions[0].qclass);
+        assert_eq!(https_query, message.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_dns_message_from_bytes_response() {
+        let message = DnsMessage::from_bytes(RESPONSE).unwrap();
+        let header = DnsHeader{id: 0x1234, qr: true, aa: true, rd: true, ra: true, ..DnsHeader::default()};
+        assert_eq!(header, message.header);
+        assert_eq!(vec![question()], message.questions);
+        let answer = DnsRecord{
+            name: "example.com".into(),
+            class: 1,
+            ttl: 3600,
+            data: ResourceRecord::HostAddress("93.184.216.34/32".parse().unwrap()),
+        };
+        assert_eq!(vec![answer], message.answers);
+        let authority = DnsRecord{
+            name: "example.com".into(),
+            class: 1,
+            ttl: 86400,
+            data: ResourceRecord::NameServer("a.iana-servers.net".into()),
+        };
+        assert_eq!(vec![authority], message.authorities);
+        assert!(message.additionals.is_empty());
+    }
+
+    #[test]
+    fn test_dns_message_from_bytes_compressed() {
+        assert_eq!(DnsMessage::from_bytes(RESPONSE), DnsMessage::from_bytes(COMPRESSED_RESPONSE));
+
+        // go.example.com is a CNAME for www.example.com, and both names are
+        // a label followed by a pointer back to the question
+        let mut message = b"\x00\x01\x81\x80\x00\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        message.extend_from_slice(b"\x07example\x03com\x00\x00\x05\x00\x01");
+        message.extend_from_slice(b"\x02go\xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x06");
+        message.extend_from_slice(b"\x03www\xc0\x0c");
+        let message = DnsMessage::from_bytes(&message).unwrap();
+        assert_eq!("go.example.com", message.answers[0].name);
+        assert_eq!(ResourceRecord::CanonicalName("www.example.com".into()), message.answers[0].data);
+    }
+
+    #[test]
+    fn test_dns_message_from_bytes_pointer_loops() {
+        let header = b"\x00\x01\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00";
+        // a pointer to itself, a pointer forwards, and a label followed by a
+        // pointer back to that label
+        let failure_cases = vec![
+            (&b"\xc0\x0c"[..], DnsParseError::InvalidPointer(12)),
+            (&b"\xc0\x0e\x00"[..], DnsParseError::InvalidPointer(14)),
+            (&b"\x01a\xc0\x0c"[..], DnsParseError::InvalidPointer(12)),
+        ];
+        for (name, error) in failure_cases.into_iter() {
+            let mut message = header.to_vec();
+            message.extend_from_slice(name);
+            message.extend_from_slice(b"\x00\x01\x00\x01");
+            assert_eq!(Err(error), DnsMessage::from_bytes(&message));
+        }
+    }
+
+    #[test]
+    fn test_dns_message_name_limits() {
+        let label = |len| "x".repeat(len);
+        let longest: DomainName = format!("{0}.{0}.{0}.{1}", label(63), label(61)).into();
+        let query = |name: DomainName| DnsMessage{
+            questions: vec![DnsQuestion{name, qtype: QType::A, qclass: QClass::In}],
+            ..DnsMessage::default()
+        };
+        let bytes = query(longest.clone()).to_bytes().unwrap();
+        assert_eq!(HEADER_LEN + 255 + 4, bytes.len());
+        assert_eq!(Ok(query(longest.clone())), DnsMessage::from_bytes(&bytes));
+        assert!(query(label(63).into()).to_bytes().is_ok());
+
+        let long_label = format!("{}.example.com", label(64));
+        let long_name = format!("{}.x", longest);
+        let invalid = |e| Err(DnsEncodeError::InvalidName(e));
+        assert_eq!(invalid(DomainNameError::LabelTooLong(64)), query(long_label.into()).to_bytes());
+        assert_eq!(invalid(DomainNameError::NameTooLong(257)), query(long_name.clone().into()).to_bytes());
+        let data = ResourceRecord::CanonicalName(long_name.into());
+        let record = DnsRecord{name: "example.com".into(), class: 1, ttl: 60, data};
+        let with_answer = DnsMessage{answers: vec![record], ..query("example.com".into())};
+        assert_eq!(invalid(DomainNameError::NameTooLong(257)), with_answer.to_bytes());
+
+        // a 64-octet label can't even be expressed, since its length byte
+        // has a high bit set
+        let mut bytes = b"\x00\x01\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x40".to_vec();
+        bytes.extend_from_slice(label(64).as_bytes());
+        bytes.extend_from_slice(b"\x00\x00\x01\x00\x01");
+        assert_eq!(Err(DnsParseError::InvalidLabel), DnsMessage::from_bytes(&bytes));
+
+        // one more label than fits, where the name is written out in full, and
+        // where the rest of it comes from a pointer to the longest name
+        let mut bytes = query(longest).to_bytes().unwrap();
+        let mut too_long = bytes.clone();
+        too_long.splice(HEADER_LEN..HEADER_LEN, b"\x01x".iter().cloned());
+        assert_eq!(Err(DnsParseError::NameTooLong), DnsMessage::from_bytes(&too_long));
+        bytes[7] = 1;
+        bytes.extend_from_slice(b"\x01x\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\xc0\x00\x02\x01");
+        assert_eq!(Err(DnsParseError::NameTooLong), DnsMessage::from_bytes(&bytes));
+    }
+
+    #[test]
+    fn test_dns_message_aaaa() {
+        // the answer to `dig example.com AAAA`
+        let mut response = b"\x56\x78\x81\x80\x00\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        response.extend_from_slice(b"\x07example\x03com\x00\x00\x1c\x00\x01");
+        response.extend_from_slice(b"\xc0\x0c\x00\x1c\x00\x01\x00\x00\x0e\x10\x00\x10");
+        response.extend_from_slice(&[
+            0x26, 0x06, 0x28, 0x00, 0x02, 0x20, 0x00, 0x01,
+            0x02, 0x48, 0x18, 0x93, 0x25, 0xc8, 0x19, 0x46,
+        ]);
+        let message = DnsMessage::from_bytes(&response).unwrap();
+        let addr: Ipv6Addr = "2606:2800:220:1:248:1893:25c8:1946".parse().unwrap();
+        assert_eq!(QType::Aaaa, message.questions[0].qtype);
+        assert_eq!(ResourceRecord::HostAddressV6(addr), message.answers[0].data);
+        assert_eq!(response, message.to_bytes().unwrap());
+
+        let last = response.len() - 17;
+        response[last] = 0x0f;
+        response.pop();
+        assert_eq!(Err(DnsParseError::InvalidRdata(TYPE_AAAA)), DnsMessage::from_bytes(&response));
+    }
+
+    #[test]
+    fn test_dns_message_txt_srv() {
+        let mut response = b"\x00\x09\x81\x80\x00\x00\x00\x02\x00\x00\x00\x00".to_vec();
+        // example.com. TXT "v=spf1 -all" "" "hi"
+        response.extend_from_slice(b"\x07example\x03com\x00\x00\x10\x00\x01\x00\x00\x0e\x10\x00\x10");
+        response.extend_from_slice(b"\x0bv=spf1 -all\x00\x02hi");
+        // _sip._tcp.example.com. SRV 10 60 5060 sip.example.com.
+        response.extend_from_slice(b"\x04_sip\x04_tcp\xc0\x0c\x00\x21\x00\x01\x00\x00\x0e\x10\x00\x17");
+        response.extend_from_slice(b"\x00\x0a\x00\x3c\x13\xc4\x03sip\x07example\x03com\x00");
+        let message = DnsMessage::from_bytes(&response).unwrap();
+        let strings = vec![b"v=spf1 -all".to_vec(), Vec::new(), b"hi".to_vec()];
+        assert_eq!(ResourceRecord::Text(strings), message.answers[0].data);
+        let service = ResourceRecord::Service{
+            priority: 10,
+            weight: 60,
+            port: 5060,
+            target: "sip.example.com".into(),
+        };
+        assert_eq!("_sip._tcp.example.com", message.answers[1].name);
+        assert_eq!(service, message.answers[1].data);
+        // the SRV target is written out in full even though `example.com` was seen before
+        assert_eq!(response, message.to_bytes().unwrap());
+
+        // a string whose length byte runs past RDLENGTH
+        let mut bad_txt = response.clone();
+        bad_txt[48] = 0x03;
+        assert_eq!(Err(DnsParseError::InvalidRdata(TYPE_TXT)), DnsMessage::from_bytes(&bad_txt));
+        // whereas one that isn't UTF-8 is fine
+        let mut binary_txt = response.clone();
+        binary_txt[49] = 0xff;
+        let binary = DnsMessage::from_bytes(&binary_txt).unwrap();
+        let strings = vec![b"v=spf1 -all".to_vec(), Vec::new(), b"\xffi".to_vec()];
+        assert_eq!(ResourceRecord::Text(strings), binary.answers[0].data);
+        assert_eq!(binary_txt, binary.to_bytes().unwrap());
+
+        let long: Vec<u8> = (0..400).map(|n| n as u8).collect();
+        let mut message = message;
+        message.answers[0].data = ResourceRecord::Text(vec![long.clone()]);
+        let message = DnsMessage::from_bytes(&message.to_bytes().unwrap()).unwrap();
+        assert_eq!(ResourceRecord::Text(vec![long[..255].to_vec(), long[255..].to_vec()]), message.answers[0].data);
+    }
+
+    #[test]
+    fn test_dns_message_soa() {
+        // the authority section of an NXDOMAIN for nonexistent.example.com
+        let mut response = b"\x00\x0a\x81\x83\x00\x00\x00\x00\x00\x01\x00\x00".to_vec();
+        response.extend_from_slice(b"\x07example\x03com\x00\x00\x06\x00\x01\x00\x00\x0e\x10\x00\x2c");
+        response.extend_from_slice(b"\x02ns\x05icann\x03org\x00\x03noc\x03dns\xc0\x26");
+        response.extend_from_slice(&[
+            0x78, 0x99, 0x1c, 0x67, 0x00, 0x00, 0x1c, 0x20, 0x00, 0x00, 0x0e, 0x10,
+            0x00, 0x12, 0x75, 0x00, 0x00, 0x00, 0x0e, 0x10,
+        ]);
+        let message = DnsMessage::from_bytes(&response).unwrap();
+        let soa = ResourceRecord::StartOfAuthority{
+            mname: "ns.icann.org".into(),
+            rname: "noc.dns.icann.org".into(),
+            serial: 2023300199,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 3600,
+        };
+        assert_eq!(soa, message.authorities[0].data);
+        assert_eq!(response, message.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_dns_message_mx() {
+        let mut response = b"\x00\x0b\x81\x80\x00\x01\x00\x02\x00\x00\x00\x00".to_vec();
+        response.extend_from_slice(b"\x07example\x03com\x00\x00\x0f\x00\x01");
+        // example.com. MX 20 backup.example.com.
+        response.extend_from_slice(b"\xc0\x0c\x00\x0f\x00\x01\x00\x00\x0e\x10\x00\x0b");
+        response.extend_from_slice(b"\x00\x14\x06backup\xc0\x0c");
+        // example.com. MX 10 mail.example.com.
+        response.extend_from_slice(b"\xc0\x0c\x00\x0f\x00\x01\x00\x00\x0e\x10\x00\x09");
+        response.extend_from_slice(b"\x00\x0a\x04mail\xc0\x0c");
+        let message = DnsMessage::from_bytes(&response).unwrap();
+        assert_eq!(response, message.to_bytes().unwrap());
+
+        let mut exchangers: Vec<(u16, &str)> = message.answers.iter()
+            .filter_map(|answer| match &answer.data {
+                ResourceRecord::MailExchanger{preference, exchange} => Some((*preference, &exchange[..])),
+                _ => None,
+            })
+            .collect();
+        exchangers.sort();
+        assert_eq!(vec![(10, "mail.example.com"), (20, "backup.example.com")], exchangers);
+    }
+
+    #[test]
+    fn test_dns_message_to_bytes() {
+        assert_eq!(QUERY, &DnsMessage::from_bytes(QUERY).unwrap().to_bytes().unwrap()[..]);
+        assert_eq!(COMPRESSED_RESPONSE, &DnsMessage::from_bytes(RESPONSE).unwrap().to_bytes().unwrap()[..]);
+
+        let record = |data| DnsRecord{name: "example.com".into(), class: 1, ttl: 300, data};
+        let message = DnsMessage{
+            header: DnsHeader{id: 0xbeef, qr: true, opcode: 2, tc: true, rcode: 3, ..DnsHeader::default()},
+            questions: vec![question()],
+            answers: vec![
+                record(ResourceRecord::CanonicalName("www.example.com".into())),
+                record(ResourceRecord::Unknown{rtype: 99, data: vec![1, 2, 3]}),
+            ],
+            authorities: vec![record(ResourceRecord::NameServer("ns1.example.com".into()))],
+            additionals: vec![record(ResourceRecord::HostAddress("192.0.2.1/32".parse().unwrap()))],
+            edns: None,
+        };
+        let bytes = message.to_bytes().unwrap();
+        assert_eq!(&[0xbe, 0xef, 0x92, 0x03, 0x00, 0x01, 0x00, 0x02, 0x00, 0x01, 0x00, 0x01], &bytes[..12]);
+        assert_eq!(Ok(message), DnsMessage::from_bytes(&bytes));
+    }
+
+    #[test]
+    fn test_dns_message_to_bytes_compressed() {
+        let record = |name: &str, data| DnsRecord{name: name.into(), class: 1, ttl: 300, data};
+        let mut message = DnsMessage{
+            header: DnsHeader{id: 7, qr: true, ..DnsHeader::default()},
+            questions: vec![question()],
+            answers: vec![record("www.example.com", ResourceRecord::CanonicalName("example.com".into()))],
+            ..DnsMessage::default()
+        };
+        let bytes = message.to_bytes().unwrap();
+        // `www` and a pointer to the question, then just a pointer in the RDATA
+        assert_eq!(&b"\x03www\xc0\x0c\x00\x05\x00\x01\x00\x00\x01\x2c\x00\x02\xc0\x0c"[..], &bytes[29..]);
+        assert_eq!(Ok(message.clone()), DnsMessage::from_bytes(&bytes));
+
+        // a second record costs its own label, a pointer to `example.com`, the
+        // fixed fields, and a pointer to `www.example.com` as its RDATA
+        let size = bytes.len();
+        message.answers.push(record("mail.example.com", ResourceRecord::CanonicalName("www.example.com".into())));
+        let bytes = message.to_bytes().unwrap();
+        assert_eq!(size + 5 + 2 + 10 + 2, bytes.len());
+        assert_eq!(Ok(message), DnsMessage::from_bytes(&bytes));
+    }
+
+    #[test]
+    fn test_dns_message_to_bytes_limited() {
+        // each A record after the first name is a pointer, the fixed fields and
+        // the address: 16 bytes, after 29 of header and question
+        let record = |n: u8| DnsRecord{
+            name: "example.com".into(),
+            class: 1,
+            ttl: 300,
+            data: ResourceRecord::HostAddress(format!("192.0.2.{}/32", n).parse().unwrap()),
+        };
+        let mut message = DnsMessage{
+            header: DnsHeader{id: 7, qr: true, ..DnsHeader::default()},
+            questions: vec![question()],
+            answers: (0..40).map(record).collect(),
+            ..DnsMessage::default()
+        };
+        assert_eq!(29 + 40 * 16, message.to_bytes().unwrap().len());
+        assert_eq!(message.to_bytes(), message.to_bytes_limited(4096));
+
+        let bytes = message.to_bytes_limited(512).unwrap();
+        assert_eq!(29 + 30 * 16, bytes.len());
+        let truncated = DnsMessage::from_bytes(&bytes).unwrap();
+        assert!(truncated.header.truncated());
+        assert_eq!(&message.answers[..30], &truncated.answers[..]);
+
+        // the OPT record always goes in, so it takes the room of an answer
+        message.edns = Some(EdnsOpt::new(512));
+        let truncated = DnsMessage::from_bytes(&message.to_bytes_limited(512).unwrap()).unwrap();
+        assert!(truncated.header.truncated());
+        assert_eq!(29, truncated.answers.len());
+        assert_eq!(message.edns, truncated.edns);
+
+        // losing additionals doesn't make a response truncated
+        message.edns = None;
+        message.additionals = message.answers.split_off(1);
+        let trimmed = DnsMessage::from_bytes(&message.to_bytes_limited(512).unwrap()).unwrap();
+        assert!(!trimmed.header.truncated());
+        assert_eq!(message.answers, trimmed.answers);
+        assert_eq!(&message.additionals[..29], &trimmed.additionals[..]);
+
+        // nor does losing nothing
+        let fits = DnsMessage::from_bytes(&message.to_bytes_limited(29 + 40 * 16).unwrap()).unwrap();
+        assert_eq!(message, fits);
+    }
+
+    #[test]
+    fn test_dns_message_to_bytes_too_long() {
+        // each string takes its length byte and up to 255 octets
+        let txt = |strings: Vec<Vec<u8>>| DnsMessage{
+            answers: vec![DnsRecord{name: "example.com".into(), class: 1, ttl: 60, data: ResourceRecord::Text(strings)}],
+            ..DnsMessage::default()
+        };
+        let mut strings = vec![vec![b'x'; 255]; 255];
+        strings.push(vec![b'x'; 254]);
+        let bytes = txt(strings.clone()).to_bytes().unwrap();
+        assert_eq!(Ok(txt(strings.clone())), DnsMessage::from_bytes(&bytes));
+        strings.last_mut().unwrap().push(b'x');
+        assert_eq!(Err(DnsEncodeError::RdataTooLong(65536)), txt(strings).to_bytes());
+
+        // an option's code and length come out of the OPT record's RDLENGTH too
+        let opt = |len| DnsMessage{
+            edns: Some(EdnsOpt{options: vec![(10, vec![0; len])], ..EdnsOpt::new(4096)}),
+            ..DnsMessage::default()
+        };
+        assert!(opt(65531).to_bytes().is_ok());
+        assert_eq!(Err(DnsEncodeError::RdataTooLong(65536)), opt(65532).to_bytes());
+
+        let questions = |count| DnsMessage{questions: vec![question(); count], ..DnsMessage::default()};
+        assert!(questions(65535).to_bytes().is_ok());
+        assert_eq!(Err(DnsEncodeError::TooManyRecords(65536)), questions(65536).to_bytes());
+    }
+
+    #[test]
+    fn test_dns_message_edns() {
+        // `dig +bufsize=4096 example.com A`, with its OPT record and no options
+        let mut query = QUERY.to_vec();
+        query[11] = 1;
+        query.extend_from_slice(b"\x00\x00\x29\x10\x00\x00\x00\x00\x00\x00\x00");
+        let message = DnsMessage::from_bytes(&query).unwrap();
+        assert_eq!(Some(EdnsOpt::new(4096)), message.edns);
+        assert!(message.additionals.is_empty());
+        assert_eq!(query, message.to_bytes().unwrap());
+
+        // an extended RCODE of BADVERS (16), DO, and a cookie option, after another additional record
+        let mut response = b"\x00\x0c\x81\x80\x00\x00\x00\x00\x00\x00\x00\x02".to_vec();
+        response.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01\x00\x00\x0e\x10\x00\x04\xc0\x00\x02\x01");
+        response.extend_from_slice(b"\x00\x00\x29\x04\xd0\x01\x00\x80\x00\x00\x0c\x00\x0a\x00\x08cookie!!");
+        let message = DnsMessage::from_bytes(&response).unwrap();
+        let edns = EdnsOpt{
+            udp_payload_size: 1232,
+            extended_rcode: 1,
+            version: 0,
+            dnssec_ok: true,
+            options: vec![(10, b"cookie!!".to_vec())],
+        };
+        assert_eq!(Some(edns), message.edns);
+        assert_eq!(1, message.additionals.len());
+        assert_eq!(16, message.extended_rcode());
+        assert_eq!(response, message.to_bytes().unwrap());
+
+        let mut repeated = query.clone();
+        repeated[11] = 2;
+        repeated.extend_from_slice(&query[query.len() - 11..]);
+        let mut not_root = b"\x00\x0d\x01\x00\x00\x00\x00\x00\x00\x00\x00\x01".to_vec();
+        not_root.extend_from_slice(b"\x01x\x00\x00\x29\x10\x00\x00\x00\x00\x00\x00\x00");
+        let mut bad_option = query.clone();
+        let last = bad_option.len() - 1;
+        bad_option[last] = 2;
+        bad_option.extend_from_slice(b"\x00\x0a");
+        let failure_cases = vec![
+            (repeated, DnsParseError::InvalidOpt),
+            (not_root, DnsParseError::InvalidOpt),
+            (bad_option, DnsParseError::InvalidRdata(TYPE_OPT)),
+        ];
+        for (input, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), DnsMessage::from_bytes(&input));
+        }
+    }
+
+    #[test]
+    fn test_dns_message_from_bytes_errors() {
+        let mut bad_rdlength = RESPONSE.to_vec();
+        bad_rdlength[51] = 0x05;
+        let mut trailing = QUERY.to_vec();
+        trailing.push(0);
+        let mut bad_label = QUERY.to_vec();
+        bad_label[12] = 0x47;
+        let failure_cases = vec![
+            (&QUERY[..11], DnsParseError::UnexpectedEof),
+            (&QUERY[..QUERY.len() - 1], DnsParseError::UnexpectedEof),
+            (&RESPONSE[..RESPONSE.len() - 1], DnsParseError::UnexpectedEof),
+            (&bad_rdlength[..], DnsParseError::InvalidRdata(TYPE_A)),
+            (&trailing[..], DnsParseError::TrailingData),
+            (&bad_label[..], DnsParseError::InvalidLabel),
+        ];
+        for (input, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), DnsMessage::from_bytes(input));
+        }
+    }
+
+    #[test]
+    fn test_resource_record_rdata() {
+        let cases = vec![
+            (QType::A, ResourceRecord::HostAddress("192.0.2.1/32".parse().unwrap()), &b"\xc0\x00\x02\x01"[..]),
+            (
+                QType::Aaaa,
+                ResourceRecord::HostAddressV6("2001:db8::1".parse().unwrap()),
+                &b"\x20\x01\x0d\xb8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01"[..],
+            ),
+            (QType::Ns, ResourceRecord::NameServer("ns.example.com".into()), &b"\x02ns\x07example\x03com\x00"[..]),
+            (QType::Cname, ResourceRecord::CanonicalName("".into()), &b"\x00"[..]),
+            (
+                QType::Mx,
+                ResourceRecord::MailExchanger{preference: 10, exchange: "mx.example.com".into()},
+                &b"\x00\x0a\x02mx\x07example\x03com\x00"[..],
+            ),
+            (
+                QType::Soa,
+                ResourceRecord::StartOfAuthority{
+                    mname: "ns.example.com".into(),
+                    rname: "admin.example.com".into(),
+                    serial: 1,
+                    refresh: 2,
+                    retry: 3,
+                    expire: 4,
+                    minimum: 5,
+                },
+                // both names in full, though they share a suffix
+                &b"\x02ns\x07example\x03com\x00\x05admin\x07example\x03com\x00\
+                   \x00\x00\x00\x01\x00\x00\x00\x02\x00\x00\x00\x03\x00\x00\x00\x04\x00\x00\x00\x05"[..],
+            ),
+            (QType::Txt, ResourceRecord::Text(vec![b"hi".to_vec(), Vec::new()]), &b"\x02hi\x00"[..]),
+            (
+                QType::Srv,
+                ResourceRecord::Service{priority: 1, weight: 2, port: 53, target: "ns.example.com".into()},
+                &b"\x00\x01\x00\x02\x00\x35\x02ns\x07example\x03com\x00"[..],
+            ),
+            (QType::Ptr, ResourceRecord::Pointer("host.example.com".into()), &b"\x04host\x07example\x03com\x00"[..]),
+            // dskey.example.com. DS 60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118, from RFC 4034 5.4
+            (
+                QType::Ds,
+                ResourceRecord::DelegationSigner{
+                    key_tag: 60485,
+                    algorithm: 5,
+                    digest_type: 1,
+                    digest: b"\x2b\xb1\x83\xaf\x5f\x22\x58\x81\x79\xa5\
+                              \x3b\x0a\x98\x63\x1f\xad\x1a\x29\x21\x18".to_vec(),
+                },
+                &b"\xec\x45\x05\x01\x2b\xb1\x83\xaf\x5f\x22\x58\x81\x79\xa5\x3b\x0a\x98\x63\x1f\xad\
+                   \x1a\x29\x21\x18"[..],
+            ),
+            // alfa.example.com. NSEC host.example.com. A MX RRSIG NSEC TYPE1234, from RFC 4034 4.3
+            (
+                QType::Nsec,
+                ResourceRecord::NextSecure{next_name: "host.example.com".into(), types: vec![1, 15, 46, 47, 1234]},
+                &b"\x04host\x07example\x03com\x00\x00\x06\x40\x01\x00\x00\x00\x03\x04\x1b\
+                   \x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\
+                   \x00\x00\x00\x00\x00\x00\x20"[..],
+            ),
+        ];
+        for (qtype, record, rdata) in cases.into_iter() {
+            assert_eq!(u16::from(qtype), record.rtype());
+            assert_eq!(rdata, &record.rdata_bytes().unwrap()[..]);
+            assert_eq!(Ok(record), ResourceRecord::parse_rdata(qtype, rdata, &[]));
+        }
+
+        let too_long = ResourceRecord::NameServer("x".repeat(MAX_NAME_LEN).into());
+        assert!(too_long.rdata_bytes().is_err());
+    }
+
+    #[test]
+    fn test_resource_record_parse_rdata_compressed() {
+        // example.com. MX 20 backup.example.com., pointing back to the question
+        let mut response = b"\x00\x0b\x81\x80\x00\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        response.extend_from_slice(b"\x07example\x03com\x00\x00\x0f\x00\x01");
+        response.extend_from_slice(b"\xc0\x0c\x00\x0f\x00\x01\x00\x00\x0e\x10\x00\x0b");
+        response.extend_from_slice(b"\x00\x14\x06backup\xc0\x0c");
+        let rdata = &response[41..];
+        let mx = ResourceRecord::MailExchanger{preference: 20, exchange: "backup.example.com".into()};
+        assert_eq!(Ok(mx), ResourceRecord::parse_rdata(QType::Mx, rdata, &response));
+        // without the message there's nothing to point to
+        let copied = rdata.to_vec();
+        assert_eq!(Err(DnsParseError::InvalidPointer(12)), ResourceRecord::parse_rdata(QType::Mx, &copied, &response));
+
+        let rdata = &COMPRESSED_RESPONSE[COMPRESSED_RESPONSE.len() - 20..];
+        let ns = ResourceRecord::NameServer("a.iana-servers.net".into());
+        assert_eq!(Ok(ns), ResourceRecord::parse_rdata(QType::Ns, rdata, COMPRESSED_RESPONSE));
+    }
+
+    #[test]
+    fn test_dns_message_ptr_compressed() {
+        // 1.2.0.192.in-addr.arpa. PTR host.in-addr.arpa., whose target points
+        // into the question's name; not a real mapping, but as compressed as it gets
+        let mut response = b"\x00\x0c\x81\x80\x00\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        response.extend_from_slice(b"\x011\x012\x010\x03192\x07in-addr\x04arpa\x00\x00\x0c\x00\x01");
+        response.extend_from_slice(b"\xc0\x0c\x00\x0c\x00\x01\x00\x00\x0e\x10\x00\x07\x04host\xc0\x16");
+        let message = DnsMessage::from_bytes(&response).unwrap();
+        let expected = ResourceRecord::Pointer("host.in-addr.arpa".into());
+        assert_eq!(expected, message.answers[0].data);
+        assert_eq!("PTR", message.answers[0].data.type_name());
+
+        // written back with the same pointers, so nothing is left dangling
+        let bytes = message.to_bytes().unwrap();
+        assert_eq!(response, bytes);
+        assert_eq!(Ok(message.clone()), DnsMessage::from_bytes(&bytes));
+        // and copied out on its own, the name is written in full
+        assert_eq!(&b"\x04host\x07in-addr\x04arpa\x00"[..], &expected.rdata_bytes().unwrap()[..]);
+    }
+
+    #[test]
+    fn test_resource_record_parse_rdata_errors() {
+        let failure_cases = vec![
+            (QType::A, &b"\xc0\x00\x02"[..], DnsParseError::InvalidRdata(TYPE_A)),
+            (QType::Aaaa, &b"\xc0\x00\x02\x01"[..], DnsParseError::InvalidRdata(TYPE_AAAA)),
+            (QType::Ns, &b"\x02ns"[..], DnsParseError::UnexpectedEof),
+            (QType::Cname, &b"\x00\x00"[..], DnsParseError::InvalidRdata(TYPE_CNAME)),
+            (QType::Mx, &b"\x00"[..], DnsParseError::UnexpectedEof),
+            (QType::Soa, &b"\x00\x00\x00\x00\x00\x01"[..], DnsParseError::UnexpectedEof),
+            (QType::Txt, &b"\x03hi"[..], DnsParseError::InvalidRdata(TYPE_TXT)),
+            (QType::Srv, &b"\x00\x01\x00\x02\x00\x35\x00\x00"[..], DnsParseError::InvalidRdata(TYPE_SRV)),
+            (QType::Dnskey, &b"\x01\x00\x03"[..], DnsParseError::UnexpectedEof),
+            (QType::Ds, &b"\xec\x45\x05"[..], DnsParseError::UnexpectedEof),
+            // a window with no octets, and windows out of order
+            (QType::Nsec, &b"\x00\x00\x00"[..], DnsParseError::InvalidRdata(TYPE_NSEC)),
+            (QType::Nsec, &b"\x00\x01\x01\x40\x00\x01\x40"[..], DnsParseError::InvalidRdata(TYPE_NSEC)),
+            (QType::Nsec, &b"\x00\x00\x21"[..], DnsParseError::InvalidRdata(TYPE_NSEC)),
+            (QType::Any, &b""[..], DnsParseError::InvalidRdata(255)),
+        ];
+        for (qtype, rdata, error) in failure_cases.into_iter() {
+            assert_eq!(Err(error), ResourceRecord::parse_rdata(qtype, rdata, &[]));
+        }
+    }
+
+    #[test]
+    fn test_dns_message_dnssec() {
+        // the example DNSKEY and RRSIG from RFC 4034 2.3 and 3.3
+        let public_key = base64::decode(
+            "AQPSKmynfzW4kyBv015MUG2DeIQ3Cbl+BBZH4b/0PY1kxkmvHjcZc8nokfzj31GajIQKY+5CptLr3buXA10hWqTkF7H6RfoRqXQe\
+             ogmMHfpftf6zMv1LyBUgia7za6ZEzOJBOztyvhjL742iU/TpPSEDhm2SNKLijfUppn1UaNvv4w==",
+        ).unwrap();
+        let signature = base64::decode(
+            "oJB1W6WNGv+ldvQ3WDG0MQkg5IEhjRip8WTrPYGv07h108dUKGMeDPKijVCHX3DDKdfb+v6oB9wfuh3DTJXUAfI/M0zmO/zz8bW0\
+             Rznl8O3tGNazPwQKkRN20XPXV6nwwfoXmJQbsLNrLfkGJ5D6fwFm8nN+6pBzeDQfsS3Ap3o=",
+        ).unwrap();
+        let mut response = b"\x00\x0c\x81\x80\x00\x00\x00\x02\x00\x00\x00\x00".to_vec();
+        // example.com. 86400 IN DNSKEY 256 3 5 AQPSKmyn...
+        response.extend_from_slice(b"\x07example\x03com\x00\x00\x30\x00\x01\x00\x01\x51\x80\x00\x86");
+        response.extend_from_slice(b"\x01\x00\x03\x05");
+        response.extend_from_slice(&public_key);
+        // host.example.com. 86400 IN RRSIG A 5 3 86400 20030322173103 20030220173103 2642 example.com. oJB1W6WN...
+        response.extend_from_slice(b"\x04host\xc0\x0c\x00\x2e\x00\x01\x00\x01\x51\x80\x00\x9f");
+        response.extend_from_slice(b"\x00\x01\x05\x03\x00\x01\x51\x80\x3e\x7c\x9d\xd7\x3e\x55\x10\xd7\x0a\x52");
+        response.extend_from_slice(b"\x07example\x03com\x00");
+        response.extend_from_slice(&signature);
+
+        let message = DnsMessage::from_bytes(&response).unwrap();
+        let dnskey = ResourceRecord::DnsKey{flags: 256, protocol: 3, algorithm: 5, public_key};
+        assert_eq!(dnskey, message.answers[0].data);
+        assert_eq!("DNSKEY", message.answers[0].data.type_name());
+        let rrsig = ResourceRecord::Signature{
+            type_covered: TYPE_A,
+            algorithm: 5,
+            labels: 3,
+            original_ttl: 86400,
+            expiration: 1048354263,
+            inception: 1045762263,
+            key_tag: 2642,
+            signer_name: "example.com".into(),
+            signature,
+        };
+        assert_eq!("host.example.com", message.answers[1].name);
+        assert_eq!(rrsig, message.answers[1].data);
+        assert_eq!("RRSIG", message.answers[1].data.type_name());
+        // the signer's name is written out in full even though `example.com` was seen before
+        assert_eq!(response, message.to_bytes().unwrap());
+    }
+}
@@ -0,0 +1,347 @@
+//! Reading master files in the RFC 1035 section 5 format used by BIND.
+//!
+//! `$ORIGIN` and `$TTL` are understood, as are parentheses continuing a
+//! record over several lines. `$INCLUDE` isn't.
+
+use std::{fmt, net::Ipv6Addr};
+
+use ipnet::Ipv4Net;
+
+use super::{DomainName, DomainNameError, NameServerDb, ResourceRecord};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ZoneParseError {
+    /// The 1-based line the offending record or directive starts on.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ZoneParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "zone file line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ZoneParseError {}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Token {
+    Word(String),
+    Quoted(String),
+}
+
+/// One record or directive, which parentheses may have spread over several lines.
+struct Entry {
+    line: usize,
+    /// Whether the entry started with whitespace, meaning it has no owner name.
+    indented: bool,
+    tokens: Vec<Token>,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Entry>, ZoneParseError> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+    let mut depth = 0;
+    for (n, line) in input.lines().enumerate() {
+        let error = |reason| ZoneParseError{line: n + 1, reason};
+        let entry = current.get_or_insert_with(|| Entry{
+            line: n + 1,
+            indented: line.starts_with(|c: char| c.is_whitespace()),
+            tokens: Vec::new(),
+        });
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => break,
+                '(' => depth += 1,
+                ')' if depth == 0 => return Err(error("unbalanced `)`")),
+                ')' => depth -= 1,
+                '"' => {
+                    let mut s = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => s.extend(chars.next()),
+                            Some(c) => s.push(c),
+                            None => return Err(error("unterminated string")),
+                        }
+                    }
+                    entry.tokens.push(Token::Quoted(s));
+                },
+                c if c.is_whitespace() => {},
+                c => {
+                    let mut word = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || "();\"".contains(c) {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                    entry.tokens.push(Token::Word(word));
+                },
+            }
+        }
+        if depth == 0 {
+            let entry = current.take().unwrap();
+            if !entry.tokens.is_empty() {
+                entries.push(entry);
+            }
+        }
+    }
+    if let Some(entry) = current {
+        return Err(ZoneParseError{line: entry.line, reason: "unbalanced `(`"});
+    }
+    return Ok(entries);
+}
+
+/// The fields of an entry, consumed left to right.
+struct Fields {
+    line: usize,
+    tokens: std::vec::IntoIter<Token>,
+}
+
+impl Fields {
+    fn word(&mut self, missing: &'static str) -> Result<String, ZoneParseError> {
+        match self.tokens.next() {
+            Some(Token::Word(word)) => Ok(word),
+            _ => Err(ZoneParseError{line: self.line, reason: missing}),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, missing: &'static str) -> Result<T, ZoneParseError> {
+        self.word(missing)?.parse().map_err(|_| ZoneParseError{line: self.line, reason: "invalid number"})
+    }
+}
+
+/// Tracks `$ORIGIN`, `$TTL`, and the last owner name while reading entries.
+struct ZoneParser {
+    origin: Option<DomainName>,
+    default_ttl: Option<u32>,
+    last_owner: Option<DomainName>,
+    line: usize,
+}
+
+impl ZoneParser {
+    fn error(&self, reason: &'static str) -> ZoneParseError {
+        ZoneParseError{line: self.line, reason}
+    }
+
+    // absolute names lose their trailing dot, and relative ones get the origin appended
+    fn name(&self, name: &str) -> Result<DomainName, ZoneParseError> {
+        let name: DomainName = if name == "@" {
+            return self.origin.clone().ok_or_else(|| self.error("`@` used with no $ORIGIN"));
+        } else if let Some(absolute) = name.strip_suffix('.') {
+            absolute.into()
+        } else {
+            match &self.origin {
+                Some(origin) if origin.is_empty() => name.into(),
+                Some(origin) => format!("{}.{}", name, origin).into(),
+                None => return Err(self.error("relative name used with no $ORIGIN")),
+            }
+        };
+        match name.validate() {
+            Ok(()) => Ok(name),
+            Err(DomainNameError::EmptyLabel) => Err(self.error("name has an empty label")),
+            Err(DomainNameError::LabelTooLong(_)) => Err(self.error("label longer than 63 octets")),
+            Err(DomainNameError::NameTooLong(_)) => Err(self.error("name longer than 255 octets")),
+        }
+    }
+
+    fn parse_entry(&mut self, entry: Entry, db: &mut NameServerDb) -> Result<(), ZoneParseError> {
+        self.line = entry.line;
+        let mut fields = Fields{line: entry.line, tokens: entry.tokens.into_iter()};
+        if entry.indented {
+            let owner = self.last_owner.clone().ok_or_else(|| self.error("record has no owner name"))?;
+            return self.parse_record(owner, fields, db);
+        }
+        let first = fields.word("expected an owner name or directive")?;
+        match &first[..] {
+            "$ORIGIN" => {
+                let origin = fields.word("$ORIGIN needs a name")?;
+                self.origin = Some(self.name(&origin)?);
+            },
+            "$TTL" => self.default_ttl = Some(fields.number("$TTL needs a value")?),
+            directive if directive.starts_with('$') => return Err(self.error("unsupported directive")),
+            owner => {
+                let owner = self.name(owner)?;
+                self.last_owner = Some(owner.clone());
+                return self.parse_record(owner, fields, db);
+            },
+        }
+        if fields.tokens.next().is_some() {
+            return Err(self.error("too many fields"));
+        }
+        return Ok(());
+    }
+
+    fn parse_record(
+        &self, owner: DomainName, mut fields: Fields, db: &mut NameServerDb,
+    ) -> Result<(), ZoneParseError> {
+        // the TTL and class are both optional and may come in either order
+        let mut ttl = None;
+        let mut rtype = fields.word("expected a record type")?;
+        loop {
+            if rtype.eq_ignore_ascii_case("IN") {
+                rtype = fields.word("expected a record type")?;
+            } else if ttl.is_none() && rtype.bytes().all(|b| b.is_ascii_digit()) {
+                ttl = Some(rtype.parse::<u32>().map_err(|_| self.error("invalid TTL"))?);
+                rtype = fields.word("expected a record type")?;
+            } else {
+                break;
+            }
+        }
+        let ttl = ttl.or(self.default_ttl).ok_or_else(|| self.error("record has no TTL and there's no $TTL"))?;
+
+        let record = match &rtype.to_ascii_uppercase()[..] {
+            "A" => {
+                let addr = fields.word("A needs an address")?;
+                let addr = addr.parse().map_err(|_| self.error("invalid IPv4 address"))?;
+                ResourceRecord::HostAddress(Ipv4Net::new(addr, 32).unwrap())
+            },
+            "AAAA" => {
+                let addr = fields.word("AAAA needs an address")?;
+                ResourceRecord::HostAddressV6(addr.parse::<Ipv6Addr>().map_err(|_| self.error("invalid IPv6 address"))?)
+            },
+            "NS" => ResourceRecord::NameServer(self.name(&fields.word("NS needs a name")?)?),
+            "CNAME" => ResourceRecord::CanonicalName(self.name(&fields.word("CNAME needs a name")?)?),
+            "PTR" => ResourceRecord::Pointer(self.name(&fields.word("PTR needs a name")?)?),
+            "MX" => ResourceRecord::MailExchanger{
+                preference: fields.number("MX needs a preference")?,
+                exchange: self.name(&fields.word("MX needs an exchange")?)?,
+            },
+            "SRV" => ResourceRecord::Service{
+                priority: fields.number("SRV needs a priority")?,
+                weight: fields.number("SRV needs a weight")?,
+                port: fields.number("SRV needs a port")?,
+                target: self.name(&fields.word("SRV needs a target")?)?,
+            },
+            "SOA" => ResourceRecord::StartOfAuthority{
+                mname: self.name(&fields.word("SOA needs an MNAME")?)?,
+                rname: self.name(&fields.word("SOA needs an RNAME")?)?,
+                serial: fields.number("SOA needs a serial")?,
+                refresh: fields.number("SOA needs a refresh")?,
+                retry: fields.number("SOA needs a retry")?,
+                expire: fields.number("SOA needs an expire")?,
+                minimum: fields.number("SOA needs a minimum")?,
+            },
+            // any mix of quoted and bare strings
+            "TXT" => {
+                let strings: Vec<Vec<u8>> = fields.tokens.by_ref()
+                    .map(|token| match token {
+                        Token::Word(s) | Token::Quoted(s) => s.into_bytes(),
+                    })
+                    .collect();
+                if strings.is_empty() {
+                    return Err(self.error("TXT needs at least one string"));
+                }
+                ResourceRecord::Text(strings)
+            },
+            _ => return Err(self.error("unsupported record type")),
+        };
+        if fields.tokens.next().is_some() {
+            return Err(self.error("too many fields"));
+        }
+        db.entry(owner).or_default().push((ttl, record));
+        return Ok(());
+    }
+}
+
+/// Parses a zone file into the records it defines, keyed by absolute owner
+/// name without the trailing dot. Records without a TTL of their own get the
+/// `$TTL` in effect.
+pub fn parse_zone_file(input: &str) -> Result<NameServerDb, ZoneParseError> {
+    let mut parser = ZoneParser{origin: None, default_ttl: None, last_owner: None, line: 0};
+    let mut db = NameServerDb::new();
+    for entry in tokenize(input)? {
+        parser.parse_entry(entry, &mut db)?;
+    }
+    return Ok(db);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZONE: &str = r#"
+$ORIGIN example.com.
+$TTL 3600
+; the zone apex
+@       IN  SOA ns1 hostmaster (
+                2024010101 ; serial
+                7200 3600 1209600 300 )
+        IN  NS  ns1
+        IN  NS  ns2.example.net.
+        IN  MX  10 mail
+        IN  MX  20 mail.backup.example.org.
+        IN  A   192.0.2.1
+ns1     300 IN A 192.0.2.53
+www     IN  CNAME @
+mail    IN  300 AAAA 2001:db8::25
+        TXT "v=spf1 mx -all" "second \"quoted\" string"
+_sip._tcp SRV 10 60 5060 www
+1.2.0.192.in-addr.arpa. PTR www
+"#;
+
+    #[test]
+    fn test_parse_zone_file() {
+        let db = parse_zone_file(ZONE).unwrap();
+        assert_eq!(6, db.len());
+        let apex = &db[&DomainName::from("example.com")];
+        let soa = ResourceRecord::StartOfAuthority{
+            mname: "ns1.example.com".into(),
+            rname: "hostmaster.example.com".into(),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        };
+        assert_eq!(vec![
+            (3600, soa),
+            (3600, ResourceRecord::NameServer("ns1.example.com".into())),
+            (3600, ResourceRecord::NameServer("ns2.example.net".into())),
+            (3600, ResourceRecord::MailExchanger{preference: 10, exchange: "mail.example.com".into()}),
+            (3600, ResourceRecord::MailExchanger{preference: 20, exchange: "mail.backup.example.org".into()}),
+            (3600, ResourceRecord::HostAddress("192.0.2.1/32".parse().unwrap())),
+        ], *apex);
+        let ns1 = (300, ResourceRecord::HostAddress("192.0.2.53/32".parse().unwrap()));
+        assert_eq!(vec![ns1], db[&DomainName::from("ns1.example.com")]);
+        let www = (3600, ResourceRecord::CanonicalName("example.com".into()));
+        assert_eq!(vec![www], db[&DomainName::from("www.example.com")]);
+        // a TTL only applies to its own record
+        assert_eq!(vec![
+            (300, ResourceRecord::HostAddressV6("2001:db8::25".parse().unwrap())),
+            (3600, ResourceRecord::Text(vec![b"v=spf1 mx -all".to_vec(), b"second \"quoted\" string".to_vec()])),
+        ], db[&DomainName::from("mail.example.com")]);
+        let srv = ResourceRecord::Service{priority: 10, weight: 60, port: 5060, target: "www.example.com".into()};
+        assert_eq!(vec![(3600, srv)], db[&DomainName::from("_sip._tcp.example.com")]);
+        let ptr = ResourceRecord::Pointer("www.example.com".into());
+        assert_eq!(vec![(3600, ptr)], db[&DomainName::from("1.2.0.192.in-addr.arpa")]);
+    }
+
+    #[test]
+    fn test_parse_zone_file_errors() {
+        let long_label = format!("$TTL 60\n{}.example.com. IN A 192.0.2.1", "x".repeat(64));
+        // fine on its own, but not once the origin is appended
+        let long_name = format!("$ORIGIN example.com.\n$TTL 60\n{0}.{0}.{0}.{0} IN A 192.0.2.1", "x".repeat(60));
+        let failure_cases = vec![
+            ("www IN A 192.0.2.1", 1, "relative name used with no $ORIGIN"),
+            ("$ORIGIN example.com.\nwww IN A 192.0.2.1", 2, "record has no TTL and there's no $TTL"),
+            ("$TTL 60\n  IN A 192.0.2.1", 2, "record has no owner name"),
+            ("$TTL 60\nwww.example.com. IN A 192.0.2", 2, "invalid IPv4 address"),
+            ("$TTL 60\nwww.example.com. IN A 192.0.2.1 extra", 2, "too many fields"),
+            ("$TTL 60\nwww.example.com. IN HINFO x y", 2, "unsupported record type"),
+            ("$TTL 60\nexample.com. IN MX mail.example.com.", 2, "invalid number"),
+            ("$TTL 60\nexample.com. SOA ( a. b. 1 2 3 4 5", 2, "unbalanced `(`"),
+            ("$TTL 60\nexample.com. TXT \"open", 2, "unterminated string"),
+            ("$INCLUDE other.zone", 1, "unsupported directive"),
+            ("$TTL 60\nwww..example.com. IN A 192.0.2.1", 2, "name has an empty label"),
+            (&long_label[..], 2, "label longer than 63 octets"),
+            (&long_name[..], 3, "name longer than 255 octets"),
+        ];
+        for (input, line, reason) in failure_cases.into_iter() {
+            assert_eq!(Err(ZoneParseError{line, reason}), parse_zone_file(input));
+        }
+    }
+}
@@ -1,2 +1,11 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// public so that `bencode!` can reach `String` and `vec!` without `std`
+#[doc(hidden)]
+pub extern crate alloc;
+
 pub mod bt;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
 pub mod dns;